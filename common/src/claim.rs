@@ -9,6 +9,32 @@ pub struct CustomClaims {
   pub subscriber_id: SubscriberId,
   #[serde(rename = "aud")]
   pub audiences: Audiences,
-  #[serde(rename = "iad")]
-  pub is_admin: bool,
+  #[serde(flatten)]
+  pub user: UserClaims,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// Claims describing the user, or the client application for tokens issued via the client credentials grant.
+/// The profile is emitted as standard OIDC claims only if set.
+pub struct UserClaims {
+  /// True if the user has the admin role, kept for validators unaware of roles. Omitted in tokens issued to clients and
+  /// in tokens restricted by scope.
+  #[serde(rename = "iad", skip_serializing_if = "Option::is_none", default)]
+  pub is_admin: Option<bool>,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub roles: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub email: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub name: Option<String>,
+  /// Scope restricting the token to specific operations at the token server, e.g., [`crate::PASSWORD_CHANGE_SCOPE`].
+  /// Tokens without scope are unrestricted.
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub scope: Option<String>,
+  /// Methods used to authenticate the user at the login, e.g., `["pwd", "otp", "mfa"]` as defined in RFC 8176
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub amr: Vec<String>,
+  /// True if the token is issued to the client application itself, whose client id is given as `sub`
+  #[serde(rename = "cli", skip_serializing_if = "std::ops::Not::not", default)]
+  pub is_client: bool,
 }
@@ -1,5 +1,6 @@
 /// Refresh token length in ascii
 pub const REFRESH_TOKEN_LEN: u64 = 256;
 /// Default duration of ID Token validity in minutes
-/// TODO: Clapで設定できるように
 pub const JWT_DURATION_MINS: usize = 30;
+/// Scope of id tokens good only for changing the password of the user, which are issued while a password change is required
+pub const PASSWORD_CHANGE_SCOPE: &str = "password_change";
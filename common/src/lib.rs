@@ -15,5 +15,7 @@ pub mod blind_sig {
   };
 }
 
+pub use claim::UserClaims;
+pub use constants::{JWT_DURATION_MINS, PASSWORD_CHANGE_SCOPE};
 pub use token::{TokenBody, TokenMeta};
-pub use validation_key::{Claims, SigningKey, ValidationKey, ValidationOptions};
+pub use validation_key::{Claims, SigningKey, SubjectKind, ValidationKey, ValidationOptions};
@@ -32,7 +32,7 @@ pub struct TokenBody {
 
 impl TokenBody {
   /// Decode id token and retrieve metadata
-  pub fn decode_id_token(&self) -> Result<UntrustedToken> {
+  pub fn decode_id_token(&self) -> Result<UntrustedToken<'_>> {
     // Token::decode_metadata(&self.id).map_err(|e| AuthError::FailedToDecodeIdToken(e).into())
     Ok(UntrustedToken::new(self.id.as_str())?)
   }
@@ -56,6 +56,9 @@ impl TokenBody {
 pub struct TokenMeta {
   pub username: String,
   pub is_admin: bool,
+  /// True if the id token is restricted to changing the password, which the user must do before anything else
+  #[serde(default)]
+  pub must_change_password: bool,
 }
 
 impl TokenBody {
@@ -139,7 +142,11 @@ mod tests {
   fn test_token_meta() {
     let username = "test_user".to_string();
     let is_admin = false;
-    let token_meta = TokenMeta { username, is_admin };
+    let token_meta = TokenMeta {
+      username,
+      is_admin,
+      must_change_password: false,
+    };
     assert_eq!(token_meta.username.as_str(), "test_user");
     assert!(!token_meta.is_admin);
   }
@@ -1,4 +1,8 @@
-use crate::{claim::CustomClaims, constants::JWT_DURATION_MINS, token::TokenBody, token_fields::*};
+use crate::{
+  claim::{CustomClaims, UserClaims},
+  token::TokenBody,
+  token_fields::*,
+};
 use anyhow::{anyhow, bail, ensure, Result};
 use chrono::{DateTime, Duration, Utc};
 use jwt_compact::{
@@ -81,24 +85,25 @@ impl SigningKey {
     }
   }
 
-  /// Generate token
+  /// Generate token valid for the given duration
   pub fn authorize(
     &self,
     subscriber_id: &SubscriberId,
     client_id: &ClientId,
     issuer: &Issuer,
-    is_admin: bool,
+    user: &UserClaims,
     refresh_required: bool,
+    duration: Duration,
   ) -> Result<TokenBody> {
     let custom_claims = CustomClaims {
       issuer: issuer.to_owned(),
       subscriber_id: subscriber_id.to_owned(),
       audiences: Audiences::new(client_id.as_str())?,
-      is_admin,
+      user: user.to_owned(),
     };
     let time_options = TimeOptions::default();
     let claims = jwt_compact::Claims::new(custom_claims)
-      .set_duration_and_issuance(&time_options, Duration::minutes(JWT_DURATION_MINS as i64))
+      .set_duration_and_issuance(&time_options, duration)
       .set_not_before(Utc::now() - Duration::minutes(10));
     let key_id = self.validation_key().key_id();
     let header = Header::empty().with_key_id(key_id);
@@ -143,6 +148,26 @@ pub enum ValidationKey {
   Ed25519(<Ed25519 as Algorithm>::VerifyingKey),
 }
 
+/// Kind of the subject a token is issued to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubjectKind {
+  /// A user, whose subscriber id is given as `sub`
+  #[default]
+  User,
+  /// A client application itself via the client credentials grant, whose client id is given as `sub`
+  Client,
+}
+
+impl SubjectKind {
+  /// Kind of the subject of the token given by its claims
+  pub fn from_claims(claims: &Claims) -> Self {
+    match claims.custom.get("cli").and_then(|v| v.as_bool()) {
+      Some(true) => Self::Client,
+      _ => Self::User,
+    }
+  }
+}
+
 /// Validation options
 pub struct ValidationOptions<T = fn() -> DateTime<Utc>>
 where
@@ -151,6 +176,10 @@ where
   pub time_options: TimeOptions<T>,
   pub allowed_issuers: Option<HashSet<Issuer>>,
   pub allowed_audiences: Option<Audiences>,
+  /// Scopes of restricted tokens to accept. Restricted tokens are rejected unless their scope is listed.
+  pub allowed_scopes: HashSet<String>,
+  /// Kind of the subject to accept, so that tokens of clients are never taken for those of users and vice versa
+  pub subject_kind: SubjectKind,
 }
 impl Default for ValidationOptions<fn() -> DateTime<Utc>> {
   fn default() -> Self {
@@ -158,6 +187,8 @@ impl Default for ValidationOptions<fn() -> DateTime<Utc>> {
       time_options: TimeOptions::default(),
       allowed_issuers: None,
       allowed_audiences: None,
+      allowed_scopes: HashSet::new(),
+      subject_kind: SubjectKind::User,
     }
   }
 }
@@ -303,6 +334,18 @@ impl ValidationKey {
         }
       }
     }
+    // validate scope of restricted token
+    if let Some(scope) = claims.custom.get("scope") {
+      let scope = scope.as_str().ok_or_else(|| anyhow!("Invalid scope"))?;
+      if !opt.allowed_scopes.contains(scope) {
+        bail!("Token is restricted to scope: {scope}");
+      }
+    }
+    // validate kind of subject
+    let subject_kind = SubjectKind::from_claims(claims);
+    if subject_kind != opt.subject_kind {
+      bail!("Token is issued to an unexpected kind of subject: {subject_kind:?}");
+    }
     Ok(claims.to_owned())
   }
 }
@@ -328,16 +371,116 @@ mod tests {
         &SubscriberId::new("test_user")?,
         &ClientId::new("client_id1")?,
         &Issuer::new("https://auth.example.com/v1.0")?,
+        &UserClaims::default(),
         false,
-        false,
+        Duration::minutes(5),
       )?;
       let vk = SigningKey::from_pem(key)?.validation_key();
-      let res = vk.validate(&token.id, &ValidationOptions::default());
-      assert!(res.is_ok());
+      let claims = vk.validate(&token.id, &ValidationOptions::default())?;
+      let (Some(issued_at), Some(expiration)) = (claims.issued_at, claims.expiration) else {
+        bail!("No iat or exp");
+      };
+      assert_eq!(expiration - issued_at, Duration::minutes(5));
+      assert!(!claims.custom.contains_key("email") && !claims.custom.contains_key("name"));
     }
     Ok(())
   }
 
+  #[test]
+  fn token_carries_profile_claims() -> Result<()> {
+    let sk = SigningKey::from_pem(P256_PRIVATE_KEY)?;
+    let user = UserClaims {
+      is_admin: Some(false),
+      roles: vec!["auditor".to_string()],
+      email: Some("user@example.com".to_string()),
+      name: Some("Example User".to_string()),
+      scope: None,
+      amr: vec!["pwd".to_string(), "otp".to_string(), "mfa".to_string()],
+      is_client: false,
+    };
+    let token = sk.authorize(
+      &SubscriberId::new("test_user")?,
+      &ClientId::new("client_id1")?,
+      &Issuer::new("https://auth.example.com/v1.0")?,
+      &user,
+      false,
+      Duration::minutes(5),
+    )?;
+    let claims = sk.validation_key().validate(&token.id, &ValidationOptions::default())?;
+    assert_eq!(claims.custom["email"], "user@example.com");
+    assert_eq!(claims.custom["name"], "Example User");
+    assert_eq!(claims.custom["roles"], serde_json::json!(["auditor"]));
+    assert_eq!(claims.custom["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+    Ok(())
+  }
+
+  #[test]
+  fn restricted_token_requires_allowed_scope() -> Result<()> {
+    let sk = SigningKey::from_pem(P256_PRIVATE_KEY)?;
+    let user = UserClaims {
+      scope: Some(crate::PASSWORD_CHANGE_SCOPE.to_string()),
+      ..Default::default()
+    };
+    let token = sk.authorize(
+      &SubscriberId::new("test_user")?,
+      &ClientId::new("client_id1")?,
+      &Issuer::new("https://auth.example.com/v1.0")?,
+      &user,
+      false,
+      Duration::minutes(5),
+    )?;
+    let vk = sk.validation_key();
+    assert!(vk.validate(&token.id, &ValidationOptions::default()).is_err());
+    let vo = ValidationOptions {
+      allowed_scopes: HashSet::from([crate::PASSWORD_CHANGE_SCOPE.to_string()]),
+      ..Default::default()
+    };
+    let claims = vk.validate(&token.id, &vo)?;
+    assert_eq!(claims.custom["scope"], crate::PASSWORD_CHANGE_SCOPE);
+    Ok(())
+  }
+
+  #[test]
+  fn client_token_requires_client_subject() -> Result<()> {
+    let sk = SigningKey::from_pem(P256_PRIVATE_KEY)?;
+    let client = UserClaims {
+      is_client: true,
+      ..Default::default()
+    };
+    let token = sk.authorize(
+      &SubscriberId::new("service_a")?,
+      &ClientId::new("service_a")?,
+      &Issuer::new("https://auth.example.com/v1.0")?,
+      &client,
+      false,
+      Duration::minutes(5),
+    )?;
+    let vk = sk.validation_key();
+    assert!(vk.validate(&token.id, &ValidationOptions::default()).is_err());
+    let vo = ValidationOptions {
+      subject_kind: SubjectKind::Client,
+      ..Default::default()
+    };
+    let claims = vk.validate(&token.id, &vo)?;
+    assert_eq!(SubjectKind::from_claims(&claims), SubjectKind::Client);
+    assert_eq!(claims.custom["sub"], "service_a");
+
+    // user tokens are not taken for client tokens
+    let token = sk.authorize(
+      &SubscriberId::new("test_user")?,
+      &ClientId::new("client_id1")?,
+      &Issuer::new("https://auth.example.com/v1.0")?,
+      &UserClaims::default(),
+      false,
+      Duration::minutes(5),
+    )?;
+    assert!(vk.validate(&token.id, &vo).is_err());
+    let claims = vk.validate(&token.id, &ValidationOptions::default())?;
+    assert_eq!(SubjectKind::from_claims(&claims), SubjectKind::User);
+    assert!(!claims.custom.contains_key("cli"));
+    Ok(())
+  }
+
   #[test]
   fn test_kid() -> Result<()> {
     let vk = SigningKey::from_pem(P256_PRIVATE_KEY)?.validation_key();
@@ -380,6 +523,8 @@ mod tests {
         time_options: stopped_time,
         allowed_issuers: Some(iss),
         allowed_audiences: Some(aud),
+        allowed_scopes: HashSet::new(),
+        subject_kind: SubjectKind::User,
       };
       let _res = vk.validate(&IdToken::new(pair.id_token)?, &vo)?;
     }
@@ -420,6 +565,8 @@ mod tests {
         time_options: stopped_time,
         allowed_issuers: Some(iss),
         allowed_audiences: Some(aud),
+        allowed_scopes: HashSet::new(),
+        subject_kind: SubjectKind::User,
       };
       let _res = vk.validate(&IdToken::new(pair.id_token)?, &vo)?;
     }
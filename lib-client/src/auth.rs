@@ -28,8 +28,8 @@ pub trait TokenHttpClient {
   async fn get_json<R>(&self, url: &Url) -> AuthResult<R>
   where
     R: DeserializeOwned + Send + Sync;
-
-  #[cfg(feature = "blind-signatures")]
+  /// Send POST request with JSON body and get JSON response using the given bearer token.
+  /// Required regardless of the `blind-signatures` feature since 0.6.0, as logouts also send id tokens.
   async fn post_json_with_bearer_token<S, R>(&self, url: &Url, json_body: &S, bearer_token: &str) -> AuthResult<R>
   where
     S: Serialize + Send + Sync,
@@ -179,6 +179,74 @@ where
     Ok(())
   }
 
+  /// Logout by revoking the refresh token at the server, and clear the id and refresh tokens held by the client.
+  /// Other sessions of the user are kept alive.
+  pub async fn logout(&self) -> AuthResult<()> {
+    let refresh_token_lock = self.refresh_token.read().await;
+    let refresh_token = refresh_token_lock.clone();
+    drop(refresh_token_lock);
+
+    if let Some(refresh_token) = refresh_token {
+      let mut logout_endpoint = self.config.token_api.clone();
+      logout_endpoint
+        .path_segments_mut()
+        .map_err(|_| AuthError::UrlError)?
+        .push(ENDPOINT_LOGOUT_PATH);
+
+      let json_request = LogoutRequest {
+        refresh_token: refresh_token.into_string(),
+        client_id: Some(self.config.client_id.clone()),
+      };
+
+      let client_lock = self.http_client.read().await;
+      let _res = client_lock
+        .post_json::<_, MessageResponse>(&logout_endpoint, &json_request)
+        .await?;
+      drop(client_lock);
+    }
+
+    self.clear_tokens().await;
+    info!("Logout success!");
+    Ok(())
+  }
+
+  /// Logout from all sessions of the user by revoking all of the user's refresh tokens at the server,
+  /// and clear the id and refresh tokens held by the client.
+  pub async fn logout_all(&self) -> AuthResult<()> {
+    let token_body = self.token().await?;
+
+    let mut logout_all_endpoint = self.config.token_api.clone();
+    logout_all_endpoint
+      .path_segments_mut()
+      .map_err(|_| AuthError::UrlError)?
+      .push(ENDPOINT_LOGOUT_ALL_PATH);
+
+    let client_lock = self.http_client.read().await;
+    let _res = client_lock
+      .post_json_with_bearer_token::<_, MessageResponse>(
+        &logout_all_endpoint,
+        &serde_json::json!({}),
+        token_body.id.as_str(),
+      )
+      .await?;
+    drop(client_lock);
+
+    self.clear_tokens().await;
+    info!("Logout from all sessions success!");
+    Ok(())
+  }
+
+  /// Clear id and refresh tokens
+  async fn clear_tokens(&self) {
+    let mut refresh_token_lock = self.refresh_token.write().await;
+    refresh_token_lock.take();
+    drop(refresh_token_lock);
+
+    let mut id_token_lock = self.id_token.write().await;
+    id_token_lock.take();
+    drop(id_token_lock);
+  }
+
   /// Update jwks key
   async fn update_validation_key(&self) -> AuthResult<()> {
     let id_token_lock = self.id_token.read().await;
@@ -280,6 +348,28 @@ where
     let is_admin = clm.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false);
     Ok(is_admin)
   }
+
+  /// Get roles of myself. Tokens without the roles claim have only the admin role if `iad` is true.
+  pub async fn roles(&self) -> AuthResult<Vec<String>> {
+    let clm = self.verify_id_token().await?;
+    let mut roles = clm
+      .custom
+      .get("roles")
+      .and_then(|v| v.as_array())
+      .map(|a| a.iter().filter_map(|r| r.as_str().map(|r| r.to_string())).collect::<Vec<_>>())
+      .unwrap_or_default();
+    let is_admin = clm.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false);
+    if is_admin && !roles.iter().any(|r| r == ROLE_ADMIN) {
+      roles.push(ROLE_ADMIN.to_string());
+    }
+    Ok(roles)
+  }
+
+  /// Check if myself can create, delete and log out users
+  async fn can_manage_users(&self) -> AuthResult<bool> {
+    let roles = self.roles().await?;
+    Ok(roles.iter().any(|r| r == ROLE_ADMIN || r == ROLE_USER_MANAGER))
+  }
 }
 
 /* ---------------------------------------------------- */
@@ -288,10 +378,9 @@ impl<H> TokenClient<H>
 where
   H: AdminTokenHttpClient,
 {
-  /// Create a user under the admin privilege
+  /// Create a user under the admin or user-manager role
   pub async fn create_user(&self, username: &str, password: &str) -> AuthResult<String> {
-    let is_admin = self.is_admin().await?;
-    if !is_admin {
+    if !self.can_manage_users().await? {
       return Err(AuthError::NotAllowed);
     }
 
@@ -318,10 +407,9 @@ where
     Ok(_res.message)
   }
 
-  /// Delete a user under the admin privilege
+  /// Delete a user under the admin or user-manager role
   pub async fn delete_user(&self, username: &str) -> AuthResult<String> {
-    let is_admin = self.is_admin().await?;
-    if !is_admin {
+    if !self.can_manage_users().await? {
       return Err(AuthError::NotAllowed);
     }
 
@@ -344,4 +432,122 @@ where
 
     Ok(_res.message)
   }
+
+  /// Revoke all sessions of a user under the admin or user-manager role
+  pub async fn logout_user(&self, username: &str) -> AuthResult<String> {
+    if !self.can_manage_users().await? {
+      return Err(AuthError::NotAllowed);
+    }
+
+    let mut logout_user_endpoint = self.config.token_api.clone();
+    logout_user_endpoint
+      .path_segments_mut()
+      .map_err(|_| AuthError::UrlError)?
+      .push(ENDPOINT_LOGOUT_USER_PATH);
+
+    let json_request = LogoutUserRequest {
+      username: username.to_string(),
+    };
+    let token_body = self.token().await?;
+
+    let client_lock = self.http_client.read().await;
+    let _res = client_lock
+      .post_json_admin::<_, MessageResponse>(&logout_user_endpoint, &json_request, &token_body)
+      .await?;
+    drop(client_lock);
+
+    Ok(_res.message)
+  }
+
+  /// Reset the password of a user under the admin or user-manager role, which the user must change at the next login.
+  /// Returns the temporary password generated by the server if no password is given.
+  pub async fn reset_password(&self, username: &str, password: Option<&str>) -> AuthResult<Option<String>> {
+    if !self.can_manage_users().await? {
+      return Err(AuthError::NotAllowed);
+    }
+
+    let mut reset_password_endpoint = self.config.token_api.clone();
+    reset_password_endpoint
+      .path_segments_mut()
+      .map_err(|_| AuthError::UrlError)?
+      .push(ENDPOINT_RESET_PASSWORD_PATH);
+
+    let json_request = ResetPasswordRequest {
+      username: username.to_string(),
+      password: password.map(|p| p.to_string()),
+    };
+    let token_body = self.token().await?;
+
+    let client_lock = self.http_client.read().await;
+    let res = client_lock
+      .post_json_admin::<_, ResetPasswordResponse>(&reset_password_endpoint, &json_request, &token_body)
+      .await?;
+    drop(client_lock);
+
+    Ok(res.password)
+  }
+
+  /// Replace roles of a user other than the admin role under the admin privilege
+  pub async fn update_roles(&self, username: &str, roles: &[&str]) -> AuthResult<String> {
+    let is_admin = self.is_admin().await?;
+    if !is_admin {
+      return Err(AuthError::NotAllowed);
+    }
+
+    let mut update_roles_endpoint = self.config.token_api.clone();
+    update_roles_endpoint
+      .path_segments_mut()
+      .map_err(|_| AuthError::UrlError)?
+      .push(ENDPOINT_UPDATE_ROLES_PATH);
+
+    let json_request = UpdateRolesRequest {
+      username: username.to_string(),
+      roles: roles.iter().map(|r| r.to_string()).collect(),
+    };
+    let token_body = self.token().await?;
+
+    let client_lock = self.http_client.read().await;
+    let _res = client_lock
+      .post_json_admin::<_, MessageResponse>(&update_roles_endpoint, &json_request, &token_body)
+      .await?;
+    drop(client_lock);
+
+    Ok(_res.message)
+  }
+
+  /// Give the admin role to a user under the admin privilege
+  pub async fn grant_admin(&self, username: &str) -> AuthResult<String> {
+    self.update_admin_role(ENDPOINT_GRANT_ADMIN_PATH, username).await
+  }
+
+  /// Take the admin role from a user under the admin privilege. The server refuses to demote the last admin.
+  pub async fn revoke_admin(&self, username: &str) -> AuthResult<String> {
+    self.update_admin_role(ENDPOINT_REVOKE_ADMIN_PATH, username).await
+  }
+
+  async fn update_admin_role(&self, path: &str, username: &str) -> AuthResult<String> {
+    let is_admin = self.is_admin().await?;
+    if !is_admin {
+      return Err(AuthError::NotAllowed);
+    }
+
+    let mut admin_role_endpoint = self.config.token_api.clone();
+    admin_role_endpoint
+      .path_segments_mut()
+      .map_err(|_| AuthError::UrlError)?
+      .push(path);
+
+    let json_request = AdminRoleRequest {
+      username: username.to_string(),
+    };
+    let token_body = self.token().await?;
+
+    let client_lock = self.http_client.read().await;
+    let _res = client_lock
+      .post_json_admin::<_, MessageResponse>(&admin_role_endpoint, &json_request, &token_body)
+      .await?;
+    drop(client_lock);
+
+    Ok(_res.message)
+  }
 }
@@ -1,8 +1,18 @@
 pub const ENDPOINT_LOGIN_PATH: &str = "tokens";
 pub const ENDPOINT_REFRESH_PATH: &str = "refresh";
+pub const ENDPOINT_LOGOUT_PATH: &str = "logout";
+pub const ENDPOINT_LOGOUT_ALL_PATH: &str = "logout_all";
 pub const ENDPOINT_JWKS_PATH: &str = "jwks";
 pub const ENDPOINT_CREATE_USER_PATH: &str = "create_user";
 pub const ENDPOINT_DELETE_USER_PATH: &str = "delete_user";
+pub const ENDPOINT_LOGOUT_USER_PATH: &str = "logout_user";
+pub const ENDPOINT_RESET_PASSWORD_PATH: &str = "reset_password";
+pub const ENDPOINT_UPDATE_ROLES_PATH: &str = "update_roles";
+pub const ENDPOINT_GRANT_ADMIN_PATH: &str = "grant_admin";
+pub const ENDPOINT_REVOKE_ADMIN_PATH: &str = "revoke_admin";
+
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_USER_MANAGER: &str = "user-manager";
 
 #[cfg(feature = "blind-signatures")]
 pub const BLIND_MESSAGE_BYTES: usize = 32;
@@ -73,7 +73,6 @@ mod tests {
 
       Ok(json_res)
     }
-    #[cfg(feature = "blind-signatures")]
     async fn post_json_with_bearer_token<S, R>(&self, url: &Url, json_body: &S, bearer_token: &str) -> AuthResult<R>
     where
       S: Serialize + Send + Sync,
@@ -169,6 +168,24 @@ mod tests {
     assert!(res.is_err());
   }
 
+  #[tokio::test]
+  async fn logout_apis_works() {
+    let token_client = get_token_client().await;
+
+    token_client.login().await.unwrap();
+    token_client.logout().await.unwrap();
+    assert!(token_client.token().await.is_err());
+    assert!(token_client.refresh().await.is_err());
+
+    token_client.login().await.unwrap();
+    token_client.logout_all().await.unwrap();
+    assert!(token_client.token().await.is_err());
+
+    token_client.login().await.unwrap();
+    let res = token_client.logout_user("test_user_not_exist").await;
+    assert!(res.is_err());
+  }
+
   #[tokio::test]
   async fn blind_sign_api_works() {
     let token_client = get_token_client().await;
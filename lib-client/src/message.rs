@@ -36,6 +36,13 @@ pub(super) struct RefreshRequest {
   pub client_id: Option<String>,
 }
 
+/// Logout request
+#[derive(Serialize, Debug)]
+pub(super) struct LogoutRequest {
+  pub refresh_token: String,
+  pub client_id: Option<String>,
+}
+
 /// Create user request
 #[derive(Serialize, Debug)]
 pub(super) struct CreateUserRequest {
@@ -43,7 +50,6 @@ pub(super) struct CreateUserRequest {
 }
 #[derive(Serialize, Debug)]
 /// Create user request inner
-
 pub(super) struct CreateUserReqInner {
   pub username: String,
   pub password: String,
@@ -55,6 +61,42 @@ pub struct DeleteUserRequest {
   pub username: String,
 }
 
+/// Logout user request
+#[derive(Serialize, Debug)]
+pub(super) struct LogoutUserRequest {
+  pub username: String,
+}
+
+/// Reset password request
+#[derive(Serialize, Debug)]
+pub(super) struct ResetPasswordRequest {
+  pub username: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Reset password response
+pub(super) struct ResetPasswordResponse {
+  /// Temporary password generated by the server if none is given
+  pub password: Option<String>,
+  #[allow(dead_code)]
+  pub message: String,
+}
+
+/// Grant or revoke admin request
+#[derive(Serialize, Debug)]
+pub(super) struct AdminRoleRequest {
+  pub username: String,
+}
+
+/// Update roles request
+#[derive(Serialize, Debug)]
+pub(super) struct UpdateRolesRequest {
+  pub username: String,
+  pub roles: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
 /// Create/delete user response
 pub(super) struct MessageResponse {
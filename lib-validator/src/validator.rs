@@ -5,7 +5,7 @@ use base64::{engine::general_purpose, Engine as _};
 use futures::future::join_all;
 use libcommon::{
   token_fields::{Audiences, ClientId, Issuer, TryNewField},
-  Claims, ValidationKey, ValidationOptions,
+  Claims, SubjectKind, ValidationKey, ValidationOptions,
 };
 use rustc_hash::FxHashMap as HashMap;
 use serde::{de::DeserializeOwned, Deserialize};
@@ -63,10 +63,21 @@ where
     Ok(validator)
   }
 
-  /// Validate an id token.
+  /// Validate an id token issued to a user. Tokens issued to client applications via the client credentials grant are
+  /// rejected, so use [`TokenValidator::validate_client_token`] for them.
   /// First by checking the key id in the id token header and try to find the validation key with the key id.
   /// Return Ok(()) if validation is successful with the found validation key.
   pub async fn validate(&self, id_token: &str) -> Result<Vec<Claims>> {
+    self.validate_subject(id_token, SubjectKind::User).await
+  }
+
+  /// Validate a token issued to a client application itself via the client credentials grant, whose `sub` claim is the
+  /// client id. Tokens issued to users are rejected.
+  pub async fn validate_client_token(&self, token: &str) -> Result<Vec<Claims>> {
+    self.validate_subject(token, SubjectKind::Client).await
+  }
+
+  async fn validate_subject(&self, id_token: &str, subject_kind: SubjectKind) -> Result<Vec<Claims>> {
     let key_id_in_id_token = key_id_in_id_token(id_token).ok_or(anyhow!("key id not found in id token"))?;
 
     let futures = self.inner.iter().map(|each| {
@@ -77,7 +88,11 @@ where
           return None; // no matched key id
         };
         // matched case
-        let res = vk.validate(&id_token.try_into().ok()?, &each.validation_options);
+        let validation_options = match subject_kind {
+          SubjectKind::User => &each.validation_options,
+          SubjectKind::Client => &each.client_validation_options,
+        };
+        let res = vk.validate(&id_token.try_into().ok()?, validation_options);
         Some(res)
       }
     });
@@ -197,6 +212,8 @@ where
   pub(crate) validation_keys: Arc<RwLock<HashMap<KeyId, ValidationKey>>>,
   /// Validation options
   pub(crate) validation_options: ValidationOptions,
+  /// Validation options for tokens issued to client applications
+  pub(crate) client_validation_options: ValidationOptions,
   /// http client to fetch jwks
   jwks_http_client: Arc<H>,
 
@@ -229,8 +246,14 @@ where
     iss.insert(Issuer::new(config.token_issuer.as_str()).unwrap_or(Issuer::new("http://localhost:3000").unwrap()));
     let aud = Audiences::from(config.client_ids.iter().flat_map(|id| ClientId::new(id.as_str())));
     let validation_options = ValidationOptions {
+      allowed_issuers: Some(iss.clone()),
+      allowed_audiences: Some(aud.clone()),
+      ..Default::default()
+    };
+    let client_validation_options = ValidationOptions {
       allowed_issuers: Some(iss),
       allowed_audiences: Some(aud),
+      subject_kind: SubjectKind::Client,
       ..Default::default()
     };
 
@@ -238,6 +261,7 @@ where
       token_api,
       validation_keys,
       validation_options,
+      client_validation_options,
       jwks_http_client: http_client.clone(),
 
       #[cfg(feature = "blind-signatures")]
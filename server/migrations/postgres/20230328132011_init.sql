@@ -0,0 +1,16 @@
+-- Add migration script here
+create table if not exists users (
+  id bigserial primary key,
+  username text not null unique,
+  subscriber_id text not null unique,
+  encoded_hash text not null,
+  is_admin boolean not null default false
+);
+
+create table if not exists tokens (
+  id bigserial primary key,
+  subscriber_id text not null,
+  client_id text not null,
+  refresh_token text not null,
+  expires bigint not null
+);
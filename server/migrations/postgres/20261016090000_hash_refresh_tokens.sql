@@ -0,0 +1,14 @@
+-- Refresh tokens are stored as keyed hashes from now on. Plaintext refresh tokens issued so far cannot be converted
+-- without the server secret, so they are invalidated and users need to log in again.
+drop table if exists tokens;
+
+create table if not exists tokens (
+  id bigserial primary key,
+  subscriber_id text not null,
+  client_id text not null,
+  refresh_token_hash text not null,
+  expires bigint not null
+);
+
+create unique index if not exists tokens_refresh_token_hash on tokens (refresh_token_hash);
+create index if not exists tokens_expires on tokens (expires);
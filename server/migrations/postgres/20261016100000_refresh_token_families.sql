@@ -0,0 +1,8 @@
+-- Refresh tokens are rotated on every refresh. Rotated tokens share the family of the original login, and consumed
+-- tokens are kept until they expire to detect their reuse. Each existing token becomes a family of its own.
+alter table tokens add column if not exists family_id text;
+update tokens set family_id = refresh_token_hash where family_id is null;
+alter table tokens alter column family_id set not null;
+alter table tokens add column if not exists consumed boolean not null default false;
+
+create index if not exists tokens_family_id on tokens (family_id);
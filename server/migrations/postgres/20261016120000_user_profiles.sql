@@ -0,0 +1,4 @@
+-- Optional profile of users. Attributes are a free-form JSON object never interpreted by the server.
+alter table users add column if not exists email text;
+alter table users add column if not exists display_name text;
+alter table users add column if not exists attributes jsonb not null default '{}'::jsonb;
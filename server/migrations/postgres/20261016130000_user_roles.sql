@@ -0,0 +1,4 @@
+-- Roles granted to users, stored as a JSON array of role names. Existing admins get the admin role, and is_admin is
+-- kept in sync with it.
+alter table users add column if not exists roles jsonb not null default '[]'::jsonb;
+update users set roles = '["admin"]'::jsonb where is_admin;
@@ -0,0 +1,3 @@
+-- Disabled users and users locked until the given UNIX time in seconds cannot get tokens
+alter table users add column if not exists enabled boolean not null default true;
+alter table users add column if not exists locked_until bigint;
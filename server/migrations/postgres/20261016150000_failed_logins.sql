@@ -0,0 +1,2 @@
+-- Number of consecutive failed logins, which locks the account when it reaches the threshold
+alter table users add column if not exists failed_logins integer not null default 0;
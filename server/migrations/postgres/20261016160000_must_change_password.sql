@@ -0,0 +1,2 @@
+-- Users whose password has been reset by an admin must change it before getting unrestricted tokens
+alter table users add column if not exists must_change_password boolean not null default false;
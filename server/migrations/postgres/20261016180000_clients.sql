@@ -0,0 +1,8 @@
+-- Client applications allowed to get tokens, managed via the client APIs and seeded from --client-ids
+create table if not exists clients (
+  id bigserial primary key,
+  client_id text not null unique,
+  display_name text,
+  enabled boolean not null default true,
+  created_at bigint not null
+);
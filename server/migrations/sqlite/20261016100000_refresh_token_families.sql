@@ -0,0 +1,21 @@
+-- Refresh tokens are rotated on every refresh. Rotated tokens share the family of the original login, and consumed
+-- tokens are kept until they expire to detect their reuse. Each existing token becomes a family of its own.
+create table if not exists tokens_with_family (
+  id integer primary key,
+  subscriber_id text not null,
+  client_id text not null,
+  refresh_token_hash text not null,
+  family_id text not null,
+  consumed integer not null default 0,
+  expires integer not null
+);
+
+insert into tokens_with_family (id, subscriber_id, client_id, refresh_token_hash, family_id, consumed, expires)
+  select id, subscriber_id, client_id, refresh_token_hash, refresh_token_hash, 0, expires from tokens;
+
+drop table tokens;
+alter table tokens_with_family rename to tokens;
+
+create unique index if not exists tokens_refresh_token_hash on tokens (refresh_token_hash);
+create index if not exists tokens_expires on tokens (expires);
+create index if not exists tokens_family_id on tokens (family_id);
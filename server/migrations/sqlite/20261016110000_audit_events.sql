@@ -0,0 +1,14 @@
+-- Security events like logins and user management operations, kept for auditing
+create table if not exists audit_events (
+  id integer primary key autoincrement,
+  created_at integer not null,
+  kind text not null,
+  actor text,
+  target text,
+  client_id text,
+  remote_addr text
+);
+
+create index if not exists audit_events_created_at on audit_events (created_at);
+create index if not exists audit_events_actor on audit_events (actor);
+create index if not exists audit_events_target on audit_events (target);
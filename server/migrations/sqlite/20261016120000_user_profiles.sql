@@ -0,0 +1,4 @@
+-- Optional profile of users. Attributes are a free-form JSON object never interpreted by the server.
+alter table users add column email text;
+alter table users add column display_name text;
+alter table users add column attributes text not null default '{}';
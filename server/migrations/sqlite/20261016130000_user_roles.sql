@@ -0,0 +1,4 @@
+-- Roles granted to users, stored as a JSON array of role names. Existing admins get the admin role, and is_admin is
+-- kept in sync with it.
+alter table users add column roles text not null default '[]';
+update users set roles = '["admin"]' where is_admin = 'true';
@@ -0,0 +1,3 @@
+-- Disabled users and users locked until the given UNIX time in seconds cannot get tokens
+alter table users add column enabled integer not null default 1;
+alter table users add column locked_until integer;
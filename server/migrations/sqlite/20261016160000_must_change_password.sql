@@ -0,0 +1,2 @@
+-- Users whose password has been reset by an admin must change it before getting unrestricted tokens
+alter table users add column must_change_password integer not null default 0;
@@ -0,0 +1,18 @@
+-- TOTP factors of users, which are pending until the first code is verified, and single-use recovery codes stored as
+-- Argon2 hashes. Refresh tokens keep the authentication methods of the login to emit them in refreshed id tokens.
+create table if not exists totp_factors (
+  subscriber_id text primary key,
+  secret text not null,
+  enabled integer not null default 0,
+  last_step integer
+);
+
+create table if not exists recovery_codes (
+  id integer primary key,
+  subscriber_id text not null,
+  encoded_hash text not null
+);
+
+create index if not exists recovery_codes_subscriber_id on recovery_codes (subscriber_id);
+
+alter table tokens add column amr text not null default '["pwd"]';
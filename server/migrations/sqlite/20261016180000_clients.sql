@@ -0,0 +1,8 @@
+-- Client applications allowed to get tokens, managed via the client APIs and seeded from --client-ids
+create table if not exists clients (
+  id integer primary key,
+  client_id text not null unique,
+  display_name text,
+  enabled integer not null default 1,
+  created_at integer not null
+);
@@ -0,0 +1,2 @@
+-- Confidential clients authenticate with the client secret to get tokens by themselves via the client credentials grant
+alter table clients add column secret_hash text;
@@ -0,0 +1,6 @@
+-- TOTP secrets are encrypted and recovery codes are stored as keyed hashes with the server key from now on. Factors
+-- stored so far cannot be converted without the key, so they are removed and users need to enroll TOTP again.
+delete from recovery_codes;
+delete from totp_factors;
+
+alter table recovery_codes rename column encoded_hash to code_hash;
@@ -0,0 +1,203 @@
+use super::{request::AdminRoleRequest, response::MessageResponse};
+use crate::{
+  constants::ADMIN_USERNAME,
+  entity::{AuditEvent, AuditEventKind, Permission, Role, Roles},
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum AdminRoleError {
+  RoleUpdateFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchUser,
+  LastAdmin,
+  DemoteProhibitedUser,
+}
+impl IntoResponse for AdminRoleError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      AdminRoleError::RoleUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Role update failed"),
+      AdminRoleError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      AdminRoleError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      AdminRoleError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      AdminRoleError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      AdminRoleError::LastAdmin => (StatusCode::BAD_REQUEST, "The last admin cannot be demoted"),
+      AdminRoleError::DemoteProhibitedUser => (StatusCode::BAD_REQUEST, "The built-in admin cannot be demoted"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Give the admin role to the given user under the admin privilege
+pub async fn grant_admin(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<AdminRoleRequest>,
+) -> Result<Json<MessageResponse>, AdminRoleError> {
+  update_admin_role(state, remote_addr, headers, request, true).await?;
+  Ok(Json(MessageResponse {
+    message: "ok. granted admin to the user.".to_string(),
+  }))
+}
+
+/// Take the admin role from the given user under the admin privilege. Neither the built-in admin nor the last admin can
+/// be demoted.
+pub async fn revoke_admin(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<AdminRoleRequest>,
+) -> Result<Json<MessageResponse>, AdminRoleError> {
+  update_admin_role(state, remote_addr, headers, request, false).await?;
+  Ok(Json(MessageResponse {
+    message: "ok. revoked admin from the user.".to_string(),
+  }))
+}
+
+async fn update_admin_role(
+  state: Arc<AppState>,
+  remote_addr: SocketAddr,
+  headers: HeaderMap,
+  request: AdminRoleRequest,
+  grant: bool,
+) -> Result<(), AdminRoleError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(AdminRoleError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(AdminRoleError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(AdminRoleError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(AdminRoleError::InvalidToken);
+  };
+
+  // roles in the token must permit to manage roles
+  if !Roles::from_claims(&claims).permits(Permission::ManageRoles) {
+    return Err(AdminRoleError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(AdminRoleError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(AdminRoleError::RoleUpdateFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(AdminRoleError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageRoles) {
+    return Err(AdminRoleError::InvalidToken);
+  }
+
+  // check if the user exist
+  let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&request.username)).await else {
+    return Err(AdminRoleError::RoleUpdateFailed);
+  };
+  let Some(target_user) = u else {
+    return Err(AdminRoleError::NoSuchUser);
+  };
+  // the built-in admin keeps full rights, as it cannot be deleted either
+  if !grant && target_user.username() == ADMIN_USERNAME {
+    return Err(AdminRoleError::DemoteProhibitedUser);
+  }
+
+  let mut roles = target_user.roles.clone();
+  if grant {
+    roles.insert(Role::Admin);
+  } else {
+    roles.remove(Role::Admin);
+  }
+  let Ok(updated) = state
+    .table
+    .user
+    .update_roles(UserSearchKey::SubscriberId(&target_user.subscriber_id), &roles)
+    .await
+  else {
+    return Err(AdminRoleError::RoleUpdateFailed);
+  };
+  // at least one admin must remain, which is checked atomically with the update against concurrent demotions
+  if !updated {
+    return Err(AdminRoleError::LastAdmin);
+  }
+
+  let event = AuditEvent::new(AuditEventKind::RolesUpdated, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{delete_user, delete_user::DeleteUserError, tests::*},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD},
+  };
+
+  fn request<T: serde::de::DeserializeOwned>(username: &str) -> Json<T> {
+    Json(serde_json::from_value(json!({ "username": username })).unwrap())
+  }
+
+  #[tokio::test]
+  async fn last_admin_cannot_be_demoted_or_deleted() {
+    let state = test_state().await;
+    add_user(&state, "operator", "operator_password").await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    // the built-in admin keeps the role whoever revokes it
+    let res = revoke_admin(State(state.clone()), remote_addr(), bearer(&admin), request("admin")).await;
+    assert!(matches!(res, Err(AdminRoleError::DemoteProhibitedUser)));
+
+    let operator = login(&state, "operator", "operator_password").await.token;
+    let res = grant_admin(State(state.clone()), remote_addr(), bearer(&operator), request("operator")).await;
+    assert!(matches!(res, Err(AdminRoleError::UnauthorizedUser)));
+    assert!(grant_admin(State(state.clone()), remote_addr(), bearer(&admin), request("operator")).await.is_ok());
+    let (users, _, _) = state.table.user.list_users(1).await.unwrap();
+    assert_eq!(users.iter().filter(|u| u.is_admin()).count(), 2);
+
+    // new admin can neither demote nor delete the built-in one
+    let operator = login(&state, "operator", "operator_password").await.token;
+    assert_eq!(state.crypto.verify_token(&operator.id).unwrap().custom["iad"], true);
+    let res = revoke_admin(State(state.clone()), remote_addr(), bearer(&operator), request("admin")).await;
+    assert!(matches!(res, Err(AdminRoleError::DemoteProhibitedUser)));
+    let res = delete_user(State(state.clone()), remote_addr(), bearer(&operator), request("admin")).await;
+    assert!(matches!(res, Err(DeleteUserError::DeleteProhibitedUser)));
+
+    add_user(&state, "another", "another_password").await;
+    assert!(grant_admin(State(state.clone()), remote_addr(), bearer(&operator), request("another")).await.is_ok());
+    let another = login(&state, "another", "another_password").await.token;
+    assert!(revoke_admin(State(state.clone()), remote_addr(), bearer(&another), request("another")).await.is_ok());
+    let res = delete_user(State(state.clone()), remote_addr(), bearer(&another), request("operator")).await;
+    assert!(res.is_err());
+    assert!(revoke_admin(State(state.clone()), remote_addr(), bearer(&admin), request("operator")).await.is_ok());
+    let (users, _, _) = state.table.user.list_users(1).await.unwrap();
+    assert_eq!(users.iter().filter(|u| u.is_admin()).count(), 1);
+  }
+}
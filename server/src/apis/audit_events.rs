@@ -0,0 +1,172 @@
+use super::{
+  request::AuditEventsRequest,
+  response::{AuditEventsResponse, AuditEventsResponseInner},
+};
+use crate::{
+  entity::{AuditEventFilter, Permission, Roles},
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::State,
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use chrono::TimeZone;
+use serde_json::json;
+use std::sync::Arc;
+
+use libcommon::token_fields::{Field, IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum AuditEventsError {
+  QueryFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  InvalidRequest,
+}
+impl IntoResponse for AuditEventsError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      AuditEventsError::QueryFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Audit event query failed"),
+      AuditEventsError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      AuditEventsError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      AuditEventsError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      AuditEventsError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Admin-only query of the audit log, newest first
+pub async fn audit_events(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Json(request): Json<AuditEventsRequest>,
+) -> Result<Json<AuditEventsResponse>, AuditEventsError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(AuditEventsError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(AuditEventsError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(AuditEventsError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(AuditEventsError::InvalidToken);
+  };
+
+  // roles in the token must permit to read the audit log
+  if !Roles::from_claims(&claims).permits(Permission::ReadAuditLog) {
+    return Err(AuditEventsError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(AuditEventsError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(AuditEventsError::QueryFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(AuditEventsError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ReadAuditLog) {
+    return Err(AuditEventsError::InvalidToken);
+  }
+
+  let to_time = |t: Option<i64>| match t {
+    Some(t) => chrono::Local
+      .timestamp_opt(t, 0)
+      .single()
+      .map(Some)
+      .ok_or(AuditEventsError::InvalidRequest),
+    None => Ok(None),
+  };
+  let filter = AuditEventFilter {
+    kind: request.kind,
+    actor: request.actor,
+    target: request.target,
+    client_id: request.client_id,
+    since: to_time(request.since)?,
+    until: to_time(request.until)?,
+  };
+  let current_page = request.page.unwrap_or(1u32);
+
+  let Ok((events, total_pages, total_events)) = state.table.audit_log.query(&filter, current_page).await else {
+    return Err(AuditEventsError::QueryFailed);
+  };
+
+  Ok(Json(AuditEventsResponse {
+    events: events
+      .into_iter()
+      .map(|e| AuditEventsResponseInner {
+        timestamp: e.timestamp.timestamp(),
+        kind: e.kind,
+        actor: e.actor.map(|a| a.into_string()),
+        target: e.target,
+        client_id: e.client_id.map(|c| c.into_string()),
+        remote_addr: e.remote_addr,
+      })
+      .collect(),
+    page: current_page,
+    total_pages,
+    total_events,
+    message: "Success".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{get_tokens, tests::*},
+    entity::AuditEventKind,
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+
+  fn request(filter: serde_json::Value) -> Json<AuditEventsRequest> {
+    Json(serde_json::from_value(filter).unwrap())
+  }
+
+  #[tokio::test]
+  async fn admin_queries_recorded_events() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let failed_login = json!({
+      "auth": { "username": "user", "password": "wrong_password" },
+      "client_id": TEST_CLIENT_ID,
+    });
+    let res = get_tokens(State(state.clone()), remote_addr(), Json(serde_json::from_value(failed_login).unwrap())).await;
+    assert!(res.is_err());
+
+    // only admin can call it
+    let res = audit_events(State(state.clone()), bearer(&user), request(json!({}))).await;
+    assert!(matches!(res, Err(AuditEventsError::UnauthorizedUser)));
+
+    let res = audit_events(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    assert_eq!(res.total_events, 3);
+    let kinds = res.events.iter().map(|e| e.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [AuditEventKind::LoginFailed, AuditEventKind::Login, AuditEventKind::Login]);
+
+    let filter = json!({ "kind": "login", "actor": user.subscriber_id.as_str() });
+    let res = audit_events(State(state.clone()), bearer(&admin), request(filter)).await.unwrap();
+    assert_eq!(res.total_events, 1);
+    let event = &res.events[0];
+    assert_eq!(event.target.as_deref(), Some("user"));
+    assert_eq!(event.client_id.as_deref(), Some(TEST_CLIENT_ID));
+    assert_eq!(event.remote_addr.as_deref(), Some("192.0.2.1"));
+  }
+}
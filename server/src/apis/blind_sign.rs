@@ -1,23 +1,22 @@
 use super::{request::BlindSignRequest, response::BlindSignResponse};
 use crate::{
-  constants::DEFAUTL_CLIENT_ID,
-  entity::Entity,
+  entity::{AccountState, AuditEvent, AuditEventKind, Entity, Permission, User},
   log::*,
-  state::AppState,
-  table::{UserSearchKey, UserTable},
+  state::{AppState, ClientAuthorization, UserAuthentication},
+  table::UserSearchKey,
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::{HeaderMap, StatusCode},
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use libcommon::{
   blind_sig::BlindedToken,
-  token_fields::{ClientId, Field, IdToken, SubscriberId, TryNewField},
+  token_fields::{Field, IdToken, SubscriberId, TryNewField},
 };
 
 #[derive(Debug)]
@@ -30,6 +29,11 @@ pub enum BlindSignError {
   InvalidRequest,
   MissingToken,
   InvalidToken,
+  AccountDisabled,
+  AccountLocked,
+  PasswordChangeRequired,
+  TotpRequired,
+  InvalidTotp,
 }
 impl IntoResponse for BlindSignError {
   fn into_response(self) -> Response {
@@ -42,6 +46,11 @@ impl IntoResponse for BlindSignError {
       BlindSignError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
       BlindSignError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
       BlindSignError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      BlindSignError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+      BlindSignError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
+      BlindSignError::PasswordChangeRequired => (StatusCode::FORBIDDEN, "Password change required"),
+      BlindSignError::TotpRequired => (StatusCode::UNAUTHORIZED, "TOTP code required"),
+      BlindSignError::InvalidTotp => (StatusCode::UNAUTHORIZED, "Invalid TOTP code"),
     };
     let body = Json(json!({
         "error": error_message,
@@ -52,43 +61,48 @@ impl IntoResponse for BlindSignError {
 
 pub async fn blind_sign(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   headers: HeaderMap,
   Json(input): Json<BlindSignRequest>,
 ) -> Result<Json<BlindSignResponse>, BlindSignError> {
   // Either id/password or id token is required
 
-  if let Some(auth) = input.auth {
+  // user who requested the signature, recorded in the audit log
+  let (actor, client_id) = if let Some(auth) = input.auth {
     // found id/password, which is prioritized over the id token based verification
     debug!("Performing blind signing based on the authentication with id/password.");
     let (username, password) = (auth.username, auth.password);
 
-    // check user existence
-    let Ok(user) = state.table.user.find_user(UserSearchKey::Username(&username)).await else {
-      return Err(BlindSignError::SignFailed);
-    };
-    let Some(user) = user else {
-      return Err(BlindSignError::UnauthorizedUser);
-    };
-    // check allowed client ids if audience is some
-    if state.crypto.audiences.is_some() {
-      let Some(client_id) = input.client_id else {
-        return Err(BlindSignError::InvalidRequest);
-      };
-      if !state.crypto.audiences.as_ref().unwrap().contains(&client_id) {
-        return Err(BlindSignError::UnauthorizedClientApp);
-      }
-      debug!("{} is verified by client_id {}.", username.as_str(), client_id.as_str());
-    } else if ClientId::new(DEFAUTL_CLIENT_ID).is_err() {
-      return Err(BlindSignError::SignFailed);
+    // check the client id against the client registry
+    let client_id = match state.authorize_client(input.client_id).await {
+      Ok(ClientAuthorization::Allowed(client_id)) => client_id,
+      Ok(ClientAuthorization::Missing) => return Err(BlindSignError::InvalidRequest),
+      Ok(ClientAuthorization::Unauthorized) => return Err(BlindSignError::UnauthorizedClientApp),
+      Err(_) => return Err(BlindSignError::SignFailed),
     };
+    debug!("{} is verified by client_id {}.", username.as_str(), client_id.as_str());
 
-    // verify password
-    let Ok(password_verified) = password.verify(&user.encoded_hash) else {
-      return Err(BlindSignError::Argon2Failure);
+    // verify password, and the second factor of users who have enabled TOTP
+    let authentication = state
+      .authenticate_user(&username, &password, auth.totp.as_deref(), &client_id, remote_addr)
+      .await;
+    let user = match authentication {
+      Ok(UserAuthentication::Authenticated(user, _)) => user,
+      Ok(UserAuthentication::NoSuchUser) => return Err(BlindSignError::UnauthorizedUser),
+      Ok(UserAuthentication::AccountDisabled) => return Err(BlindSignError::AccountDisabled),
+      Ok(UserAuthentication::AccountLocked) => return Err(BlindSignError::AccountLocked),
+      Ok(UserAuthentication::InvalidPassword) => return Err(BlindSignError::InvalidPassword),
+      Ok(UserAuthentication::TotpRequired) => return Err(BlindSignError::TotpRequired),
+      Ok(UserAuthentication::InvalidTotp) => return Err(BlindSignError::InvalidTotp),
+      Err(_) => return Err(BlindSignError::Argon2Failure),
     };
-    if !password_verified {
-      return Err(BlindSignError::InvalidPassword);
+    if user.must_change_password {
+      return Err(BlindSignError::PasswordChangeRequired);
     }
+    if !is_permitted(&state, &user) {
+      return Err(BlindSignError::UnauthorizedUser);
+    }
+    (user.subscriber_id, Some(client_id))
   } else {
     debug!("Performing blind signing based on the authentication with id token.");
     let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
@@ -103,7 +117,8 @@ pub async fn blind_sign(
     let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
       return Err(BlindSignError::MissingToken);
     };
-    let Ok(claims) = state.crypto.verify_token(&id_token) else {
+    // id tokens issued to clients that have been disabled or deleted since are rejected as well
+    let Ok(claims) = state.verify_token(&id_token).await else {
       return Err(BlindSignError::InvalidToken);
     };
 
@@ -114,10 +129,19 @@ pub async fn blind_sign(
     let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
       return Err(BlindSignError::SignFailed);
     };
-    if opt.is_none() {
+    let Some(user) = opt else {
       return Err(BlindSignError::UnauthorizedUser);
     };
-  }
+    check_account(&user)?;
+    // id tokens issued before the password was reset
+    if user.must_change_password {
+      return Err(BlindSignError::PasswordChangeRequired);
+    }
+    if !is_permitted(&state, &user) {
+      return Err(BlindSignError::UnauthorizedUser);
+    }
+    (sub, None)
+  };
 
   let blinded_token = BlindedToken::new(&input.blinded_token_message.0, &input.blinded_token_options);
 
@@ -126,6 +150,12 @@ pub async fn blind_sign(
     return Err(BlindSignError::SignFailed);
   };
 
+  let mut event = AuditEvent::new(AuditEventKind::BlindSignatureIssued, Some(remote_addr)).actor(&actor);
+  if let Some(client_id) = &client_id {
+    event = event.client_id(client_id);
+  }
+  state.table.record_audit_event(event).await;
+
   let expires_at =
     state.blind_crypto.rotated_at.load(std::sync::atomic::Ordering::Relaxed) + state.blind_crypto.rotation_period.as_secs();
 
@@ -135,3 +165,17 @@ pub async fn blind_sign(
     message: "ok".to_string(),
   }))
 }
+
+/// Reject disabled and locked users
+fn check_account(user: &User) -> Result<(), BlindSignError> {
+  match user.account_state() {
+    AccountState::Active => Ok(()),
+    AccountState::Disabled => Err(BlindSignError::AccountDisabled),
+    AccountState::Locked => Err(BlindSignError::AccountLocked),
+  }
+}
+
+/// Whether the user can get blind signatures, which requires a role permitting it only if configured so
+fn is_permitted(state: &AppState, user: &User) -> bool {
+  !state.blind_crypto.role_required || user.roles.permits(Permission::IssueBlindSignatures)
+}
@@ -0,0 +1,175 @@
+use super::{request::ClientTokensRequest, response::ClientTokensResponse};
+use crate::{
+  constants::CLIENT_CREDENTIALS_GRANT_TYPE,
+  entity::{AuditEvent, AuditEventKind},
+  log::*,
+  state::AppState,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::{
+  token_fields::{ClientId, Field},
+  TokenBody,
+};
+
+#[derive(Debug)]
+pub enum ClientTokensError {
+  TokenCreationFailed,
+  ClientLookupFailed,
+  Argon2Failure,
+  UnsupportedGrantType,
+  InvalidClient,
+}
+impl IntoResponse for ClientTokensError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      ClientTokensError::TokenCreationFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation failed"),
+      ClientTokensError::ClientLookupFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Client lookup failed"),
+      ClientTokensError::Argon2Failure => (StatusCode::INTERNAL_SERVER_ERROR, "Something failed in authentication"),
+      ClientTokensError::UnsupportedGrantType => (StatusCode::BAD_REQUEST, "Unsupported grant type"),
+      ClientTokensError::InvalidClient => (StatusCode::UNAUTHORIZED, "Invalid client"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Issue a token to a confidential client authenticated with its client secret via the client credentials grant, for
+/// service-to-service requests without users. No refresh token is issued, and the client asks again once it expires.
+pub async fn client_tokens(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  Json(input): Json<ClientTokensRequest>,
+) -> Result<Json<ClientTokensResponse>, ClientTokensError> {
+  if input.grant_type != CLIENT_CREDENTIALS_GRANT_TYPE {
+    return Err(ClientTokensError::UnsupportedGrantType);
+  }
+
+  // only enabled confidential clients can authenticate by themselves
+  let Ok(client) = state.table.client.find(&input.client_id).await else {
+    return Err(ClientTokensError::ClientLookupFailed);
+  };
+  let Ok(client) = state
+    .authenticate_client(&input.client_id, client, &input.client_secret, remote_addr)
+    .await
+  else {
+    return Err(ClientTokensError::Argon2Failure);
+  };
+  let Some(client) = client else {
+    return Err(ClientTokensError::InvalidClient);
+  };
+  debug!("{} is verified by client secret. Issue token.", client.client_id.as_str());
+
+  let token = issue_client_token(&state, &client.client_id, remote_addr).await?;
+
+  Ok(Json(ClientTokensResponse {
+    token,
+    message: "ok".to_string(),
+  }))
+}
+
+/// Issue a token to the confidential client that has just been authenticated with its client secret
+pub(super) async fn issue_client_token(
+  state: &AppState,
+  client_id: &ClientId,
+  remote_addr: SocketAddr,
+) -> Result<TokenBody, ClientTokensError> {
+  let Ok(token) = state.crypto.generate_client_token(client_id) else {
+    return Err(ClientTokensError::TokenCreationFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::ClientCredentials, Some(remote_addr)).client_id(client_id);
+  state.table.record_audit_event(event).await;
+
+  Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{create_client, list_clients, tests::*, update_client, update_user, update_user::UpdateUserError},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD},
+  };
+  use libcommon::{SubjectKind, ValidationOptions};
+
+  fn client_credentials<T: serde::de::DeserializeOwned>(client_id: &str, client_secret: &str) -> Json<T> {
+    request(json!({ "grant_type": "client_credentials", "client_id": client_id, "client_secret": client_secret }))
+  }
+
+  #[tokio::test]
+  async fn confidential_client_gets_client_token() {
+    let state = test_state().await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let create = json!({ "client_id": "service", "confidential": true });
+    let created = create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .unwrap();
+    let secret = created.client_secret.clone().unwrap();
+    let res = list_clients(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    assert!(res.clients.iter().any(|c| c.client_id == "service" && c.confidential));
+
+    let res = client_tokens(State(state.clone()), remote_addr(), client_credentials("service", &secret))
+      .await
+      .unwrap();
+    assert!(res.token.refresh.is_none());
+    assert_eq!(res.token.subscriber_id.as_str(), "service");
+
+    // client tokens are told apart from user tokens, and never accepted by the APIs for users
+    let vo = ValidationOptions {
+      subject_kind: SubjectKind::Client,
+      ..Default::default()
+    };
+    let claims = state.crypto.signing_key.validate(&res.token.id, &vo).unwrap();
+    assert_eq!(claims.custom["cli"], true);
+    assert_eq!(claims.custom["sub"], "service");
+    assert!(state.crypto.verify_token(&res.token.id).is_err());
+    let update = json!({ "password": "new_password" });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&res.token), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::InvalidToken)));
+  }
+
+  #[tokio::test]
+  async fn client_credentials_require_enabled_confidential_client() {
+    let state = test_state().await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let create = json!({ "client_id": "service", "confidential": true });
+    let secret = create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .unwrap()
+      .client_secret
+      .clone()
+      .unwrap();
+    let create = json!({ "client_id": "public" });
+    let created = create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .unwrap();
+    assert!(created.client_secret.is_none());
+
+    let res = client_tokens(State(state.clone()), remote_addr(), client_credentials("service", "wrong_secret")).await;
+    assert!(matches!(res, Err(ClientTokensError::InvalidClient)));
+    let res = client_tokens(State(state.clone()), remote_addr(), client_credentials("public", &secret)).await;
+    assert!(matches!(res, Err(ClientTokensError::InvalidClient)));
+    let res = client_tokens(State(state.clone()), remote_addr(), client_credentials("unknown", &secret)).await;
+    assert!(matches!(res, Err(ClientTokensError::InvalidClient)));
+    let password_grant = json!({ "grant_type": "password", "client_id": "service", "client_secret": secret });
+    let res = client_tokens(State(state.clone()), remote_addr(), request(password_grant)).await;
+    assert!(matches!(res, Err(ClientTokensError::UnsupportedGrantType)));
+
+    let update = json!({ "client_id": "service", "enabled": false });
+    assert!(update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update))
+      .await
+      .is_ok());
+    let res = client_tokens(State(state.clone()), remote_addr(), client_credentials("service", &secret)).await;
+    assert!(matches!(res, Err(ClientTokensError::InvalidClient)));
+  }
+}
@@ -0,0 +1,388 @@
+use super::{
+  request::{CreateClientRequest, DeleteClientRequest, ListClientsRequest, UpdateClientRequest},
+  response::{CreateClientResponse, ListClientsResponse, ListClientsResponseInner, MessageResponse},
+};
+use crate::{
+  constants::CLIENT_SECRET_LEN,
+  entity::{
+    generate_random_string, AuditEvent, AuditEventKind, Client, EncodedHash, Entity, Password, Permission, Roles,
+    TryNewEntity,
+  },
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{ClientId, Field, IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum ClientError {
+  ClientUpdateFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchClient,
+  ClientAlreadyExists,
+  InvalidRequest,
+}
+impl IntoResponse for ClientError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      ClientError::ClientUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Client update failed"),
+      ClientError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      ClientError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      ClientError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      ClientError::NoSuchClient => (StatusCode::BAD_REQUEST, "No such client"),
+      ClientError::ClientAlreadyExists => (StatusCode::BAD_REQUEST, "Client already exists"),
+      ClientError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Register a client application allowed to get tokens under the admin privilege. Confidential clients are given a
+/// randomly generated client secret, which is returned only in the response.
+pub async fn create_client(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<CreateClientRequest>,
+) -> Result<Json<CreateClientResponse>, ClientError> {
+  let sub = authorize(&state, &headers).await?;
+
+  // client ids are deserialized without validation
+  let Ok(client_id) = ClientId::new(request.client_id.as_str()) else {
+    return Err(ClientError::InvalidRequest);
+  };
+  let Ok(found) = state.table.client.find(&client_id).await else {
+    return Err(ClientError::ClientUpdateFailed);
+  };
+  if found.is_some() {
+    return Err(ClientError::ClientAlreadyExists);
+  }
+  let mut client = Client::new(&client_id, request.display_name);
+  let client_secret = if request.confidential {
+    let Ok(secret) = generate_random_string(CLIENT_SECRET_LEN) else {
+      return Err(ClientError::ClientUpdateFailed);
+    };
+    let Ok(secret_hash) = Password::new(&secret).and_then(|s| EncodedHash::generate(&s)) else {
+      return Err(ClientError::ClientUpdateFailed);
+    };
+    client.secret_hash = Some(secret_hash);
+    Some(secret)
+  } else {
+    None
+  };
+  let Ok(_) = state.table.client.add(&client).await else {
+    return Err(ClientError::ClientUpdateFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::ClientCreated, Some(remote_addr))
+    .actor(&sub)
+    .client_id(&client_id);
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(CreateClientResponse {
+    client_secret,
+    message: "ok. registered the client.".to_string(),
+  }))
+}
+
+/// List registered client applications under the admin privilege
+pub async fn list_clients(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+  Json(request): Json<ListClientsRequest>,
+) -> Result<Json<ListClientsResponse>, ClientError> {
+  authorize(&state, &headers).await?;
+
+  let current_page = request.page.unwrap_or(1u32);
+  let Ok((clients, total_pages, total_clients)) = state.table.client.list(current_page).await else {
+    return Err(ClientError::InvalidRequest);
+  };
+
+  Ok(Json(ListClientsResponse {
+    clients: clients
+      .into_iter()
+      .map(|c| ListClientsResponseInner {
+        confidential: c.is_confidential(),
+        client_id: c.client_id.into_string(),
+        display_name: c.display_name.map(|n| n.into_string()),
+        enabled: c.enabled,
+        created_at: c.created_at.timestamp(),
+      })
+      .collect(),
+    page: current_page,
+    total_pages,
+    total_clients,
+    message: "Success".to_string(),
+  }))
+}
+
+/// Update the display name and/or enable or disable a registered client under the admin privilege.
+/// Disabled clients can neither get nor refresh tokens, while their refresh tokens are kept.
+pub async fn update_client(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<UpdateClientRequest>,
+) -> Result<Json<MessageResponse>, ClientError> {
+  let sub = authorize(&state, &headers).await?;
+
+  if request.update.is_empty() {
+    return Err(ClientError::InvalidRequest);
+  }
+  let Ok(updated) = state.table.client.update(&request.client_id, &request.update).await else {
+    return Err(ClientError::ClientUpdateFailed);
+  };
+  if !updated {
+    return Err(ClientError::NoSuchClient);
+  }
+
+  let event = AuditEvent::new(AuditEventKind::ClientUpdated, Some(remote_addr))
+    .actor(&sub)
+    .client_id(&request.client_id);
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. updated the client.".to_string(),
+  }))
+}
+
+/// Delete a registered client together with the refresh tokens issued to it under the admin privilege
+pub async fn delete_client(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<DeleteClientRequest>,
+) -> Result<Json<MessageResponse>, ClientError> {
+  let sub = authorize(&state, &headers).await?;
+
+  let Ok(deleted) = state.table.client.delete(&request.client_id).await else {
+    return Err(ClientError::ClientUpdateFailed);
+  };
+  if !deleted {
+    return Err(ClientError::NoSuchClient);
+  }
+
+  let event = AuditEvent::new(AuditEventKind::ClientDeleted, Some(remote_addr))
+    .actor(&sub)
+    .client_id(&request.client_id);
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. deleted the client.".to_string(),
+  }))
+}
+
+/// Verify the bearer token of a user whose roles permit to manage clients, and return the subscriber id
+async fn authorize(state: &AppState, headers: &HeaderMap) -> Result<SubscriberId, ClientError> {
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(ClientError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(ClientError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(ClientError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(ClientError::InvalidToken);
+  };
+
+  // roles in the token must permit to manage clients
+  if !Roles::from_claims(&claims).permits(Permission::ManageClients) {
+    return Err(ClientError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(ClientError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(ClientError::ClientUpdateFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(ClientError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageClients) {
+    return Err(ClientError::InvalidToken);
+  }
+  Ok(sub)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{get_tokens, get_tokens::GetTokensError, refresh, refresh::RefreshError, tests::*},
+    state::tests::{test_state, test_state_without_client_ids, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+
+  fn login_request<T: serde::de::DeserializeOwned>(client_id: &str) -> Json<T> {
+    request(json!({ "auth": { "username": "user", "password": "user_password" }, "client_id": client_id }))
+  }
+
+  #[tokio::test]
+  async fn client_changes_apply_without_restart() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    // unregistered clients are rejected until registered by admins
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request("mobile")).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+    let create = json!({ "client_id": "mobile", "display_name": "Mobile App" });
+    let res = create_client(State(state.clone()), remote_addr(), bearer(&user), request(create.clone())).await;
+    assert!(matches!(res, Err(ClientError::UnauthorizedUser)));
+    assert!(create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create.clone()))
+      .await
+      .is_ok());
+    let res = create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create)).await;
+    assert!(matches!(res, Err(ClientError::ClientAlreadyExists)));
+    let mobile = get_tokens(State(state.clone()), remote_addr(), login_request("mobile"))
+      .await
+      .unwrap()
+      .token
+      .clone();
+
+    let res = list_clients(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    assert_eq!(res.total_clients, 2);
+    let listed = res.clients.iter().find(|c| c.client_id == "mobile").unwrap();
+    assert_eq!(listed.display_name.as_deref(), Some("Mobile App"));
+    assert!(listed.enabled);
+
+    // disabled clients can neither log in nor refresh, but can once enabled again
+    let refresh_request = json!({ "refresh_token": mobile.refresh.as_ref().unwrap().as_str(), "client_id": "mobile" });
+    let update = json!({ "client_id": "mobile", "enabled": false });
+    assert!(update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update))
+      .await
+      .is_ok());
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request("mobile")).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+    let res = refresh(State(state.clone()), remote_addr(), request(refresh_request.clone())).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedClientApp)));
+    let update = json!({ "client_id": "mobile", "enabled": true, "display_name": null });
+    assert!(update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update))
+      .await
+      .is_ok());
+    let refreshed = refresh(State(state.clone()), remote_addr(), request(refresh_request))
+      .await
+      .unwrap()
+      .token
+      .clone();
+    let res = list_clients(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    assert!(res.clients.iter().any(|c| c.client_id == "mobile" && c.display_name.is_none()));
+
+    // deleting the client revokes its refresh tokens, which stay revoked even if it is registered again
+    let delete = json!({ "client_id": "mobile" });
+    assert!(delete_client(State(state.clone()), remote_addr(), bearer(&admin), request(delete.clone()))
+      .await
+      .is_ok());
+    let res = delete_client(State(state.clone()), remote_addr(), bearer(&admin), request(delete)).await;
+    assert!(matches!(res, Err(ClientError::NoSuchClient)));
+    let create = json!({ "client_id": "mobile" });
+    assert!(create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .is_ok());
+    let refresh_request = json!({ "refresh_token": refreshed.refresh.as_ref().unwrap().as_str(), "client_id": "mobile" });
+    let res = refresh(State(state.clone()), remote_addr(), request(refresh_request)).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+
+    // the other client is unaffected
+    assert!(get_tokens(State(state.clone()), remote_addr(), login_request(TEST_CLIENT_ID))
+      .await
+      .is_ok());
+  }
+
+  #[tokio::test]
+  async fn tokens_of_removed_clients_are_rejected() {
+    let state = test_state().await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let create = json!({ "client_id": "console" });
+    assert!(create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .is_ok());
+    let admin_login = json!({ "auth": { "username": "admin", "password": TEST_ADMIN_PASSWORD }, "client_id": "console" });
+    let console = get_tokens(State(state.clone()), remote_addr(), request(admin_login))
+      .await
+      .unwrap()
+      .token
+      .clone();
+    assert!(list_clients(State(state.clone()), bearer(&console), request(json!({}))).await.is_ok());
+
+    // id tokens issued to the client are rejected once it is disabled, even before they expire
+    let update = json!({ "client_id": "console", "enabled": false });
+    assert!(update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update))
+      .await
+      .is_ok());
+    let res = list_clients(State(state.clone()), bearer(&console), request(json!({}))).await;
+    assert!(matches!(res, Err(ClientError::InvalidToken)));
+
+    // deleting every client never allows any client
+    for client_id in ["console", TEST_CLIENT_ID] {
+      let delete = json!({ "client_id": client_id });
+      assert!(delete_client(State(state.clone()), remote_addr(), bearer(&admin), request(delete))
+        .await
+        .is_ok());
+    }
+    let res = list_clients(State(state.clone()), bearer(&admin), request(json!({}))).await;
+    assert!(matches!(res, Err(ClientError::InvalidToken)));
+    let admin_login = json!({ "auth": { "username": "admin", "password": TEST_ADMIN_PASSWORD }, "client_id": "other" });
+    let res = get_tokens(State(state.clone()), remote_addr(), request(admin_login)).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+  }
+
+  #[tokio::test]
+  async fn registered_clients_are_enforced_without_client_ids() {
+    let state = test_state_without_client_ids().await;
+    add_user(&state, "user", "user_password").await;
+
+    // any client is allowed while no client is registered
+    let admin_login = json!({ "auth": { "username": "admin", "password": TEST_ADMIN_PASSWORD } });
+    let admin = get_tokens(State(state.clone()), remote_addr(), request(admin_login))
+      .await
+      .unwrap()
+      .token
+      .clone();
+    assert!(get_tokens(State(state.clone()), remote_addr(), login_request("mobile")).await.is_ok());
+
+    // registering a client enforces the registry, where disabled clients are refused
+    let create = json!({ "client_id": "mobile" });
+    assert!(create_client(State(state.clone()), remote_addr(), bearer(&admin), request(create))
+      .await
+      .is_ok());
+    assert!(get_tokens(State(state.clone()), remote_addr(), login_request("mobile")).await.is_ok());
+    // tokens issued to the default client id are no longer accepted
+    let update = json!({ "client_id": "mobile", "enabled": false });
+    let res = update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update.clone())).await;
+    assert!(matches!(res, Err(ClientError::InvalidToken)));
+    let admin_login = json!({ "auth": { "username": "admin", "password": TEST_ADMIN_PASSWORD }, "client_id": "mobile" });
+    let admin = get_tokens(State(state.clone()), remote_addr(), request(admin_login))
+      .await
+      .unwrap()
+      .token
+      .clone();
+    assert!(update_client(State(state.clone()), remote_addr(), bearer(&admin), request(update))
+      .await
+      .is_ok());
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request("mobile")).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request("other")).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+  }
+}
@@ -2,16 +2,16 @@ use super::{request::CreateUserRequest, response::MessageResponse};
 use crate::{
   entity::*,
   state::AppState,
-  table::{UserSearchKey, UserTable},
+  table::UserSearchKey,
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::{HeaderMap, StatusCode},
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
 
@@ -21,24 +21,35 @@ pub enum CreateUserError {
   UnauthorizedUser,
   MissingToken,
   InvalidToken,
+  WeakPassword(Vec<PasswordPolicyViolation>),
+  BreachedPassword,
 }
 impl IntoResponse for CreateUserError {
   fn into_response(self) -> Response {
-    let (status, error_message) = match self {
+    let (status, error_message) = match &self {
       CreateUserError::UserCreationFailed => (StatusCode::INTERNAL_SERVER_ERROR, "User creation failed"),
       CreateUserError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       CreateUserError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
       CreateUserError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      CreateUserError::WeakPassword(_) => (StatusCode::BAD_REQUEST, "Password policy violation"),
+      CreateUserError::BreachedPassword => (StatusCode::BAD_REQUEST, "Password found in breached password list"),
+    };
+    let body = match self {
+      CreateUserError::WeakPassword(violations) => Json(json!({
+          "error": error_message,
+          "details": violations,
+      })),
+      _ => Json(json!({
+          "error": error_message,
+      })),
     };
-    let body = Json(json!({
-        "error": error_message,
-    }));
     (status, body).into_response()
   }
 }
 
 pub async fn create_user(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   headers: HeaderMap,
   Json(request): Json<CreateUserRequest>,
 ) -> Result<Json<MessageResponse>, CreateUserError> {
@@ -55,12 +66,12 @@ pub async fn create_user(
   let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
     return Err(CreateUserError::MissingToken);
   };
-  let Ok(claims) = state.crypto.verify_token(&id_token) else {
+  let Ok(claims) = state.verify_token(&id_token).await else {
     return Err(CreateUserError::InvalidToken);
   };
 
-  // is_admin must be true here
-  if !claims.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false) {
+  // roles in the token must permit to create users
+  if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
     return Err(CreateUserError::UnauthorizedUser);
   }
 
@@ -74,18 +85,35 @@ pub async fn create_user(
   let Some(u) = opt else {
     return Err(CreateUserError::UnauthorizedUser);
   };
-  if !u.is_admin() {
+  if !u.roles.permits(Permission::ManageUsers) {
     return Err(CreateUserError::InvalidToken);
   }
 
+  if let Err(violations) = state.password_policy.check(&request.auth.username, &request.auth.password) {
+    return Err(CreateUserError::WeakPassword(violations));
+  }
+  if state.breached_passwords.as_ref().is_some_and(|list| list.contains(&request.auth.password)) {
+    return Err(CreateUserError::BreachedPassword);
+  }
+
   // add if new user doesn't exist
-  let Ok(new_user) = User::new(&request.auth.username, Some(request.auth.password)) else {
+  let Ok(mut new_user) = User::new(&request.auth.username, Some(request.auth.password)) else {
     return Err(CreateUserError::UserCreationFailed);
   };
+  new_user.profile = UserProfile {
+    email: request.email,
+    display_name: request.display_name,
+    attributes: request.attributes.unwrap_or_default(),
+  };
   let Ok(_) = state.table.user.add(new_user).await else {
     return Err(CreateUserError::UserCreationFailed);
   };
 
+  let event = AuditEvent::new(AuditEventKind::UserCreated, Some(remote_addr))
+    .actor(&sub)
+    .target(request.auth.username.as_str());
+  state.table.record_audit_event(event).await;
+
   Ok(Json(MessageResponse {
     message: "ok. created the user.".to_string(),
   }))
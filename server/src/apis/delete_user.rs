@@ -1,17 +1,18 @@
 use super::{request::DeleteUserRequest, response::MessageResponse};
 use crate::{
+  entity::{AuditEvent, AuditEventKind, Permission, Roles},
   state::AppState,
-  table::{UserSearchKey, UserTable},
+  table::UserSearchKey,
   ADMIN_USERNAME,
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::{HeaderMap, StatusCode},
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
 
@@ -23,6 +24,7 @@ pub enum DeleteUserError {
   InvalidToken,
   NoSuchUser,
   DeleteProhibitedUser,
+  LastAdmin,
 }
 impl IntoResponse for DeleteUserError {
   fn into_response(self) -> Response {
@@ -33,6 +35,7 @@ impl IntoResponse for DeleteUserError {
       DeleteUserError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
       DeleteUserError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
       DeleteUserError::DeleteProhibitedUser => (StatusCode::BAD_REQUEST, "Delete prohibited user"),
+      DeleteUserError::LastAdmin => (StatusCode::BAD_REQUEST, "The last admin cannot be deleted"),
     };
     let body = Json(json!({
         "error": error_message,
@@ -43,6 +46,7 @@ impl IntoResponse for DeleteUserError {
 
 pub async fn delete_user(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   headers: HeaderMap,
   Json(request): Json<DeleteUserRequest>,
 ) -> Result<Json<MessageResponse>, DeleteUserError> {
@@ -59,12 +63,12 @@ pub async fn delete_user(
   let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
     return Err(DeleteUserError::MissingToken);
   };
-  let Ok(claims) = state.crypto.verify_token(&id_token) else {
+  let Ok(claims) = state.verify_token(&id_token).await else {
     return Err(DeleteUserError::InvalidToken);
   };
 
-  // is_admin must be true here
-  if !claims.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false) {
+  // roles in the token must permit to delete users
+  if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
     return Err(DeleteUserError::UnauthorizedUser);
   }
 
@@ -78,7 +82,7 @@ pub async fn delete_user(
   let Some(request_user) = opt else {
     return Err(DeleteUserError::UnauthorizedUser);
   };
-  if !request_user.is_admin() {
+  if !request_user.roles.permits(Permission::ManageUsers) {
     return Err(DeleteUserError::InvalidToken);
   }
 
@@ -94,9 +98,23 @@ pub async fn delete_user(
   if request_user.username() == target_user.username() || target_user.username() == ADMIN_USERNAME {
     return Err(DeleteUserError::DeleteProhibitedUser);
   }
-  let Ok(_) = state.table.user.delete_user(UserSearchKey::Username(target_username)).await else {
+  // users of the admin role are managed only by admins
+  if target_user.is_admin() && !request_user.is_admin() {
+    return Err(DeleteUserError::DeleteProhibitedUser);
+  }
+  let Ok(deleted) = state.table.user.delete_user(UserSearchKey::Username(target_username)).await else {
     return Err(DeleteUserError::UserDeletionFailed);
   };
+  // at least one admin must remain, which is checked atomically with the deletion against concurrent deletions
+  if !deleted {
+    return Err(DeleteUserError::LastAdmin);
+  }
+
+  let event = AuditEvent::new(AuditEventKind::UserDeleted, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
   Ok(Json(MessageResponse {
     message: "ok. deleted the user.".to_string(),
   }))
@@ -1,21 +1,19 @@
 use super::{request::TokensRequest, response::TokensResponse};
 use crate::{
-  constants::DEFAUTL_CLIENT_ID,
-  entity::{Entity, RefreshTokenInfo},
+  entity::{AuditEvent, AuditEventKind, Entity, RefreshTokenInfo, TokenFamilyId},
   log::*,
-  state::AppState,
-  table::{UserSearchKey, UserTable},
+  state::{AppState, ClientAuthorization, UserAuthentication},
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::StatusCode,
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use libcommon::token_fields::{ClientId, Field, TryNewField};
+use libcommon::token_fields::Field;
 
 #[derive(Debug)]
 pub enum GetTokensError {
@@ -25,6 +23,10 @@ pub enum GetTokensError {
   UnauthorizedClientApp,
   UnauthorizedUser,
   InvalidRequest,
+  AccountDisabled,
+  AccountLocked,
+  TotpRequired,
+  InvalidTotp,
 }
 impl IntoResponse for GetTokensError {
   fn into_response(self) -> Response {
@@ -35,6 +37,10 @@ impl IntoResponse for GetTokensError {
       GetTokensError::UnauthorizedClientApp => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       GetTokensError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       GetTokensError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+      GetTokensError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+      GetTokensError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
+      GetTokensError::TotpRequired => (StatusCode::UNAUTHORIZED, "TOTP code required"),
+      GetTokensError::InvalidTotp => (StatusCode::UNAUTHORIZED, "Invalid TOTP code"),
     };
     let body = Json(json!({
         "error": error_message,
@@ -45,63 +51,186 @@ impl IntoResponse for GetTokensError {
 
 pub async fn get_tokens(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   Json(input): Json<TokensRequest>,
 ) -> Result<Json<TokensResponse>, GetTokensError> {
   // Getusername and password form
   let (username, password) = (input.auth.username, input.auth.password);
 
-  // check user existence
-  let Ok(user) = state.table.user.find_user(UserSearchKey::Username(&username)).await else {
-    return Err(GetTokensError::TokenCreationFailed);
-  };
-  let Some(user) = user else {
-    return Err(GetTokensError::UnauthorizedUser);
-  };
-  // check allowed client ids if audience is some
-  let client_id = if state.crypto.audiences.is_some() {
-    let Some(client_id) = input.client_id else {
-      return Err(GetTokensError::InvalidRequest);
-    };
-    if !state.crypto.audiences.as_ref().unwrap().contains(&client_id) {
-      return Err(GetTokensError::UnauthorizedClientApp);
-    }
-    debug!("{} is verified by client_id {}.", username.as_str(), client_id.as_str());
-    client_id
-  } else {
-    let Ok(cid) = ClientId::new(DEFAUTL_CLIENT_ID) else {
-      return Err(GetTokensError::TokenCreationFailed);
-    };
-    cid
+  // check the client id against the client registry
+  let client_id = match state.authorize_client(input.client_id).await {
+    Ok(ClientAuthorization::Allowed(client_id)) => client_id,
+    Ok(ClientAuthorization::Missing) => return Err(GetTokensError::InvalidRequest),
+    Ok(ClientAuthorization::Unauthorized) => return Err(GetTokensError::UnauthorizedClientApp),
+    Err(_) => return Err(GetTokensError::TokenCreationFailed),
   };
+  debug!("{} is verified by client_id {}.", username.as_str(), client_id.as_str());
 
-  // verify password
-  let Ok(password_verified) = password.verify(&user.encoded_hash) else {
-    return Err(GetTokensError::Argon2Failure);
+  // verify password, and the second factor of users who have enabled TOTP
+  let authentication = state
+    .authenticate_user(&username, &password, input.auth.totp.as_deref(), &client_id, remote_addr)
+    .await;
+  let (user, amr) = match authentication {
+    Ok(UserAuthentication::Authenticated(user, amr)) => (user, amr),
+    Ok(UserAuthentication::NoSuchUser) => return Err(GetTokensError::UnauthorizedUser),
+    Ok(UserAuthentication::AccountDisabled) => return Err(GetTokensError::AccountDisabled),
+    Ok(UserAuthentication::AccountLocked) => return Err(GetTokensError::AccountLocked),
+    Ok(UserAuthentication::InvalidPassword) => return Err(GetTokensError::InvalidPassword),
+    Ok(UserAuthentication::TotpRequired) => return Err(GetTokensError::TotpRequired),
+    Ok(UserAuthentication::InvalidTotp) => return Err(GetTokensError::InvalidTotp),
+    Err(_) => return Err(GetTokensError::Argon2Failure),
   };
-  if !password_verified {
-    return Err(GetTokensError::InvalidPassword);
-  }
 
   debug!("{} is verified by password. Issue id_token.", username.as_str());
 
+  // users whose password has been reset only get an id token restricted to changing the password, without refresh token
+  let refresh_required = !user.must_change_password;
+
   // generate id_token with refresh token
-  let Ok(token) = state.crypto.generate_token(&user, &client_id, true) else {
+  let Ok(token) = state.crypto.generate_token(&user, &client_id, &amr, refresh_required) else {
     return Err(GetTokensError::TokenCreationFailed);
   };
 
   // Record refresh token to db
-  let Ok(refresh) = RefreshTokenInfo::try_from(&token.body) else {
-    error!("Failed to retrieve refresh token from token struct");
-    return Err(GetTokensError::TokenCreationFailed);
-  };
-  if state.table.refresh_token.add_and_prune(&refresh).await.is_err() {
-    error!("Failed to store refresh token");
-    return Err(GetTokensError::TokenCreationFailed);
-  };
+  if refresh_required {
+    let Ok(refresh) = RefreshTokenInfo::try_new(
+      &token.body,
+      &state.crypto.refresh_token_secret,
+      TokenFamilyId::generate(),
+      amr,
+      state.crypto.lifetimes.refresh_token(&client_id),
+    ) else {
+      error!("Failed to retrieve refresh token from token struct");
+      return Err(GetTokensError::TokenCreationFailed);
+    };
+    if state.table.refresh_token.add(&refresh).await.is_err() {
+      error!("Failed to store refresh token");
+      return Err(GetTokensError::TokenCreationFailed);
+    };
+  }
+
+  let event = AuditEvent::new(AuditEventKind::Login, Some(remote_addr))
+    .actor(&user.subscriber_id)
+    .target(username.as_str())
+    .client_id(&client_id);
+  state.table.record_audit_event(event).await;
 
+  let message = if refresh_required {
+    "ok. login."
+  } else {
+    "ok. login. password change required."
+  };
   Ok(Json(TokensResponse {
     token: token.body,
     metadata: token.meta,
-    message: "ok. login.".to_string(),
+    message: message.to_string(),
   }))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{tests::*, update_user_status},
+    constants::{ARGON2_CONFIG, ARGON2_SALT_LEN},
+    entity::{EncodedHash, Password, TryNewEntity, User, Username},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+    table::UserSearchKey,
+  };
+
+  fn request(username: &str, password: &str, client_id: &str) -> Json<TokensRequest> {
+    Json(
+      serde_json::from_value(json!({
+        "auth": { "username": username, "password": password },
+        "client_id": client_id,
+      }))
+      .unwrap(),
+    )
+  }
+
+  #[tokio::test]
+  async fn get_tokens_issues_id_and_refresh_tokens() {
+    let state = test_state().await;
+    let res = get_tokens(State(state), remote_addr(), request("admin", TEST_ADMIN_PASSWORD, TEST_CLIENT_ID))
+      .await
+      .unwrap();
+    assert!(res.token.refresh.is_some());
+    assert_eq!(res.metadata.username, "admin");
+    assert!(res.metadata.is_admin);
+  }
+
+  #[tokio::test]
+  async fn get_tokens_rejects_invalid_credentials() {
+    let state = test_state().await;
+    let res = get_tokens(State(state.clone()), remote_addr(), request("admin", "wrong_password", TEST_CLIENT_ID)).await;
+    assert!(matches!(res, Err(GetTokensError::InvalidPassword)));
+
+    let res = get_tokens(State(state.clone()), remote_addr(), request("nobody", TEST_ADMIN_PASSWORD, TEST_CLIENT_ID)).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedUser)));
+
+    let res = get_tokens(State(state), remote_addr(), request("admin", TEST_ADMIN_PASSWORD, "unknown_client")).await;
+    assert!(matches!(res, Err(GetTokensError::UnauthorizedClientApp)));
+  }
+
+  #[tokio::test]
+  async fn repeated_failed_logins_lock_account() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let username = Username::new("user").unwrap();
+    let failed_logins = |state: Arc<AppState>| {
+      let username = username.clone();
+      async move {
+        let user = state.table.user.find_user(UserSearchKey::Username(&username)).await.unwrap();
+        user.unwrap().failed_logins
+      }
+    };
+
+    // a successful login resets the count
+    for _ in 0..2 {
+      let res = get_tokens(State(state.clone()), remote_addr(), request("user", "wrong_password", TEST_CLIENT_ID)).await;
+      assert!(matches!(res, Err(GetTokensError::InvalidPassword)));
+    }
+    assert_eq!(failed_logins(state.clone()).await, 2);
+    assert!(get_tokens(State(state.clone()), remote_addr(), request("user", "user_password", TEST_CLIENT_ID)).await.is_ok());
+    assert_eq!(failed_logins(state.clone()).await, 0);
+
+    // locked at the threshold even with the right password
+    for _ in 1..state.lockout.threshold {
+      let res = get_tokens(State(state.clone()), remote_addr(), request("user", "wrong_password", TEST_CLIENT_ID)).await;
+      assert!(matches!(res, Err(GetTokensError::InvalidPassword)));
+    }
+    let res = get_tokens(State(state.clone()), remote_addr(), request("user", "wrong_password", TEST_CLIENT_ID)).await;
+    assert!(matches!(res, Err(GetTokensError::AccountLocked)));
+    let res = get_tokens(State(state.clone()), remote_addr(), request("user", "user_password", TEST_CLIENT_ID)).await;
+    assert!(matches!(res, Err(GetTokensError::AccountLocked)));
+
+    // admins can unlock the user
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let update = Json(serde_json::from_value(json!({ "username": "user", "locked_until": null })).unwrap());
+    assert!(update_user_status(State(state.clone()), remote_addr(), bearer(&admin), update).await.is_ok());
+    assert_eq!(failed_logins(state.clone()).await, 0);
+    assert!(get_tokens(State(state), remote_addr(), request("user", "user_password", TEST_CLIENT_ID)).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn outdated_password_hash_is_replaced_on_login() {
+    let state = test_state().await;
+    let username = Username::new("user").unwrap();
+    let mut user = User::new(&username, Some(Password::new("user_password").unwrap())).unwrap();
+    let weaker = argon2::Config {
+      mem_cost: 1024,
+      time_cost: 1,
+      lanes: 1,
+      ..ARGON2_CONFIG
+    };
+    let weak_hash = argon2::hash_encoded(b"user_password", &[0u8; ARGON2_SALT_LEN], &weaker).unwrap();
+    user.encoded_hash = EncodedHash::new(weak_hash.as_str()).unwrap();
+    state.table.user.add(user).await.unwrap();
+
+    assert!(get_tokens(State(state.clone()), remote_addr(), request("user", "user_password", TEST_CLIENT_ID)).await.is_ok());
+    let user = state.table.user.find_user(UserSearchKey::Username(&username)).await.unwrap().unwrap();
+    assert_ne!(user.encoded_hash.as_str(), weak_hash);
+    assert!(!user.encoded_hash.needs_rehash());
+    assert!(get_tokens(State(state), remote_addr(), request("user", "user_password", TEST_CLIENT_ID)).await.is_ok());
+  }
+}
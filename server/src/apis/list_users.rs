@@ -3,9 +3,9 @@ use super::{
   response::{ListUserResponse, ListUserResponseInner},
 };
 use crate::{
-  entity::Entity,
+  entity::{Entity, Permission, Role, Roles},
   state::AppState,
-  table::{UserSearchKey, UserTable},
+  table::UserSearchKey,
 };
 use axum::{
   extract::State,
@@ -58,12 +58,12 @@ pub async fn list_users(
   let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
     return Err(ListUserError::MissingToken);
   };
-  let Ok(claims) = state.crypto.verify_token(&id_token) else {
+  let Ok(claims) = state.verify_token(&id_token).await else {
     return Err(ListUserError::InvalidToken);
   };
 
-  // is_admin must be true here
-  if !claims.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false) {
+  // roles in the token must permit to list users
+  if !Roles::from_claims(&claims).permits(Permission::ListUsers) {
     return Err(ListUserError::UnauthorizedUser);
   }
 
@@ -77,7 +77,7 @@ pub async fn list_users(
   let Some(request_user) = opt else {
     return Err(ListUserError::UnauthorizedUser);
   };
-  if !request_user.is_admin() {
+  if !request_user.roles.permits(Permission::ListUsers) {
     return Err(ListUserError::InvalidToken);
   }
 
@@ -93,7 +93,15 @@ pub async fn list_users(
       .map(|u| ListUserResponseInner {
         username: u.username.into_string(),
         subscriber_id: u.subscriber_id.into_string(),
-        is_admin: u.is_admin.get(),
+        is_admin: u.roles.contains(Role::Admin),
+        roles: u.roles.to_strings(),
+        email: u.profile.email.map(|e| e.into_string()),
+        display_name: u.profile.display_name.map(|n| n.into_string()),
+        attributes: u.profile.attributes.into_inner(),
+        enabled: u.enabled,
+        locked_until: u.locked_until.map(|t| t.timestamp()),
+        failed_logins: u.failed_logins,
+        must_change_password: u.must_change_password,
       })
       .collect(),
     page: current_page,
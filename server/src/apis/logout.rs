@@ -0,0 +1,165 @@
+use super::{request::LogoutRequest, response::MessageResponse};
+use crate::{
+  log::*,
+  state::{AppState, ClientAuthorization},
+};
+use axum::{
+  extract::State,
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use libcommon::token_fields::{Field, IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum LogoutError {
+  RevocationFailed,
+  UnauthorizedClientApp,
+  MissingToken,
+  InvalidToken,
+  InvalidRequest,
+}
+impl IntoResponse for LogoutError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      LogoutError::RevocationFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Token revocation failed"),
+      LogoutError::UnauthorizedClientApp => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      LogoutError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      LogoutError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      LogoutError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Revoke the presented refresh token together with the ones rotated from the same login.
+/// Unknown or expired refresh tokens are accepted as already logged out.
+pub async fn logout(
+  State(state): State<Arc<AppState>>,
+  Json(input): Json<LogoutRequest>,
+) -> Result<Json<MessageResponse>, LogoutError> {
+  // check the client id against the client registry
+  let client_id = match state.authorize_client(input.client_id).await {
+    Ok(ClientAuthorization::Allowed(client_id)) => client_id,
+    Ok(ClientAuthorization::Missing) => return Err(LogoutError::InvalidRequest),
+    Ok(ClientAuthorization::Unauthorized) => return Err(LogoutError::UnauthorizedClientApp),
+    Err(_) => return Err(LogoutError::RevocationFailed),
+  };
+
+  let Ok(refresh_token_hash) = state.crypto.refresh_token_secret.hash(&input.refresh_token) else {
+    return Err(LogoutError::RevocationFailed);
+  };
+  let Ok(entry_opt) = state
+    .table
+    .refresh_token
+    .find_refresh_token(&refresh_token_hash, &client_id)
+    .await
+  else {
+    return Err(LogoutError::RevocationFailed);
+  };
+  if let Some(entry) = entry_opt {
+    if state.table.refresh_token.revoke_family(&entry.family_id).await.is_err() {
+      error!("Failed to revoke refresh token family");
+      return Err(LogoutError::RevocationFailed);
+    }
+    debug!("Logged out: subscriber_id = {}", entry.subscriber_id.as_str());
+  }
+
+  Ok(Json(MessageResponse {
+    message: "ok. logged out.".to_string(),
+  }))
+}
+
+/// Revoke all refresh tokens of the user identified by the bearer id token
+pub async fn logout_all(
+  State(state): State<Arc<AppState>>,
+  headers: HeaderMap,
+) -> Result<Json<MessageResponse>, LogoutError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(LogoutError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(LogoutError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(LogoutError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(LogoutError::InvalidToken);
+  };
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(LogoutError::InvalidToken);
+  };
+
+  if state.table.refresh_token.revoke_subscriber(&sub).await.is_err() {
+    error!("Failed to revoke refresh tokens");
+    return Err(LogoutError::RevocationFailed);
+  }
+  debug!("Logged out from all sessions: subscriber_id = {}", sub.as_str());
+
+  Ok(Json(MessageResponse {
+    message: "ok. logged out from all sessions.".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{refresh, tests::*},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use libcommon::token_fields::RefreshToken;
+
+  fn request(refresh_token: &RefreshToken) -> Json<LogoutRequest> {
+    let request = json!({ "refresh_token": refresh_token.as_str(), "client_id": TEST_CLIENT_ID });
+    Json(serde_json::from_value(request).unwrap())
+  }
+
+  async fn can_refresh(state: &Arc<AppState>, refresh_token: &RefreshToken) -> bool {
+    let request = json!({ "refresh_token": refresh_token.as_str(), "client_id": TEST_CLIENT_ID });
+    refresh(State(state.clone()), remote_addr(), Json(serde_json::from_value(request).unwrap())).await.is_ok()
+  }
+
+  #[tokio::test]
+  async fn logout_revokes_presented_refresh_token() {
+    let state = test_state().await;
+    let session = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token.refresh.unwrap();
+    let other_session = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token.refresh.unwrap();
+
+    assert!(logout(State(state.clone()), request(&session)).await.is_ok());
+    assert!(!can_refresh(&state, &session).await);
+    assert!(can_refresh(&state, &other_session).await);
+
+    // logging out again or with an unknown token is not an error
+    assert!(logout(State(state.clone()), request(&session)).await.is_ok());
+    assert!(logout(State(state), request(&RefreshToken::generate().unwrap())).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn logout_all_revokes_every_session_of_caller() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let first = login(&state, "user", "user_password").await.token;
+    let second = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    let res = logout_all(State(state.clone()), HeaderMap::new()).await;
+    assert!(matches!(res, Err(LogoutError::MissingToken)));
+
+    assert!(logout_all(State(state.clone()), bearer(&first)).await.is_ok());
+    assert!(!can_refresh(&state, first.refresh.as_ref().unwrap()).await);
+    assert!(!can_refresh(&state, second.refresh.as_ref().unwrap()).await);
+    assert!(can_refresh(&state, admin.refresh.as_ref().unwrap()).await);
+  }
+}
@@ -0,0 +1,156 @@
+use super::{request::LogoutUserRequest, response::MessageResponse};
+use crate::{
+  entity::{AuditEvent, AuditEventKind, Permission, Roles},
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum LogoutUserError {
+  RevocationFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchUser,
+}
+impl IntoResponse for LogoutUserError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      LogoutUserError::RevocationFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Token revocation failed"),
+      LogoutUserError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      LogoutUserError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      LogoutUserError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      LogoutUserError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Revoke all refresh tokens of the given user under the admin privilege
+pub async fn logout_user(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<LogoutUserRequest>,
+) -> Result<Json<MessageResponse>, LogoutUserError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(LogoutUserError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(LogoutUserError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(LogoutUserError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(LogoutUserError::InvalidToken);
+  };
+
+  // roles in the token must permit to log out users
+  if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
+    return Err(LogoutUserError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(LogoutUserError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(LogoutUserError::RevocationFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(LogoutUserError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageUsers) {
+    return Err(LogoutUserError::InvalidToken);
+  }
+
+  // check if the user exist
+  let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&request.username)).await else {
+    return Err(LogoutUserError::RevocationFailed);
+  };
+  let Some(target_user) = u else {
+    return Err(LogoutUserError::NoSuchUser);
+  };
+  // users of the admin role are managed only by admins
+  if target_user.is_admin() && !request_user.is_admin() {
+    return Err(LogoutUserError::UnauthorizedUser);
+  }
+  let Ok(_) = state.table.refresh_token.revoke_subscriber(&target_user.subscriber_id).await else {
+    return Err(LogoutUserError::RevocationFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::UserLoggedOut, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. revoked all sessions of the user.".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{refresh, tests::*},
+    entity::AuditEventFilter,
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use libcommon::token_fields::{Field, RefreshToken};
+
+  fn request(username: &str) -> Json<LogoutUserRequest> {
+    Json(serde_json::from_value(json!({ "username": username })).unwrap())
+  }
+
+  async fn can_refresh(state: &Arc<AppState>, refresh_token: &RefreshToken) -> bool {
+    let request = json!({ "refresh_token": refresh_token.as_str(), "client_id": TEST_CLIENT_ID });
+    refresh(State(state.clone()), remote_addr(), Json(serde_json::from_value(request).unwrap())).await.is_ok()
+  }
+
+  #[tokio::test]
+  async fn admin_revokes_sessions_of_user() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    // only admin can call it
+    let res = logout_user(State(state.clone()), remote_addr(), bearer(&user), request("admin")).await;
+    assert!(matches!(res, Err(LogoutUserError::UnauthorizedUser)));
+    let res = logout_user(State(state.clone()), remote_addr(), bearer(&admin), request("nobody")).await;
+    assert!(matches!(res, Err(LogoutUserError::NoSuchUser)));
+
+    assert!(logout_user(State(state.clone()), remote_addr(), bearer(&admin), request("user")).await.is_ok());
+    assert!(!can_refresh(&state, user.refresh.as_ref().unwrap()).await);
+    assert!(can_refresh(&state, admin.refresh.as_ref().unwrap()).await);
+
+    // the revocation is recorded with the admin who did it
+    let filter = AuditEventFilter {
+      kind: Some(AuditEventKind::UserLoggedOut),
+      ..Default::default()
+    };
+    let (events, _, _) = state.table.audit_log.query(&filter, 1).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].actor.as_ref(), Some(&admin.subscriber_id));
+    assert_eq!(events[0].target.as_deref(), Some("user"));
+  }
+}
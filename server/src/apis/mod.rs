@@ -3,27 +3,102 @@ mod blind_jwks;
 #[cfg(feature = "blind-signatures")]
 mod blind_sign;
 
+mod admin_role;
+mod audit_events;
+mod client_tokens;
+mod clients;
 mod create_user;
 mod delete_user;
 mod get_tokens;
 mod health_check;
 mod jwks;
 mod list_users;
+mod logout;
+mod logout_user;
+mod oauth2_token;
 mod refresh;
 mod request;
+mod reset_password;
 mod response;
+mod totp;
+mod update_roles;
 mod update_user;
+mod update_user_status;
 
 #[cfg(feature = "blind-signatures")]
 pub use blind_jwks::blind_jwks;
 #[cfg(feature = "blind-signatures")]
 pub use blind_sign::blind_sign;
 
+pub use admin_role::{grant_admin, revoke_admin};
+pub use audit_events::audit_events;
+pub use client_tokens::client_tokens;
+pub use clients::{create_client, delete_client, list_clients, update_client};
 pub use create_user::create_user;
 pub use delete_user::delete_user;
 pub use get_tokens::get_tokens;
 pub use health_check::health_check;
 pub use jwks::jwks;
 pub use list_users::list_users;
+pub use logout::{logout, logout_all};
+pub use logout_user::logout_user;
+pub use oauth2_token::oauth2_token;
 pub use refresh::refresh;
+pub use reset_password::reset_password;
+pub use totp::{activate_totp, disable_totp, enroll_totp};
+pub use update_roles::update_roles;
 pub use update_user::update_user;
+pub use update_user_status::update_user_status;
+
+#[cfg(test)]
+/// Helpers shared by handler tests
+pub(crate) mod tests {
+  use super::response::TokensResponse;
+  use crate::{
+    entity::{Password, TryNewEntity, User, Username},
+    state::{tests::TEST_CLIENT_ID, AppState},
+  };
+  use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue},
+    Json,
+  };
+  use libcommon::{token_fields::Field, TokenBody};
+  use std::{net::SocketAddr, sync::Arc};
+
+  /// Address of the peer sending requests in tests
+  pub(crate) fn remote_addr() -> ConnectInfo<SocketAddr> {
+    ConnectInfo(SocketAddr::from(([192, 0, 2, 1], 50000)))
+  }
+
+  /// Json body of the request deserialized from the value
+  pub(crate) fn request<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Json<T> {
+    Json(serde_json::from_value(value).unwrap())
+  }
+
+  /// Log in with the password and return the issued tokens
+  pub(crate) async fn login(state: &Arc<AppState>, username: &str, password: &str) -> TokensResponse {
+    let request = serde_json::json!({
+      "auth": { "username": username, "password": password },
+      "client_id": TEST_CLIENT_ID,
+    });
+    super::get_tokens(State(state.clone()), remote_addr(), Json(serde_json::from_value(request).unwrap()))
+      .await
+      .unwrap()
+      .0
+  }
+
+  /// Authorization header carrying the id token as a bearer token
+  pub(crate) fn bearer(token: &TokenBody) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let value = HeaderValue::from_str(&format!("Bearer {}", token.id.as_str())).unwrap();
+    headers.insert("authorization", value);
+    headers
+  }
+
+  /// Add a non-admin user
+  pub(crate) async fn add_user(state: &Arc<AppState>, username: &str, password: &str) {
+    let user = User::new(&Username::new(username).unwrap(), Some(Password::new(password).unwrap())).unwrap();
+    state.table.user.add(user).await.unwrap();
+  }
+}
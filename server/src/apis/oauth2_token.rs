@@ -0,0 +1,403 @@
+use super::{
+  client_tokens::{issue_client_token, ClientTokensError},
+  get_tokens,
+  get_tokens::GetTokensError,
+  refresh,
+  refresh::RefreshError,
+  request::{OAuth2TokenRequest, PasswordCredentialRequest, RefreshRequest, TokensRequest},
+  response::{OAuth2TokenResponse, TokensResponse},
+};
+use crate::{
+  constants::{CLIENT_CREDENTIALS_GRANT_TYPE, PASSWORD_GRANT_TYPE, REFRESH_TOKEN_GRANT_TYPE},
+  entity::{Password, TryNewEntity, Username},
+  log::*,
+  state::AppState,
+};
+use axum::{
+  extract::{rejection::FormRejection, ConnectInfo, State},
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Form, Json,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::{
+  token_fields::{ClientId, Field, RefreshToken, TryNewField},
+  TokenBody, PASSWORD_CHANGE_SCOPE,
+};
+
+/// Error of the token endpoint, which is returned with the error code defined in RFC 6749
+#[derive(Debug)]
+pub enum OAuth2Error {
+  InvalidRequest(&'static str),
+  InvalidClient,
+  InvalidGrant(&'static str),
+  UnauthorizedClient,
+  UnsupportedGrantType,
+  ServerError,
+}
+impl IntoResponse for OAuth2Error {
+  fn into_response(self) -> Response {
+    let (status, error, description) = match self {
+      OAuth2Error::InvalidRequest(description) => (StatusCode::BAD_REQUEST, "invalid_request", description),
+      OAuth2Error::InvalidClient => (StatusCode::UNAUTHORIZED, "invalid_client", "Client authentication failed"),
+      OAuth2Error::InvalidGrant(description) => (StatusCode::BAD_REQUEST, "invalid_grant", description),
+      OAuth2Error::UnauthorizedClient => (
+        StatusCode::BAD_REQUEST,
+        "unauthorized_client",
+        "Client is not allowed to use the grant type",
+      ),
+      OAuth2Error::UnsupportedGrantType => {
+        (StatusCode::BAD_REQUEST, "unsupported_grant_type", "Unsupported grant type")
+      }
+      OAuth2Error::ServerError => (StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Token creation failed"),
+    };
+    let body = Json(json!({
+        "error": error,
+        "error_description": description,
+    }));
+    let mut res = (status, no_store_headers(), body).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+      res
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Basic"));
+    }
+    res
+  }
+}
+impl From<GetTokensError> for OAuth2Error {
+  fn from(e: GetTokensError) -> Self {
+    match e {
+      GetTokensError::TokenCreationFailed | GetTokensError::Argon2Failure => OAuth2Error::ServerError,
+      GetTokensError::InvalidPassword | GetTokensError::UnauthorizedUser => {
+        OAuth2Error::InvalidGrant("Invalid username or password")
+      }
+      GetTokensError::UnauthorizedClientApp => OAuth2Error::InvalidClient,
+      GetTokensError::InvalidRequest => OAuth2Error::InvalidRequest("Missing client_id"),
+      GetTokensError::AccountDisabled => OAuth2Error::InvalidGrant("Account disabled"),
+      GetTokensError::AccountLocked => OAuth2Error::InvalidGrant("Account locked"),
+      GetTokensError::TotpRequired => OAuth2Error::InvalidGrant("TOTP code required"),
+      GetTokensError::InvalidTotp => OAuth2Error::InvalidGrant("Invalid TOTP code"),
+    }
+  }
+}
+impl From<RefreshError> for OAuth2Error {
+  fn from(e: RefreshError) -> Self {
+    match e {
+      RefreshError::TokenCreationFailed => OAuth2Error::ServerError,
+      RefreshError::UnauthorizedClientApp => OAuth2Error::InvalidClient,
+      RefreshError::UnauthorizedOrExpiredRefreshToken => OAuth2Error::InvalidGrant("Invalid or expired refresh token"),
+      RefreshError::InvalidRequest => OAuth2Error::InvalidRequest("Missing client_id"),
+      RefreshError::AccountDisabled => OAuth2Error::InvalidGrant("Account disabled"),
+      RefreshError::AccountLocked => OAuth2Error::InvalidGrant("Account locked"),
+    }
+  }
+}
+impl From<ClientTokensError> for OAuth2Error {
+  fn from(e: ClientTokensError) -> Self {
+    match e {
+      ClientTokensError::TokenCreationFailed
+      | ClientTokensError::ClientLookupFailed
+      | ClientTokensError::Argon2Failure => OAuth2Error::ServerError,
+      ClientTokensError::UnsupportedGrantType => OAuth2Error::UnsupportedGrantType,
+      ClientTokensError::InvalidClient => OAuth2Error::InvalidClient,
+    }
+  }
+}
+
+/// Token endpoint of RFC 6749 taking form-encoded requests of the password, refresh token and client credentials
+/// grants, for OAuth 2.0 libraries. The password and refresh token grants share the logic of `/tokens` and `/refresh`,
+/// and the client credentials grant does that of `/client_tokens`. Clients authenticate with HTTP Basic or with
+/// `client_id` and `client_secret` in the form, and public clients just give `client_id`.
+/// Served at `/oauth2/token` out of `/v1.0`.
+pub async fn oauth2_token(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  form: Result<Form<OAuth2TokenRequest>, FormRejection>,
+) -> Result<Response, OAuth2Error> {
+  let Ok(Form(input)) = form else {
+    return Err(OAuth2Error::InvalidRequest("Request must be form-encoded"));
+  };
+  let Some(grant_type) = input.grant_type.clone() else {
+    return Err(OAuth2Error::InvalidRequest("Missing grant_type"));
+  };
+  if ![PASSWORD_GRANT_TYPE, REFRESH_TOKEN_GRANT_TYPE, CLIENT_CREDENTIALS_GRANT_TYPE].contains(&grant_type.as_str()) {
+    return Err(OAuth2Error::UnsupportedGrantType);
+  }
+
+  // confidential clients must authenticate with their secrets whatever the grant type is
+  let (client_id, client_secret) = client_credentials(&headers, &input)?;
+  let authenticated = match (&client_id, &client_secret) {
+    (Some(client_id), Some(client_secret)) => {
+      let Ok(client) = state.table.client.find(client_id).await else {
+        return Err(OAuth2Error::ServerError);
+      };
+      let Ok(client) = state.authenticate_client(client_id, client, client_secret, remote_addr).await else {
+        return Err(OAuth2Error::ServerError);
+      };
+      if client.is_none() {
+        return Err(OAuth2Error::InvalidClient);
+      }
+      true
+    }
+    (Some(client_id), None) => match state.table.client.find(client_id).await {
+      Ok(Some(client)) if client.is_confidential() => return Err(OAuth2Error::InvalidClient),
+      Ok(_) => false,
+      Err(_) => return Err(OAuth2Error::ServerError),
+    },
+    (None, Some(_)) => return Err(OAuth2Error::InvalidClient),
+    (None, None) => false,
+  };
+
+  let res = match grant_type.as_str() {
+    PASSWORD_GRANT_TYPE => {
+      let (Some(username), Some(password)) = (input.username, input.password) else {
+        return Err(OAuth2Error::InvalidRequest("Missing username or password"));
+      };
+      let (Ok(username), Ok(password)) = (Username::new(username), Password::new(password)) else {
+        return Err(OAuth2Error::InvalidRequest("Invalid username or password"));
+      };
+      let request = TokensRequest {
+        auth: PasswordCredentialRequest {
+          username,
+          password,
+          totp: input.totp,
+        },
+        client_id,
+      };
+      let Json(res) = get_tokens(State(state.clone()), ConnectInfo(remote_addr), Json(request)).await?;
+      user_token_response(res)
+    }
+    REFRESH_TOKEN_GRANT_TYPE => {
+      let Some(Ok(refresh_token)) = input.refresh_token.map(RefreshToken::new) else {
+        return Err(OAuth2Error::InvalidRequest("Missing refresh_token"));
+      };
+      let request = RefreshRequest { refresh_token, client_id };
+      let Json(res) = refresh(State(state.clone()), ConnectInfo(remote_addr), Json(request)).await?;
+      user_token_response(res)
+    }
+    _ => {
+      // clients get tokens by themselves only if they are confidential
+      let Some(client_id) = client_id.filter(|_| authenticated) else {
+        return Err(OAuth2Error::UnauthorizedClient);
+      };
+      let token = issue_client_token(&state, &client_id, remote_addr).await?;
+      OAuth2TokenResponse {
+        expires_in: expires_in(&token),
+        access_token: token.id.into_string(),
+        token_type: "Bearer".to_string(),
+        refresh_token: None,
+        id_token: None,
+        scope: None,
+      }
+    }
+  };
+  debug!("Issued tokens via the {grant_type} grant of the OAuth 2.0 token endpoint");
+
+  Ok((no_store_headers(), Json(res)).into_response())
+}
+
+/// Standard response of tokens issued to the user by `/tokens` or `/refresh`
+fn user_token_response(res: TokensResponse) -> OAuth2TokenResponse {
+  let token = res.token;
+  OAuth2TokenResponse {
+    expires_in: expires_in(&token),
+    access_token: token.id.as_str().to_string(),
+    token_type: "Bearer".to_string(),
+    refresh_token: token.refresh.map(|r| r.into_string()),
+    id_token: Some(token.id.into_string()),
+    scope: res.metadata.must_change_password.then(|| PASSWORD_CHANGE_SCOPE.to_string()),
+  }
+}
+
+/// Lifetime of the token in seconds, which depends on the client it is issued to
+fn expires_in(token: &TokenBody) -> i64 {
+  let parse = |t: &str| t.parse::<DateTime<Utc>>().map(|t| t.timestamp()).unwrap_or_default();
+  parse(&token.expires) - parse(&token.issued_at)
+}
+
+/// Responses of the token endpoint must not be cached as RFC 6749 requires
+fn no_store_headers() -> [(header::HeaderName, &'static str); 2] {
+  [(header::CACHE_CONTROL, "no-store"), (header::PRAGMA, "no-cache")]
+}
+
+/// Client id and secret given by HTTP Basic or in the form. Clients must not use both methods at once.
+fn client_credentials(
+  headers: &HeaderMap,
+  input: &OAuth2TokenRequest,
+) -> Result<(Option<ClientId>, Option<Password>), OAuth2Error> {
+  let (client_id, client_secret) = match basic_credentials(headers)? {
+    Some((client_id, client_secret)) => {
+      if input.client_secret.is_some() || input.client_id.as_ref().is_some_and(|id| id != &client_id) {
+        return Err(OAuth2Error::InvalidRequest("Multiple client authentication methods"));
+      }
+      (Some(client_id), Some(client_secret))
+    }
+    None => (input.client_id.clone(), input.client_secret.clone()),
+  };
+  // empty values are taken as absent, e.g., the empty secret of public clients using HTTP Basic
+  let client_id = client_id.filter(|id| !id.is_empty()).map(ClientId::new).transpose();
+  let client_secret = client_secret.filter(|s| !s.is_empty()).map(Password::new).transpose();
+  let (Ok(client_id), Ok(client_secret)) = (client_id, client_secret) else {
+    return Err(OAuth2Error::InvalidRequest("Invalid client_id or client_secret"));
+  };
+  Ok((client_id, client_secret))
+}
+
+/// Client id and secret in the HTTP Basic authorization header, each of which is form-urlencoded as RFC 6749 requires
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, OAuth2Error> {
+  let Some(authorization) = headers.get(header::AUTHORIZATION) else {
+    return Ok(None);
+  };
+  let Some(("Basic" | "basic", encoded)) = authorization.to_str().ok().and_then(|v| v.split_once(' ')) else {
+    return Err(OAuth2Error::InvalidClient);
+  };
+  let Some(decoded) = general_purpose::STANDARD
+    .decode(encoded.trim())
+    .ok()
+    .and_then(|bytes| String::from_utf8(bytes).ok())
+  else {
+    return Err(OAuth2Error::InvalidClient);
+  };
+  let Some((client_id, client_secret)) = decoded.split_once(':') else {
+    return Err(OAuth2Error::InvalidClient);
+  };
+  let form_urldecode = |s: &str| {
+    percent_decode_str(&s.replace('+', " "))
+      .decode_utf8()
+      .map(|s| s.into_owned())
+      .map_err(|_| OAuth2Error::InvalidClient)
+  };
+  Ok(Some((form_urldecode(client_id)?, form_urldecode(client_secret)?)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{create_client, tests::*},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use axum::http::HeaderValue;
+  use serde_json::Value;
+
+  fn form(value: Value) -> Result<Form<OAuth2TokenRequest>, FormRejection> {
+    Ok(Form(serde_json::from_value(value).unwrap()))
+  }
+
+  fn basic(client_id: &str, client_secret: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let credentials = general_purpose::STANDARD.encode(format!("{client_id}:{client_secret}"));
+    let value = HeaderValue::from_str(&format!("Basic {credentials}")).unwrap();
+    headers.insert(header::AUTHORIZATION, value);
+    headers
+  }
+
+  /// Status, error code if any, and body of the response
+  async fn call(state: &Arc<AppState>, headers: HeaderMap, request: Value) -> (StatusCode, Option<String>, Value) {
+    let res = oauth2_token(State(state.clone()), remote_addr(), headers, form(request))
+      .await
+      .into_response();
+    let status = res.status();
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let error = body["error"].as_str().map(str::to_string);
+    (status, error, body)
+  }
+
+  #[tokio::test]
+  async fn password_and_refresh_token_grants_work() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+
+    let request = json!({
+      "grant_type": "password", "username": "user", "password": "user_password", "client_id": TEST_CLIENT_ID
+    });
+    let (status, _, body) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["token_type"], "Bearer");
+    assert_eq!(body["expires_in"], state.crypto.lifetimes.id_token.num_seconds());
+    assert_eq!(body["access_token"], body["id_token"]);
+    assert!(body.get("scope").is_none());
+    let access_token = libcommon::token_fields::IdToken::new(body["access_token"].as_str().unwrap()).unwrap();
+    assert!(state.crypto.verify_token(&access_token).is_ok());
+
+    // public clients can also use HTTP Basic with the empty secret
+    let refresh_token = body["refresh_token"].as_str().unwrap().to_string();
+    let request = json!({ "grant_type": "refresh_token", "refresh_token": refresh_token });
+    let (status, _, body) = call(&state, basic(TEST_CLIENT_ID, ""), request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(body["refresh_token"].as_str().unwrap(), refresh_token);
+    let (status, error, _) = call(&state, basic(TEST_CLIENT_ID, ""), request).await;
+    assert_eq!((status, error.as_deref()), (StatusCode::BAD_REQUEST, Some("invalid_grant")));
+
+    let request = json!({
+      "grant_type": "password", "username": "user", "password": "wrong_password", "client_id": TEST_CLIENT_ID
+    });
+    let (status, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!((status, error.as_deref()), (StatusCode::BAD_REQUEST, Some("invalid_grant")));
+    let request = json!({ "grant_type": "password", "username": "user", "password": "user_password" });
+    let (_, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!(error.as_deref(), Some("invalid_request"));
+    let request = json!({
+      "grant_type": "password", "username": "user", "password": "user_password", "client_id": "unknown"
+    });
+    let (status, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!((status, error.as_deref()), (StatusCode::UNAUTHORIZED, Some("invalid_client")));
+    let request = json!({ "grant_type": "authorization_code", "client_id": TEST_CLIENT_ID });
+    let (_, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!(error.as_deref(), Some("unsupported_grant_type"));
+    let (_, error, _) = call(&state, HeaderMap::new(), json!({ "client_id": TEST_CLIENT_ID })).await;
+    assert_eq!(error.as_deref(), Some("invalid_request"));
+    let request = json!({ "grant_type": "client_credentials", "client_id": TEST_CLIENT_ID });
+    let (_, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!(error.as_deref(), Some("unauthorized_client"));
+  }
+
+  #[tokio::test]
+  async fn confidential_clients_authenticate_with_http_basic() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let create = json!({ "client_id": "web app", "confidential": true });
+    let create = request(create);
+    let created = create_client(State(state.clone()), remote_addr(), bearer(&admin), create)
+      .await
+      .unwrap();
+    let secret = created.client_secret.clone().unwrap();
+
+    // confidential clients cannot use the grants without the secret
+    let password_grant = json!({ "grant_type": "password", "username": "user", "password": "user_password" });
+    let mut request = password_grant.clone();
+    request["client_id"] = json!("web app");
+    let (status, error, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!((status, error.as_deref()), (StatusCode::UNAUTHORIZED, Some("invalid_client")));
+    let (_, error, _) = call(&state, basic("web+app", "wrong_secret"), password_grant.clone()).await;
+    assert_eq!(error.as_deref(), Some("invalid_client"));
+
+    // client ids in Basic credentials are form-urlencoded
+    let (status, _, body) = call(&state, basic("web+app", &secret), password_grant.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["refresh_token"].is_string());
+    let mut request = password_grant;
+    request["client_secret"] = json!(secret);
+    let (_, error, _) = call(&state, basic("web%20app", &secret), request).await;
+    assert_eq!(error.as_deref(), Some("invalid_request"));
+
+    let request = json!({ "grant_type": "client_credentials" });
+    let (status, _, body) = call(&state, basic("web%20app", &secret), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("refresh_token").is_none() && body.get("id_token").is_none());
+    let access_token = libcommon::token_fields::IdToken::new(body["access_token"].as_str().unwrap()).unwrap();
+    assert!(state.crypto.verify_token(&access_token).is_err());
+    let request = json!({ "grant_type": "client_credentials", "client_id": "web app", "client_secret": secret });
+    let (status, _, _) = call(&state, HeaderMap::new(), request).await;
+    assert_eq!(status, StatusCode::OK);
+  }
+}
@@ -1,20 +1,20 @@
 use super::{request::RefreshRequest, response::TokensResponse};
 use crate::{
-  constants::DEFAUTL_CLIENT_ID,
+  entity::{AccountState, AuditEvent, AuditEventKind, RefreshTokenInfo},
   log::*,
-  state::AppState,
-  table::{UserSearchKey, UserTable},
+  state::{AppState, ClientAuthorization},
+  table::{RefreshTokenConsumption, UserSearchKey},
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::StatusCode,
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use libcommon::token_fields::{ClientId, Field, TryNewField};
+use libcommon::token_fields::Field;
 
 #[derive(Debug)]
 pub enum RefreshError {
@@ -22,6 +22,8 @@ pub enum RefreshError {
   UnauthorizedClientApp,
   UnauthorizedOrExpiredRefreshToken,
   InvalidRequest,
+  AccountDisabled,
+  AccountLocked,
 }
 impl IntoResponse for RefreshError {
   fn into_response(self) -> Response {
@@ -30,6 +32,8 @@ impl IntoResponse for RefreshError {
       RefreshError::UnauthorizedClientApp => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       RefreshError::UnauthorizedOrExpiredRefreshToken => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       RefreshError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+      RefreshError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+      RefreshError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
     };
     let body = Json(json!({
         "error": error_message,
@@ -40,37 +44,67 @@ impl IntoResponse for RefreshError {
 
 pub async fn refresh(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   Json(input): Json<RefreshRequest>,
 ) -> Result<Json<TokensResponse>, RefreshError> {
   let refresh_token = input.refresh_token;
 
-  // check allowed client ids if audience is some
-  let client_id = if state.crypto.audiences.is_some() {
-    let Some(client_id) = input.client_id else {
-      return Err(RefreshError::InvalidRequest);
-    };
-    if !state.crypto.audiences.as_ref().unwrap().contains(&client_id) {
-      return Err(RefreshError::UnauthorizedClientApp);
-    }
-    debug!("client_id is ok: {}.", client_id.as_str());
-    client_id
-  } else {
-    let Ok(cid) = ClientId::new(DEFAUTL_CLIENT_ID) else {
+  // check the client id against the client registry
+  let client_id = match state.authorize_client(input.client_id).await {
+    Ok(ClientAuthorization::Allowed(client_id)) => client_id,
+    Ok(ClientAuthorization::Missing) => return Err(RefreshError::InvalidRequest),
+    Ok(ClientAuthorization::Unauthorized) => return Err(RefreshError::UnauthorizedClientApp),
+    Err(_) => return Err(RefreshError::TokenCreationFailed),
+  };
+  debug!("client_id is ok: {}.", client_id.as_str());
+  // check refresh token existence by its keyed hash
+  let Ok(refresh_token_hash) = state.crypto.refresh_token_secret.hash(&refresh_token) else {
+    return Err(RefreshError::TokenCreationFailed);
+  };
+  // check the account before consuming the refresh token, which stays usable once the account is restored
+  let Ok(found) = state
+    .table
+    .refresh_token
+    .find_refresh_token(&refresh_token_hash, &client_id)
+    .await
+  else {
+    return Err(RefreshError::TokenCreationFailed);
+  };
+  if let Some(found) = found {
+    let Ok(Some(user)) = state.table.user.find_user(UserSearchKey::SubscriberId(&found.subscriber_id)).await else {
       return Err(RefreshError::TokenCreationFailed);
     };
-    cid
-  };
-  // check user existence
-  let Ok(entry_opt) = state
+    match user.account_state() {
+      AccountState::Active => (),
+      AccountState::Disabled => return Err(RefreshError::AccountDisabled),
+      AccountState::Locked => return Err(RefreshError::AccountLocked),
+    }
+  }
+  let Ok(consumption) = state
     .table
     .refresh_token
-    .prune_and_find(&refresh_token, &client_id)
+    .consume_refresh_token(&refresh_token_hash, &client_id)
     .await
   else {
     return Err(RefreshError::TokenCreationFailed);
   };
-  let Some(entry) = entry_opt else {
-    return Err(RefreshError::UnauthorizedOrExpiredRefreshToken);
+  let entry = match consumption {
+    RefreshTokenConsumption::Consumed(entry) => entry,
+    RefreshTokenConsumption::Reused(entry) => {
+      // a consumed refresh token is replayed, so the whole family is considered to be compromised
+      warn!(
+        "Reuse of a consumed refresh token detected. Revoke all refresh tokens of the family: subscriber_id = {}",
+        entry.subscriber_id.as_str()
+      );
+      if state.table.refresh_token.revoke_family(&entry.family_id).await.is_err() {
+        error!("Failed to revoke refresh token family");
+        return Err(RefreshError::TokenCreationFailed);
+      }
+      return Err(RefreshError::UnauthorizedOrExpiredRefreshToken);
+    }
+    RefreshTokenConsumption::NotFound => {
+      return Err(RefreshError::UnauthorizedOrExpiredRefreshToken);
+    }
   };
 
   // find user by subscriber_id
@@ -83,13 +117,158 @@ pub async fn refresh(
     return Err(RefreshError::TokenCreationFailed);
   };
 
-  // generate id_token without refresh token
-  let Ok(token) = state.crypto.generate_token(&user, &client_id, false) else {
+  // generate id_token with a new refresh token that replaces the consumed one
+  let Ok(token) = state.crypto.generate_token(&user, &client_id, &entry.amr, true) else {
+    return Err(RefreshError::TokenCreationFailed);
+  };
+  let Ok(refresh) = RefreshTokenInfo::try_new(
+    &token.body,
+    &state.crypto.refresh_token_secret,
+    entry.family_id,
+    entry.amr,
+    state.crypto.lifetimes.refresh_token(&client_id),
+  ) else {
+    error!("Failed to retrieve refresh token from token struct");
     return Err(RefreshError::TokenCreationFailed);
   };
+  if state.table.refresh_token.add(&refresh).await.is_err() {
+    error!("Failed to store refresh token");
+    return Err(RefreshError::TokenCreationFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::Refresh, Some(remote_addr))
+    .actor(&user.subscriber_id)
+    .target(user.username())
+    .client_id(&client_id);
+  state.table.record_audit_event(event).await;
+
   Ok(Json(TokensResponse {
     token: token.body,
     metadata: token.meta,
-    message: "ok. id_token is refreshed.".to_string(),
+    message: "ok. id_token and refresh_token are refreshed.".to_string(),
   }))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{delete_user, tests as helpers, update_user},
+    entity::{Password, TryNewEntity, Username},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use libcommon::token_fields::RefreshToken;
+
+  async fn login(state: &Arc<AppState>) -> TokensResponse {
+    helpers::login(state, "admin", TEST_ADMIN_PASSWORD).await
+  }
+
+  async fn refresh_with(
+    state: &Arc<AppState>,
+    refresh_token: &RefreshToken,
+    client_id: &str,
+  ) -> Result<Json<TokensResponse>, RefreshError> {
+    let request = json!({ "refresh_token": refresh_token.as_str(), "client_id": client_id });
+    refresh(State(state.clone()), helpers::remote_addr(), helpers::request(request)).await
+  }
+
+  /// Exchange the refresh token for a new one
+  async fn rotate(state: &Arc<AppState>, refresh_token: &RefreshToken) -> RefreshToken {
+    let res = refresh_with(state, refresh_token, TEST_CLIENT_ID).await.unwrap();
+    res.0.token.refresh.unwrap()
+  }
+
+  #[tokio::test]
+  async fn refresh_issues_new_id_token() {
+    let state = test_state().await;
+    let login = login(&state).await;
+    let refresh_token = login.token.refresh.clone().unwrap();
+
+    let res = refresh_with(&state, &refresh_token, "unknown_client").await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedClientApp)));
+
+    let res = refresh_with(&state, &refresh_token, TEST_CLIENT_ID).await.unwrap();
+    assert_eq!(res.token.subscriber_id, login.token.subscriber_id);
+  }
+
+  #[tokio::test]
+  async fn refresh_rotates_refresh_token() {
+    let state = test_state().await;
+    let first = login(&state).await.token.refresh.unwrap();
+
+    let second = rotate(&state, &first).await;
+    assert_ne!(second, first);
+    let third = rotate(&state, &second).await;
+    assert_ne!(third, second);
+  }
+
+  #[tokio::test]
+  async fn reuse_of_consumed_refresh_token_revokes_family() {
+    let state = test_state().await;
+    let first = login(&state).await.token.refresh.unwrap();
+    let other_session = login(&state).await.token.refresh.unwrap();
+    let second = rotate(&state, &first).await;
+
+    // replay of the consumed token
+    let res = refresh_with(&state, &first, TEST_CLIENT_ID).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+
+    // the token rotated from it is revoked as well, but other sessions are untouched
+    let res = refresh_with(&state, &second, TEST_CLIENT_ID).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+    assert!(refresh_with(&state, &other_session, TEST_CLIENT_ID).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn refresh_fails_after_user_deletion() {
+    let state = test_state().await;
+    helpers::add_user(&state, "user", "user_password").await;
+    let user = helpers::login(&state, "user", "user_password").await.token;
+    let admin = login(&state).await.token;
+
+    let request = helpers::request(json!({ "username": "user" }));
+    assert!(delete_user(State(state.clone()), helpers::remote_addr(), helpers::bearer(&admin), request).await.is_ok());
+
+    let res = refresh_with(&state, user.refresh.as_ref().unwrap(), TEST_CLIENT_ID).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+  }
+
+  #[tokio::test]
+  async fn refresh_fails_after_password_change() {
+    let state = test_state().await;
+    helpers::add_user(&state, "user", "user_password").await;
+    let first = helpers::login(&state, "user", "user_password").await.token;
+    let second = helpers::login(&state, "user", "user_password").await.token;
+
+    // renaming keeps sessions
+    let request = helpers::request(json!({ "auth": { "username": "renamed" } }));
+    assert!(update_user(State(state.clone()), helpers::remote_addr(), helpers::bearer(&first), request).await.is_ok());
+    let second_rotated = rotate(&state, second.refresh.as_ref().unwrap()).await;
+
+    // password change via the api ends every session including the caller's one
+    let request = helpers::request(json!({ "auth": { "password": "new_password" } }));
+    assert!(update_user(State(state.clone()), helpers::remote_addr(), helpers::bearer(&first), request).await.is_ok());
+    for refresh_token in [first.refresh.as_ref().unwrap(), &second_rotated] {
+      let res = refresh_with(&state, refresh_token, TEST_CLIENT_ID).await;
+      assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+    }
+  }
+
+  #[tokio::test]
+  async fn refresh_fails_after_admin_password_change() {
+    let state = test_state().await;
+    let admin = login(&state).await.token;
+
+    // as done by the admin subcommand
+    let admin_name = Username::new("admin").unwrap();
+    state
+      .table
+      .user
+      .update_password(UserSearchKey::Username(&admin_name), &Password::new("new_password").unwrap())
+      .await
+      .unwrap();
+
+    let res = refresh_with(&state, admin.refresh.as_ref().unwrap(), TEST_CLIENT_ID).await;
+    assert!(matches!(res, Err(RefreshError::UnauthorizedOrExpiredRefreshToken)));
+  }
+}
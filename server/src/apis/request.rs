@@ -1,7 +1,10 @@
-use crate::entity::{Password, Username};
+use crate::entity::{
+  deserialize_some, AuditEventKind, ClientUpdate, DisplayName, Email, Password, ProfileUpdate, Roles, UserAttributes,
+  Username,
+};
 use serde::Deserialize;
 
-use libcommon::token_fields::{ClientId, RefreshToken};
+use libcommon::token_fields::{ClientId, RefreshToken, SubscriberId};
 
 #[cfg(feature = "blind-signatures")]
 use libcommon::blind_sig::BlindOptions;
@@ -10,8 +13,10 @@ use libcommon::blind_sig::BlindOptions;
 pub struct PasswordCredentialRequest {
   pub username: Username,
   pub password: Password,
+  /// TOTP code or recovery code, required at login if the user has enabled TOTP
+  pub totp: Option<String>,
 }
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct PasswordCredentialOptionalRequest {
   pub username: Option<Username>,
   pub password: Option<Password>,
@@ -29,9 +34,23 @@ pub struct RefreshRequest {
   pub client_id: Option<ClientId>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogoutRequest {
+  pub refresh_token: RefreshToken,
+  pub client_id: Option<ClientId>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogoutUserRequest {
+  pub username: Username,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct CreateUserRequest {
   pub auth: PasswordCredentialRequest,
+  pub email: Option<Email>,
+  pub display_name: Option<DisplayName>,
+  pub attributes: Option<UserAttributes>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -41,7 +60,62 @@ pub struct DeleteUserRequest {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct UpdateUserRequest {
+  /// New username and/or password of the requesting user
+  #[serde(default)]
   pub auth: PasswordCredentialOptionalRequest,
+  /// User whose profile is updated by a user manager. The requesting user if omitted.
+  pub username: Option<Username>,
+  /// Profile fields, which only user managers can update
+  #[serde(flatten)]
+  pub profile: ProfileUpdate,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ResetPasswordRequest {
+  pub username: Username,
+  /// Temporary password, which is randomly generated if omitted
+  pub password: Option<Password>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnrollTotpRequest {
+  /// Current password of the requesting user, since a stolen id token must not be enough to register a second factor
+  pub password: Password,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ActivateTotpRequest {
+  /// First code generated by the authenticator app from the enrolled secret
+  pub totp: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DisableTotpRequest {
+  /// User whose TOTP is disabled by a user manager. The requesting user if omitted.
+  pub username: Option<Username>,
+  /// TOTP code or recovery code, required to disable TOTP of the requesting user
+  pub totp: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateRolesRequest {
+  pub username: Username,
+  /// Roles replacing the current ones of the user, except for the admin role
+  pub roles: Roles,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AdminRoleRequest {
+  pub username: Username,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateUserStatusRequest {
+  pub username: Username,
+  pub enabled: Option<bool>,
+  /// Unix time in seconds until which the user is locked. `null` unlocks the user.
+  #[serde(default, deserialize_with = "deserialize_some")]
+  pub locked_until: Option<Option<i64>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -49,6 +123,67 @@ pub struct ListUserRequest {
   pub page: Option<u32>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuditEventsRequest {
+  pub page: Option<u32>,
+  pub kind: Option<AuditEventKind>,
+  pub actor: Option<SubscriberId>,
+  pub target: Option<String>,
+  pub client_id: Option<ClientId>,
+  /// Unix time in seconds, inclusive
+  pub since: Option<i64>,
+  /// Unix time in seconds, exclusive
+  pub until: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateClientRequest {
+  pub client_id: ClientId,
+  pub display_name: Option<DisplayName>,
+  /// Confidential clients are given a client secret to get tokens by themselves via the client credentials grant
+  #[serde(default)]
+  pub confidential: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ListClientsRequest {
+  pub page: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateClientRequest {
+  pub client_id: ClientId,
+  #[serde(flatten)]
+  pub update: ClientUpdate,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeleteClientRequest {
+  pub client_id: ClientId,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClientTokensRequest {
+  /// Must be `client_credentials`
+  pub grant_type: String,
+  pub client_id: ClientId,
+  pub client_secret: Password,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// Form-encoded request to the OAuth 2.0 token endpoint, where each grant type takes its own parameters. Parameters are
+/// validated by the handler so that errors are reported as RFC 6749 defines.
+pub struct OAuth2TokenRequest {
+  pub grant_type: Option<String>,
+  pub username: Option<String>,
+  pub password: Option<String>,
+  /// TOTP code or recovery code for the password grant, which is an extension to RFC 6749
+  pub totp: Option<String>,
+  pub refresh_token: Option<String>,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
 #[cfg(feature = "blind-signatures")]
 #[derive(Deserialize, Debug, Clone)]
 pub struct BlindSignRequest {
@@ -0,0 +1,276 @@
+use super::{request::ResetPasswordRequest, response::ResetPasswordResponse};
+use crate::{
+  constants::PASSWORD_LEN,
+  entity::{
+    generate_random_string, AuditEvent, AuditEventKind, Password, PasswordPolicyViolation, Permission, Roles, TryNewEntity,
+  },
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum ResetPasswordError {
+  ResetFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchUser,
+  UpdateProhibitedUser,
+  WeakPassword(Vec<PasswordPolicyViolation>),
+  BreachedPassword,
+}
+impl IntoResponse for ResetPasswordError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match &self {
+      ResetPasswordError::ResetFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Password reset failed"),
+      ResetPasswordError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      ResetPasswordError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      ResetPasswordError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      ResetPasswordError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      ResetPasswordError::UpdateProhibitedUser => (StatusCode::BAD_REQUEST, "Update prohibited user"),
+      ResetPasswordError::WeakPassword(_) => (StatusCode::BAD_REQUEST, "Password policy violation"),
+      ResetPasswordError::BreachedPassword => (StatusCode::BAD_REQUEST, "Password found in breached password list"),
+    };
+    let body = match self {
+      ResetPasswordError::WeakPassword(violations) => Json(json!({
+          "error": error_message,
+          "details": violations,
+      })),
+      _ => Json(json!({
+          "error": error_message,
+      })),
+    };
+    (status, body).into_response()
+  }
+}
+
+/// Set a temporary password of the given user under the user manager privilege, which the user must change at the next
+/// login. The password is randomly generated unless given, and all sessions of the user are revoked.
+pub async fn reset_password(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<ResetPasswordRequest>,
+) -> Result<Json<ResetPasswordResponse>, ResetPasswordError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(ResetPasswordError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(ResetPasswordError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(ResetPasswordError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(ResetPasswordError::InvalidToken);
+  };
+
+  // roles in the token must permit to manage users
+  if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
+    return Err(ResetPasswordError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(ResetPasswordError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(ResetPasswordError::ResetFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(ResetPasswordError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageUsers) {
+    return Err(ResetPasswordError::InvalidToken);
+  }
+
+  // check if the user exist
+  let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&request.username)).await else {
+    return Err(ResetPasswordError::ResetFailed);
+  };
+  let Some(target_user) = u else {
+    return Err(ResetPasswordError::NoSuchUser);
+  };
+  // user managers change their own passwords via update_user
+  if request_user.username() == target_user.username() {
+    return Err(ResetPasswordError::UpdateProhibitedUser);
+  }
+  // users of the admin role are managed only by admins
+  if target_user.is_admin() && !request_user.is_admin() {
+    return Err(ResetPasswordError::UnauthorizedUser);
+  }
+
+  // given temporary password must satisfy the policy, while a generated one is returned to the user manager
+  let (temporary_password, generated) = match request.password {
+    Some(password) => {
+      if let Err(violations) = state.password_policy.check(&target_user.username, &password) {
+        return Err(ResetPasswordError::WeakPassword(violations));
+      }
+      if state.breached_passwords.as_ref().is_some_and(|list| list.contains(&password)) {
+        return Err(ResetPasswordError::BreachedPassword);
+      }
+      (password, None)
+    }
+    None => {
+      let Ok(random_pass) = generate_random_string(PASSWORD_LEN) else {
+        return Err(ResetPasswordError::ResetFailed);
+      };
+      let Ok(password) = Password::new(&random_pass) else {
+        return Err(ResetPasswordError::ResetFailed);
+      };
+      (password, Some(random_pass))
+    }
+  };
+
+  let Ok(_) = state
+    .table
+    .user
+    .reset_password(UserSearchKey::SubscriberId(&target_user.subscriber_id), &temporary_password)
+    .await
+  else {
+    return Err(ResetPasswordError::ResetFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::PasswordReset, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(ResetPasswordResponse {
+    password: generated,
+    message: "ok. reset the password of the user, which must be changed at the next login.".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{get_tokens, list_users, refresh, tests::*, update_user, update_user::UpdateUserError},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use libcommon::{
+    token_fields::{Audiences, Field},
+    ValidationOptions, PASSWORD_CHANGE_SCOPE,
+  };
+  use std::collections::HashSet;
+
+  #[tokio::test]
+  async fn reset_password_forces_change_at_next_login() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    let res = reset_password(
+      State(state.clone()),
+      remote_addr(),
+      bearer(&user),
+      request(json!({ "username": "admin" })),
+    )
+    .await;
+    assert!(matches!(res, Err(ResetPasswordError::UnauthorizedUser)));
+    let res = reset_password(
+      State(state.clone()),
+      remote_addr(),
+      bearer(&admin),
+      request(json!({ "username": "admin" })),
+    )
+    .await;
+    assert!(matches!(res, Err(ResetPasswordError::UpdateProhibitedUser)));
+
+    // the generated password is returned, and the sessions of the user are revoked
+    let res = reset_password(
+      State(state.clone()),
+      remote_addr(),
+      bearer(&admin),
+      request(json!({ "username": "user" })),
+    )
+    .await
+    .unwrap();
+    let temporary_password = res.password.clone().unwrap();
+    assert_eq!(temporary_password.len(), PASSWORD_LEN);
+    let refresh_request = json!({ "refresh_token": user.refresh.as_ref().unwrap().as_str(), "client_id": TEST_CLIENT_ID });
+    assert!(refresh(State(state.clone()), remote_addr(), request(refresh_request))
+      .await
+      .is_err());
+    let res = list_users(State(state.clone()), bearer(&admin), request(json!({})))
+      .await
+      .unwrap();
+    assert!(res.users.iter().find(|u| u.username == "user").unwrap().must_change_password);
+
+    // the temporary password gives only a restricted token without refresh token
+    let res = login(&state, "user", &temporary_password).await;
+    assert!(res.metadata.must_change_password);
+    assert!(res.token.refresh.is_none());
+    let restricted = res.token;
+    assert!(state.crypto.verify_token(&restricted.id).is_err());
+
+    // validators of the client reject the restricted token even if they are unaware of the scope
+    let vo = ValidationOptions {
+      allowed_audiences: Some(Audiences::new(TEST_CLIENT_ID).unwrap()),
+      allowed_scopes: HashSet::from([PASSWORD_CHANGE_SCOPE.to_string()]),
+      ..Default::default()
+    };
+    assert!(state.crypto.signing_key.validate(&restricted.id, &vo).is_err());
+    let claims = state.crypto.verify_password_change_token(&restricted.id).unwrap();
+    assert_eq!(claims.custom["aud"], json!([state.crypto.issuer.as_str()]));
+    assert!(["iad", "roles", "email", "name"].iter().all(|c| !claims.custom.contains_key(*c)));
+    let res = reset_password(
+      State(state.clone()),
+      remote_addr(),
+      bearer(&restricted),
+      request(json!({ "username": "admin" })),
+    )
+    .await;
+    assert!(matches!(res, Err(ResetPasswordError::InvalidToken)));
+    let res = list_users(State(state.clone()), bearer(&restricted), request(json!({}))).await;
+    assert!(res.is_err());
+
+    // the restricted token is good only for changing the password to another one
+    let update = json!({ "username": "user", "display_name": "User" });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&restricted), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::PasswordChangeRequired)));
+    let update = json!({ "auth": { "password": temporary_password } });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&restricted), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::PasswordNotChanged)));
+    let update = json!({ "auth": { "password": "another_secret" } });
+    assert!(
+      update_user(State(state.clone()), remote_addr(), bearer(&restricted), request(update))
+        .await
+        .is_ok()
+    );
+
+    let res = login(&state, "user", "another_secret").await;
+    assert!(!res.metadata.must_change_password);
+    assert!(res.token.refresh.is_some());
+    assert!(state.crypto.verify_token(&res.token.id).is_ok());
+
+    // a given temporary password must satisfy the policy
+    let reset = json!({ "username": "user", "password": "short" });
+    let res = reset_password(State(state.clone()), remote_addr(), bearer(&admin), request(reset)).await;
+    assert!(matches!(res, Err(ResetPasswordError::WeakPassword(_))));
+    let reset = json!({ "username": "user", "password": "temporary_password" });
+    let res = reset_password(State(state.clone()), remote_addr(), bearer(&admin), request(reset))
+      .await
+      .unwrap();
+    assert!(res.password.is_none());
+    let login_request = json!({ "auth": { "username": "user", "password": "temporary_password" }, "client_id": TEST_CLIENT_ID });
+    let res = get_tokens(State(state), remote_addr(), request(login_request)).await.unwrap();
+    assert!(res.metadata.must_change_password);
+  }
+}
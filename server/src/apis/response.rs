@@ -1,3 +1,4 @@
+use crate::entity::AuditEventKind;
 use libcommon::{TokenBody, TokenMeta};
 use serde::Serialize;
 
@@ -11,6 +12,29 @@ pub struct TokensResponse {
   pub message: String,
 }
 #[derive(Serialize, Debug, Clone)]
+pub struct ClientTokensResponse {
+  /// Token issued to the client itself, without refresh token
+  pub token: TokenBody,
+  pub message: String,
+}
+#[derive(Serialize, Debug, Clone)]
+/// Successful response of the OAuth 2.0 token endpoint as defined in RFC 6749 and OpenID Connect
+pub struct OAuth2TokenResponse {
+  pub access_token: String,
+  /// Always `Bearer`
+  pub token_type: String,
+  /// Lifetime of the access token in seconds
+  pub expires_in: i64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub refresh_token: Option<String>,
+  /// Same JWT as the access token, which is not given to clients getting tokens by themselves
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id_token: Option<String>,
+  /// Given only if the token is restricted, e.g., to changing the password
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+}
+#[derive(Serialize, Debug, Clone)]
 pub struct MessageResponse {
   pub message: String,
 }
@@ -29,6 +53,88 @@ pub struct ListUserResponseInner {
   pub username: String,
   pub subscriber_id: String,
   pub is_admin: bool,
+  pub roles: Vec<String>,
+  pub email: Option<String>,
+  pub display_name: Option<String>,
+  pub attributes: serde_json::Map<String, serde_json::Value>,
+  pub enabled: bool,
+  /// Unix time in seconds until which the user is locked
+  pub locked_until: Option<i64>,
+  /// Number of consecutive failed logins
+  pub failed_logins: u32,
+  /// True if the password has been reset and must be changed at the next login
+  pub must_change_password: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResetPasswordResponse {
+  /// Randomly generated temporary password, which is returned only if no password is given
+  pub password: Option<String>,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EnrollTotpResponse {
+  /// Base32-encoded secret to be registered with authenticator apps
+  pub secret: String,
+  /// URI of the secret to be shown as a QR code
+  pub otpauth_uri: String,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ActivateTotpResponse {
+  /// Single-use codes to log in without the authenticator app, which are never shown again
+  pub recovery_codes: Vec<String>,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CreateClientResponse {
+  /// Randomly generated secret of the confidential client, which is never shown again
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_secret: Option<String>,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ListClientsResponse {
+  pub clients: Vec<ListClientsResponseInner>,
+  pub page: u32,
+  pub total_pages: u32,
+  pub total_clients: u32,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ListClientsResponseInner {
+  pub client_id: String,
+  pub display_name: Option<String>,
+  pub enabled: bool,
+  /// True if the client has a client secret for the client credentials grant
+  pub confidential: bool,
+  /// Unix time in seconds when the client was registered
+  pub created_at: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEventsResponse {
+  pub events: Vec<AuditEventsResponseInner>,
+  pub page: u32,
+  pub total_pages: u32,
+  pub total_events: u32,
+  pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEventsResponseInner {
+  /// Unix time in seconds
+  pub timestamp: i64,
+  pub kind: AuditEventKind,
+  pub actor: Option<String>,
+  pub target: Option<String>,
+  pub client_id: Option<String>,
+  pub remote_addr: Option<String>,
 }
 
 #[cfg(feature = "blind-signatures")]
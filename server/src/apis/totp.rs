@@ -0,0 +1,397 @@
+use super::{
+  request::{ActivateTotpRequest, DisableTotpRequest, EnrollTotpRequest},
+  response::{ActivateTotpResponse, EnrollTotpResponse, MessageResponse},
+};
+use crate::{
+  constants::TOTP_DEFAULT_ISSUER,
+  entity::{
+    generate_recovery_codes, normalize_recovery_code, AccountState, AuditEvent, AuditEventKind, Entity, Permission,
+    Roles, TotpFactor, TotpSecret, User,
+  },
+  error::{bail, Result},
+  state::{AppState, SecondFactor},
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+use url::Url;
+
+use libcommon::{
+  token_fields::{Field, IdToken, SubscriberId, TryNewField},
+  Claims,
+};
+
+#[derive(Debug)]
+pub enum TotpError {
+  TotpUpdateFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  InvalidPassword,
+  NoSuchUser,
+  NotEnrolled,
+  AlreadyEnabled,
+  TotpRequired,
+  InvalidTotp,
+  AccountDisabled,
+  AccountLocked,
+}
+impl IntoResponse for TotpError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      TotpError::TotpUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "TOTP update failed"),
+      TotpError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      TotpError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      TotpError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      TotpError::InvalidPassword => (StatusCode::UNAUTHORIZED, "Invalid password"),
+      TotpError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      TotpError::NotEnrolled => (StatusCode::BAD_REQUEST, "TOTP not enrolled"),
+      TotpError::AlreadyEnabled => (StatusCode::BAD_REQUEST, "TOTP already enabled"),
+      TotpError::TotpRequired => (StatusCode::UNAUTHORIZED, "TOTP code required"),
+      TotpError::InvalidTotp => (StatusCode::UNAUTHORIZED, "Invalid TOTP code"),
+      TotpError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+      TotpError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Generate a new TOTP secret of the requesting user re-authenticated with the password, which is enabled once a code
+/// generated from it is verified via `activate_totp`. The secret is returned with an otpauth URI to be shown as a QR
+/// code to authenticator apps.
+pub async fn enroll_totp(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<EnrollTotpRequest>,
+) -> Result<Json<EnrollTotpResponse>, TotpError> {
+  let (_, user) = authenticate(&state, &headers).await?;
+
+  // a stolen id token must not be enough to register the second factor of the attacker, so the password is required
+  match user.account_state() {
+    AccountState::Active => (),
+    AccountState::Disabled => return Err(TotpError::AccountDisabled),
+    AccountState::Locked => return Err(TotpError::AccountLocked),
+  }
+  let Ok(password_verified) = request.password.verify(&user.encoded_hash) else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  if !password_verified {
+    if state.record_failed_login(&user, remote_addr).await {
+      return Err(TotpError::AccountLocked);
+    }
+    return Err(TotpError::InvalidPassword);
+  }
+
+  let Ok(current) = state.table.totp.find(&user.subscriber_id).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  if current.is_some_and(|f| f.enabled) {
+    return Err(TotpError::AlreadyEnabled);
+  }
+
+  let secret = TotpSecret::generate();
+  let Ok(sealed) = state.crypto.second_factor_key.seal(&secret, &user.subscriber_id) else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let factor = TotpFactor::pending(&user.subscriber_id, sealed);
+  // authenticator apps show the host of the issuer as the name of the service
+  let issuer = Url::parse(state.crypto.issuer.as_str())
+    .ok()
+    .and_then(|url| url.host_str().map(str::to_string))
+    .unwrap_or_else(|| TOTP_DEFAULT_ISSUER.to_string());
+  let Ok(otpauth_uri) = secret.otpauth_uri(&issuer, user.username()) else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Ok(_) = state.table.totp.enroll(&factor).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+
+  Ok(Json(EnrollTotpResponse {
+    secret: secret.to_base32(),
+    otpauth_uri,
+    message: "ok. register the secret with the authenticator app, and activate it with a generated code.".to_string(),
+  }))
+}
+
+/// Enable the enrolled TOTP secret of the requesting user with the first code generated from it.
+/// Recovery codes are returned only once, and only their keyed hashes are stored.
+pub async fn activate_totp(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<ActivateTotpRequest>,
+) -> Result<Json<ActivateTotpResponse>, TotpError> {
+  let (_, user) = authenticate(&state, &headers).await?;
+
+  let Ok(factor) = state.table.totp.find(&user.subscriber_id).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Some(factor) = factor else {
+    return Err(TotpError::NotEnrolled);
+  };
+  if factor.enabled {
+    return Err(TotpError::AlreadyEnabled);
+  }
+  let Ok(secret) = state.crypto.second_factor_key.open(&factor.secret, &user.subscriber_id) else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Ok(verified) = secret.verify(request.totp.trim(), chrono::Local::now().timestamp()) else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Some(step) = verified else {
+    return Err(TotpError::InvalidTotp);
+  };
+
+  let recovery_codes = generate_recovery_codes();
+  let Ok(hashes) = recovery_codes
+    .iter()
+    .map(|code| {
+      let Some(normalized) = normalize_recovery_code(code) else {
+        bail!("Invalid recovery code is generated");
+      };
+      state.crypto.second_factor_key.hash_recovery_code(&normalized)
+    })
+    .collect::<Result<Vec<_>>>()
+  else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Ok(enabled) = state.table.totp.enable(&user.subscriber_id, step, &hashes).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  // enabled or re-enrolled concurrently
+  if !enabled {
+    return Err(TotpError::NotEnrolled);
+  }
+
+  let event = AuditEvent::new(AuditEventKind::TotpEnabled, Some(remote_addr))
+    .actor(&user.subscriber_id)
+    .target(user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(ActivateTotpResponse {
+    recovery_codes,
+    message: "ok. enabled TOTP. keep the recovery codes securely.".to_string(),
+  }))
+}
+
+/// Disable TOTP of the requesting user with a TOTP code or a recovery code. User managers can also disable TOTP of
+/// other users who have lost their authenticator apps and recovery codes, without any code.
+pub async fn disable_totp(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<DisableTotpRequest>,
+) -> Result<Json<MessageResponse>, TotpError> {
+  let (claims, request_user) = authenticate(&state, &headers).await?;
+  let actor = request_user.subscriber_id.clone();
+
+  let target_user = match request.username {
+    Some(username) if username.as_str() != request_user.username() => {
+      // roles in the token must permit to manage users
+      if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
+        return Err(TotpError::UnauthorizedUser);
+      }
+      if !request_user.roles.permits(Permission::ManageUsers) {
+        return Err(TotpError::InvalidToken);
+      }
+      let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&username)).await else {
+        return Err(TotpError::TotpUpdateFailed);
+      };
+      let Some(target_user) = u else {
+        return Err(TotpError::NoSuchUser);
+      };
+      // users of the admin role are managed only by admins
+      if target_user.is_admin() && !request_user.is_admin() {
+        return Err(TotpError::UnauthorizedUser);
+      }
+      let Ok(Some(factor)) = state.table.totp.find(&target_user.subscriber_id).await else {
+        return Err(TotpError::NotEnrolled);
+      };
+      if !factor.enabled {
+        return Err(TotpError::NotEnrolled);
+      }
+      target_user
+    }
+    _ => {
+      // a stolen id token must not be enough to remove the second factor, so a code is required like logins
+      match request_user.account_state() {
+        AccountState::Active => (),
+        AccountState::Disabled => return Err(TotpError::AccountDisabled),
+        AccountState::Locked => return Err(TotpError::AccountLocked),
+      }
+      let Ok(second_factor) = state
+        .verify_second_factor(&request_user, request.totp.as_deref(), remote_addr)
+        .await
+      else {
+        return Err(TotpError::TotpUpdateFailed);
+      };
+      match second_factor {
+        SecondFactor::Verified => (),
+        SecondFactor::NotEnrolled => return Err(TotpError::NotEnrolled),
+        SecondFactor::Missing => return Err(TotpError::TotpRequired),
+        SecondFactor::Invalid => {
+          if state.record_failed_login(&request_user, remote_addr).await {
+            return Err(TotpError::AccountLocked);
+          }
+          return Err(TotpError::InvalidTotp);
+        }
+      }
+      request_user
+    }
+  };
+
+  let Ok(_) = state.table.totp.disable(&target_user.subscriber_id).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::TotpDisabled, Some(remote_addr))
+    .actor(&actor)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. disabled TOTP of the user.".to_string(),
+  }))
+}
+
+/// Verify the bearer token, which must not be restricted, and find the requesting user
+async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<(Claims, User), TotpError> {
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(TotpError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(TotpError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(TotpError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(TotpError::InvalidToken);
+  };
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(TotpError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(TotpError::TotpUpdateFailed);
+  };
+  let Some(user) = opt else {
+    return Err(TotpError::UnauthorizedUser);
+  };
+  Ok((claims, user))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{get_tokens, get_tokens::GetTokensError, refresh, response::TokensResponse, tests::*},
+    constants::{RECOVERY_CODE_COUNT, TOTP_STEP_SECS},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+
+  async fn login_with_totp(state: &Arc<AppState>, totp: Option<&str>) -> Result<TokensResponse, GetTokensError> {
+    let login_request = json!({
+      "auth": { "username": "user", "password": "user_password", "totp": totp },
+      "client_id": TEST_CLIENT_ID,
+    });
+    get_tokens(State(state.clone()), remote_addr(), request(login_request))
+      .await
+      .map(|res| res.0)
+  }
+
+  #[tokio::test]
+  async fn totp_is_required_once_activated() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let claims = state.crypto.verify_token(&user.id).unwrap();
+    assert_eq!(claims.custom["amr"], json!(["pwd"]));
+
+    // the secret is pending until the first code is verified, and enrolled only with the password
+    let enroll = json!({ "password": "user_password" });
+    let activate = json!({ "totp": "000000" });
+    let res = activate_totp(State(state.clone()), remote_addr(), bearer(&user), request(activate)).await;
+    assert!(matches!(res, Err(TotpError::NotEnrolled)));
+    let wrong_password = json!({ "password": "wrong_password" });
+    let res = enroll_totp(State(state.clone()), remote_addr(), bearer(&user), request(wrong_password)).await;
+    assert!(matches!(res, Err(TotpError::InvalidPassword)));
+    let enrolled = enroll_totp(State(state.clone()), remote_addr(), bearer(&user), request(enroll.clone()))
+      .await
+      .unwrap();
+    assert!(enrolled.otpauth_uri.starts_with("otpauth://totp/"));
+    let secret = TotpSecret::from_base32(&enrolled.secret).unwrap();
+    // codes of the next step are still accepted if the clock moves on during the test
+    let step = chrono::Local::now().timestamp().div_euclid(TOTP_STEP_SECS);
+    assert!(login_with_totp(&state, None).await.is_ok());
+
+    let activate = json!({ "totp": secret.code(step).unwrap() });
+    let recovery_codes = activate_totp(State(state.clone()), remote_addr(), bearer(&user), request(activate))
+      .await
+      .unwrap()
+      .recovery_codes
+      .clone();
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+    let res = enroll_totp(State(state.clone()), remote_addr(), bearer(&user), request(enroll)).await;
+    assert!(matches!(res, Err(TotpError::AlreadyEnabled)));
+
+    // the code used for the activation cannot be replayed, while the next one is accepted
+    let res = login_with_totp(&state, None).await;
+    assert!(matches!(res, Err(GetTokensError::TotpRequired)));
+    let res = login_with_totp(&state, Some(&secret.code(step).unwrap())).await;
+    assert!(matches!(res, Err(GetTokensError::InvalidTotp)));
+    let res = login_with_totp(&state, Some(&secret.code(step + 1).unwrap())).await.unwrap();
+    let claims = state.crypto.verify_token(&res.token.id).unwrap();
+    assert_eq!(claims.custom["amr"], json!(["pwd", "otp", "mfa"]));
+
+    // the methods of the login are kept through refreshes
+    let refresh_request = json!({
+      "refresh_token": res.token.refresh.as_ref().unwrap().as_str(),
+      "client_id": TEST_CLIENT_ID,
+    });
+    let refreshed = refresh(State(state.clone()), remote_addr(), request(refresh_request))
+      .await
+      .unwrap();
+    let claims = state.crypto.verify_token(&refreshed.token.id).unwrap();
+    assert_eq!(claims.custom["amr"], json!(["pwd", "otp", "mfa"]));
+
+    // a recovery code is accepted only once, ignoring case
+    let res = login_with_totp(&state, Some(&recovery_codes[0].to_uppercase())).await;
+    assert!(res.is_ok());
+    let res = login_with_totp(&state, Some(&recovery_codes[0])).await;
+    assert!(matches!(res, Err(GetTokensError::InvalidTotp)));
+    // malformed codes are rejected like wrong ones
+    let res = login_with_totp(&state, Some("-")).await;
+    assert!(matches!(res, Err(GetTokensError::InvalidTotp)));
+
+    // disabling requires a code of the user, but not of user managers
+    let user = login_with_totp(&state, Some(&recovery_codes[1])).await.unwrap().token;
+    let res = disable_totp(State(state.clone()), remote_addr(), bearer(&user), request(json!({}))).await;
+    assert!(matches!(res, Err(TotpError::TotpRequired)));
+    let disable = json!({ "username": "admin" });
+    let res = disable_totp(State(state.clone()), remote_addr(), bearer(&user), request(disable)).await;
+    assert!(matches!(res, Err(TotpError::UnauthorizedUser)));
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let disable = json!({ "username": "user" });
+    assert!(disable_totp(State(state.clone()), remote_addr(), bearer(&admin), request(disable))
+      .await
+      .is_ok());
+    let res = login_with_totp(&state, None).await.unwrap();
+    let claims = state.crypto.verify_token(&res.token.id).unwrap();
+    assert_eq!(claims.custom["amr"], json!(["pwd"]));
+  }
+}
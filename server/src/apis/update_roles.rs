@@ -0,0 +1,172 @@
+use super::{request::UpdateRolesRequest, response::MessageResponse};
+use crate::{
+  entity::{AuditEvent, AuditEventKind, Permission, Role, Roles},
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum UpdateRolesError {
+  RoleUpdateFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchUser,
+  AdminRoleNotAllowed,
+}
+impl IntoResponse for UpdateRolesError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      UpdateRolesError::RoleUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "Role update failed"),
+      UpdateRolesError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      UpdateRolesError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      UpdateRolesError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      UpdateRolesError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      UpdateRolesError::AdminRoleNotAllowed => (StatusCode::BAD_REQUEST, "The admin role cannot be updated here"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Replace roles of the given user under the admin privilege. The admin role of the user is kept as it is.
+pub async fn update_roles(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<UpdateRolesRequest>,
+) -> Result<Json<MessageResponse>, UpdateRolesError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(UpdateRolesError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(UpdateRolesError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(UpdateRolesError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(UpdateRolesError::InvalidToken);
+  };
+
+  // roles in the token must permit to manage roles
+  if !Roles::from_claims(&claims).permits(Permission::ManageRoles) {
+    return Err(UpdateRolesError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(UpdateRolesError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(UpdateRolesError::RoleUpdateFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(UpdateRolesError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageRoles) {
+    return Err(UpdateRolesError::InvalidToken);
+  }
+
+  if request.roles.contains(Role::Admin) {
+    return Err(UpdateRolesError::AdminRoleNotAllowed);
+  }
+
+  // check if the user exist
+  let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&request.username)).await else {
+    return Err(UpdateRolesError::RoleUpdateFailed);
+  };
+  let Some(target_user) = u else {
+    return Err(UpdateRolesError::NoSuchUser);
+  };
+  let mut roles = request.roles;
+  if target_user.is_admin() {
+    roles.insert(Role::Admin);
+  }
+  let Ok(_) = state
+    .table
+    .user
+    .update_roles(UserSearchKey::SubscriberId(&target_user.subscriber_id), &roles)
+    .await
+  else {
+    return Err(UpdateRolesError::RoleUpdateFailed);
+  };
+
+  let event = AuditEvent::new(AuditEventKind::RolesUpdated, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. updated roles of the user.".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{audit_events, create_user, delete_user, list_users, tests::*},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD},
+  };
+
+  #[tokio::test]
+  async fn roles_permit_admin_endpoints() {
+    let state = test_state().await;
+    add_user(&state, "manager", "manager_password").await;
+    add_user(&state, "auditor", "auditor_password").await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let claims = state.crypto.verify_token(&admin.id).unwrap();
+    assert_eq!(claims.custom["roles"], json!(["admin"]));
+
+    // only admins manage roles, and the admin role is not given here
+    let manager = login(&state, "manager", "manager_password").await.token;
+    let update = json!({ "username": "manager", "roles": ["user-manager"] });
+    let res = update_roles(State(state.clone()), remote_addr(), bearer(&manager), request(update.clone())).await;
+    assert!(matches!(res, Err(UpdateRolesError::UnauthorizedUser)));
+    assert!(update_roles(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+    let update = json!({ "username": "auditor", "roles": ["auditor", "admin"] });
+    let res = update_roles(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await;
+    assert!(matches!(res, Err(UpdateRolesError::AdminRoleNotAllowed)));
+    let update = json!({ "username": "auditor", "roles": ["auditor"] });
+    assert!(update_roles(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+
+    // roles are emitted in tokens issued after the update
+    let manager = login(&state, "manager", "manager_password").await.token;
+    let auditor = login(&state, "auditor", "auditor_password").await.token;
+    let claims = state.crypto.verify_token(&manager.id).unwrap();
+    assert_eq!(claims.custom["roles"], json!(["user-manager"]));
+    assert_eq!(claims.custom["iad"], false);
+
+    // user manager creates users but neither reads the audit log nor deletes the admin
+    let new_user = json!({ "auth": { "username": "user", "password": "secret_password" } });
+    assert!(create_user(State(state.clone()), remote_addr(), bearer(&manager), request(new_user)).await.is_ok());
+    let new_user = json!({ "auth": { "username": "user2", "password": "secret_password" } });
+    assert!(create_user(State(state.clone()), remote_addr(), bearer(&auditor), request(new_user)).await.is_err());
+    assert!(audit_events(State(state.clone()), bearer(&manager), request(json!({}))).await.is_err());
+    let target = json!({ "username": "admin" });
+    assert!(delete_user(State(state.clone()), remote_addr(), bearer(&manager), request(target)).await.is_err());
+
+    // auditor reads users and the audit log
+    assert!(list_users(State(state.clone()), bearer(&auditor), request(json!({}))).await.is_ok());
+    let filter = json!({ "kind": "roles_updated" });
+    let res = audit_events(State(state.clone()), bearer(&auditor), request(filter)).await.unwrap();
+    assert_eq!(res.total_events, 2);
+  }
+}
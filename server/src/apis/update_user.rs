@@ -1,17 +1,18 @@
 use super::{request::UpdateUserRequest, response::MessageResponse};
 use crate::{
   constants::ADMIN_USERNAME,
+  entity::{AuditEvent, AuditEventKind, Entity, PasswordPolicyViolation, Permission},
   state::AppState,
-  table::{UserSearchKey, UserTable},
+  table::UserSearchKey,
 };
 use axum::{
-  extract::State,
+  extract::{ConnectInfo, State},
   http::{HeaderMap, StatusCode},
   response::{IntoResponse, Response},
   Json,
 };
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
 
@@ -22,25 +23,44 @@ pub enum UpdateUserError {
   MissingToken,
   InvalidToken,
   ChangeAdminNameProhibited,
+  NoSuchUser,
+  InvalidRequest,
+  WeakPassword(Vec<PasswordPolicyViolation>),
+  BreachedPassword,
+  PasswordNotChanged,
+  PasswordChangeRequired,
 }
 impl IntoResponse for UpdateUserError {
   fn into_response(self) -> Response {
-    let (status, error_message) = match self {
+    let (status, error_message) = match &self {
       UpdateUserError::UserUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "User update failed"),
       UpdateUserError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
       UpdateUserError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
       UpdateUserError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
       UpdateUserError::ChangeAdminNameProhibited => (StatusCode::BAD_REQUEST, "Changing the admin name 'admin' is not allowed."),
+      UpdateUserError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      UpdateUserError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+      UpdateUserError::WeakPassword(_) => (StatusCode::BAD_REQUEST, "Password policy violation"),
+      UpdateUserError::BreachedPassword => (StatusCode::BAD_REQUEST, "Password found in breached password list"),
+      UpdateUserError::PasswordNotChanged => (StatusCode::BAD_REQUEST, "New password must differ from the current one"),
+      UpdateUserError::PasswordChangeRequired => (StatusCode::FORBIDDEN, "Password change required"),
+    };
+    let body = match self {
+      UpdateUserError::WeakPassword(violations) => Json(json!({
+          "error": error_message,
+          "details": violations,
+      })),
+      _ => Json(json!({
+          "error": error_message,
+      })),
     };
-    let body = Json(json!({
-        "error": error_message,
-    }));
     (status, body).into_response()
   }
 }
 
 pub async fn update_user(
   State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
   headers: HeaderMap,
   Json(request): Json<UpdateUserRequest>,
 ) -> Result<Json<MessageResponse>, UpdateUserError> {
@@ -57,7 +77,8 @@ pub async fn update_user(
   let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
     return Err(UpdateUserError::MissingToken);
   };
-  let Ok(claims) = state.crypto.verify_token(&id_token) else {
+  // only this endpoint accepts tokens restricted to changing the password
+  let Ok(claims) = state.verify_password_change_token(&id_token).await else {
     return Err(UpdateUserError::InvalidToken);
   };
 
@@ -71,22 +92,208 @@ pub async fn update_user(
   let Some(u) = opt else {
     return Err(UpdateUserError::UnauthorizedUser);
   };
+  let updates_auth = request.auth.username.is_some() || request.auth.password.is_some();
+  if !updates_auth && request.profile.is_empty() {
+    return Err(UpdateUserError::InvalidRequest);
+  }
+  // users whose password has been reset can do nothing but change their own password
+  if u.must_change_password || claims.custom.contains_key("scope") {
+    let updates_others = request
+      .username
+      .as_ref()
+      .is_some_and(|username| username.as_str() != u.username());
+    if request.auth.password.is_none() || !request.profile.is_empty() || updates_others {
+      return Err(UpdateUserError::PasswordChangeRequired);
+    }
+  }
+  // profile fields are managed only by user managers
+  if !request.profile.is_empty() && !u.roles.permits(Permission::ManageUsers) {
+    return Err(UpdateUserError::UnauthorizedUser);
+  }
+
+  // user other than the requesting one, whose profile is updated by a user manager
+  let target_user = match &request.username {
+    Some(username) if username.as_str() != u.username() => {
+      if !u.roles.permits(Permission::ManageUsers) {
+        return Err(UpdateUserError::UnauthorizedUser);
+      }
+      if updates_auth {
+        return Err(UpdateUserError::InvalidRequest);
+      }
+      let Ok(opt) = state.table.user.find_user(UserSearchKey::Username(username)).await else {
+        return Err(UpdateUserError::UserUpdateFailed);
+      };
+      let Some(target_user) = opt else {
+        return Err(UpdateUserError::NoSuchUser);
+      };
+      // users of the admin role are managed only by admins
+      if target_user.is_admin() && !u.is_admin() {
+        return Err(UpdateUserError::UnauthorizedUser);
+      }
+      Some(target_user)
+    }
+    _ => None,
+  };
+
   // admin cannot change username
   if u.username() == ADMIN_USERNAME && request.auth.username.is_some() {
     return Err(UpdateUserError::ChangeAdminNameProhibited);
   }
 
+  // new password is checked against the new username if it is also changed
+  if let Some(password) = &request.auth.password {
+    let username = request.auth.username.as_ref().unwrap_or(&u.username);
+    if let Err(violations) = state.password_policy.check(username, password) {
+      return Err(UpdateUserError::WeakPassword(violations));
+    }
+    if state.breached_passwords.as_ref().is_some_and(|list| list.contains(password)) {
+      return Err(UpdateUserError::BreachedPassword);
+    }
+    // the temporary password given by an admin must be replaced
+    if u.must_change_password && password.verify(&u.encoded_hash).unwrap_or(false) {
+      return Err(UpdateUserError::PasswordNotChanged);
+    }
+  }
+
   // update the user itself for the given subscriber_id
-  let Ok(_) = state
-    .table
-    .user
-    .update_user(&sub, request.auth.username.as_ref(), request.auth.password.as_ref())
-    .await
-  else {
-    return Err(UpdateUserError::UserUpdateFailed);
-  };
+  if updates_auth {
+    let Ok(_) = state
+      .table
+      .user
+      .update_user(&sub, request.auth.username.as_ref(), request.auth.password.as_ref())
+      .await
+    else {
+      return Err(UpdateUserError::UserUpdateFailed);
+    };
+  }
+
+  let target_user = target_user.unwrap_or(u);
+  if !request.profile.is_empty() {
+    let Ok(_) = state
+      .table
+      .user
+      .update_profile(UserSearchKey::SubscriberId(&target_user.subscriber_id), &request.profile)
+      .await
+    else {
+      return Err(UpdateUserError::UserUpdateFailed);
+    };
+  }
+
+  let target = request.auth.username.as_ref().map(|u| u.as_str()).unwrap_or(target_user.username());
+  let event = AuditEvent::new(AuditEventKind::UserUpdated, Some(remote_addr))
+    .actor(&sub)
+    .target(target);
+  state.table.record_audit_event(event).await;
 
   Ok(Json(MessageResponse {
     message: "ok. updated the user.".to_string(),
   }))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{create_user, create_user::CreateUserError, list_users, tests::*},
+    breached_passwords::{BreachedHashKind, BreachedPasswords},
+    entity::CharacterClass,
+    state::tests::{test_state, TEST_ADMIN_PASSWORD},
+  };
+  use libcommon::token_fields::Field;
+
+  #[tokio::test]
+  async fn admin_manages_profiles_emitted_as_claims() {
+    let state = test_state().await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let new_user = json!({
+      "auth": { "username": "user", "password": "secret_password" },
+      "email": "user@example.com",
+      "display_name": "Example User",
+      "attributes": { "team": "red" },
+    });
+    assert!(create_user(State(state.clone()), remote_addr(), bearer(&admin), request(new_user)).await.is_ok());
+
+    let user = login(&state, "user", "secret_password").await.token;
+    let claims = state.crypto.verify_token(&user.id).unwrap();
+    assert_eq!(claims.custom["email"], "user@example.com");
+    assert_eq!(claims.custom["name"], "Example User");
+
+    // profile is managed only by admins
+    let update = json!({ "display_name": "Renamed" });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&user), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::UnauthorizedUser)));
+    let update = json!({ "username": "user", "display_name": "Renamed", "email": null });
+    assert!(update_user(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+    let update = json!({ "username": "user", "auth": { "password": "new_password" } });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::InvalidRequest)));
+
+    let user = login(&state, "user", "secret_password").await.token;
+    let claims = state.crypto.verify_token(&user.id).unwrap();
+    assert!(!claims.custom.contains_key("email"));
+    assert_eq!(claims.custom["name"], "Renamed");
+
+    let res = list_users(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    let listed = res.users.iter().find(|u| u.username == "user").unwrap();
+    assert_eq!(listed.subscriber_id, user.subscriber_id.as_str());
+    assert_eq!(listed.display_name.as_deref(), Some("Renamed"));
+    assert_eq!(listed.attributes["team"], "red");
+  }
+
+  #[tokio::test]
+  async fn passwords_must_satisfy_policy() {
+    let state = test_state().await;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    let new_user = json!({ "auth": { "username": "user", "password": "short" } });
+    let res = create_user(State(state.clone()), remote_addr(), bearer(&admin), request(new_user)).await;
+    let Err(CreateUserError::WeakPassword(violations)) = res else {
+      panic!("weak password must be rejected");
+    };
+    assert_eq!(violations, vec![PasswordPolicyViolation::TooShort { min_length: 8 }]);
+    let body = CreateUserError::WeakPassword(violations).into_response().into_body();
+    let body: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(body, 1024).await.unwrap()).unwrap();
+    assert_eq!(body["details"], json!([{ "rule": "too_short", "min_length": 8 }]));
+
+    let new_user = json!({ "auth": { "username": "user", "password": "secret_password" } });
+    assert!(create_user(State(state.clone()), remote_addr(), bearer(&admin), request(new_user)).await.is_ok());
+    let user = login(&state, "user", "secret_password").await.token;
+
+    // the new password is checked against the new username
+    let update = json!({ "auth": { "username": "renamed", "password": "Renamed_password" } });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&user), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::WeakPassword(v)) if v == vec![PasswordPolicyViolation::ContainsUsername]));
+
+    let mut state = Arc::try_unwrap(state).ok().unwrap();
+    state.password_policy.required_classes.insert(CharacterClass::Digit);
+    let state = Arc::new(state);
+    let update = json!({ "auth": { "password": "another_password" } });
+    let res = update_user(State(state.clone()), remote_addr(), bearer(&user), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::WeakPassword(_))));
+    let update = json!({ "auth": { "password": "another_password_1" } });
+    assert!(update_user(State(state), remote_addr(), bearer(&user), request(update)).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn breached_passwords_are_rejected() {
+    // SHA-1 of "password1234"
+    let path = std::env::temp_dir().join(format!("breached-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "E6B6AFBD6D76BB5D2041542D7D2E3FAC5BB05593:1\n").unwrap();
+    let mut state = Arc::try_unwrap(test_state().await).ok().unwrap();
+    state.breached_passwords = Some(BreachedPasswords::open(path.to_str().unwrap(), BreachedHashKind::Sha1).unwrap());
+    let state = Arc::new(state);
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+
+    let new_user = json!({ "auth": { "username": "user", "password": "password1234" } });
+    let res = create_user(State(state.clone()), remote_addr(), bearer(&admin), request(new_user)).await;
+    assert!(matches!(res, Err(CreateUserError::BreachedPassword)));
+    let new_user = json!({ "auth": { "username": "user", "password": "secret_password" } });
+    assert!(create_user(State(state.clone()), remote_addr(), bearer(&admin), request(new_user)).await.is_ok());
+
+    let user = login(&state, "user", "secret_password").await.token;
+    let update = json!({ "auth": { "password": "password1234" } });
+    let res = update_user(State(state), remote_addr(), bearer(&user), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserError::BreachedPassword)));
+    std::fs::remove_file(path).unwrap();
+  }
+}
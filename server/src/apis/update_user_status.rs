@@ -0,0 +1,256 @@
+use super::{request::UpdateUserStatusRequest, response::MessageResponse};
+use crate::{
+  constants::ADMIN_USERNAME,
+  entity::{AuditEvent, AuditEventKind, Permission, Roles},
+  state::AppState,
+  table::UserSearchKey,
+};
+use axum::{
+  extract::{ConnectInfo, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use chrono::TimeZone;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use libcommon::token_fields::{IdToken, SubscriberId, TryNewField};
+
+#[derive(Debug)]
+pub enum UpdateUserStatusError {
+  StatusUpdateFailed,
+  UnauthorizedUser,
+  MissingToken,
+  InvalidToken,
+  NoSuchUser,
+  UpdateProhibitedUser,
+  LastAdmin,
+  InvalidRequest,
+}
+impl IntoResponse for UpdateUserStatusError {
+  fn into_response(self) -> Response {
+    let (status, error_message) = match self {
+      UpdateUserStatusError::StatusUpdateFailed => (StatusCode::INTERNAL_SERVER_ERROR, "User status update failed"),
+      UpdateUserStatusError::UnauthorizedUser => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      UpdateUserStatusError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
+      UpdateUserStatusError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+      UpdateUserStatusError::NoSuchUser => (StatusCode::BAD_REQUEST, "No such user"),
+      UpdateUserStatusError::UpdateProhibitedUser => (StatusCode::BAD_REQUEST, "Update prohibited user"),
+      UpdateUserStatusError::LastAdmin => (StatusCode::BAD_REQUEST, "The last active admin cannot be disabled or locked"),
+      UpdateUserStatusError::InvalidRequest => (StatusCode::BAD_REQUEST, "Invalid request"),
+    };
+    let body = Json(json!({
+        "error": error_message,
+    }));
+    (status, body).into_response()
+  }
+}
+
+/// Enable, disable, lock or unlock the given user under the user manager privilege
+pub async fn update_user_status(
+  State(state): State<Arc<AppState>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
+  Json(request): Json<UpdateUserStatusRequest>,
+) -> Result<Json<MessageResponse>, UpdateUserStatusError> {
+  // First retrieve and verify bearer token
+  let Some(Ok(bearer)) = headers.get("authorization").map(|v| v.to_str()) else {
+    return Err(UpdateUserStatusError::MissingToken);
+  };
+  let mut iter = bearer.split(' ');
+  let token_str_opt = if let Some("Bearer") = iter.next() {
+    iter.next()
+  } else {
+    return Err(UpdateUserStatusError::MissingToken);
+  };
+  let Some(Ok(id_token)) = token_str_opt.map(IdToken::new) else {
+    return Err(UpdateUserStatusError::MissingToken);
+  };
+  let Ok(claims) = state.verify_token(&id_token).await else {
+    return Err(UpdateUserStatusError::InvalidToken);
+  };
+
+  // roles in the token must permit to manage users
+  if !Roles::from_claims(&claims).permits(Permission::ManageUsers) {
+    return Err(UpdateUserStatusError::UnauthorizedUser);
+  }
+
+  // just in case, check user existence
+  let Some(Ok(sub)) = claims.custom.get("sub").and_then(|v| v.as_str()).map(SubscriberId::new) else {
+    return Err(UpdateUserStatusError::InvalidToken);
+  };
+  let Ok(opt) = state.table.user.find_user(UserSearchKey::SubscriberId(&sub)).await else {
+    return Err(UpdateUserStatusError::StatusUpdateFailed);
+  };
+  let Some(request_user) = opt else {
+    return Err(UpdateUserStatusError::UnauthorizedUser);
+  };
+  if !request_user.roles.permits(Permission::ManageUsers) {
+    return Err(UpdateUserStatusError::InvalidToken);
+  }
+
+  if request.enabled.is_none() && request.locked_until.is_none() {
+    return Err(UpdateUserStatusError::InvalidRequest);
+  }
+  let locked_until = match request.locked_until {
+    Some(Some(t)) => {
+      let Some(t) = chrono::Local.timestamp_opt(t, 0).single() else {
+        return Err(UpdateUserStatusError::InvalidRequest);
+      };
+      Some(Some(t))
+    }
+    Some(None) => Some(None),
+    None => None,
+  };
+
+  // check if the user exist
+  let Ok(u) = state.table.user.find_user(UserSearchKey::Username(&request.username)).await else {
+    return Err(UpdateUserStatusError::StatusUpdateFailed);
+  };
+  let Some(target_user) = u else {
+    return Err(UpdateUserStatusError::NoSuchUser);
+  };
+  // user managers cannot shut themselves out
+  if request_user.username() == target_user.username() {
+    return Err(UpdateUserStatusError::UpdateProhibitedUser);
+  }
+  // users of the admin role are managed only by admins
+  if target_user.is_admin() && !request_user.is_admin() {
+    return Err(UpdateUserStatusError::UpdateProhibitedUser);
+  }
+
+  // the built-in admin can be unlocked but never shut out via the API, as it cannot be deleted or demoted either
+  let shuts_out = request.enabled == Some(false) || matches!(locked_until, Some(Some(t)) if t > chrono::Local::now());
+  if shuts_out && target_user.username() == ADMIN_USERNAME {
+    return Err(UpdateUserStatusError::UpdateProhibitedUser);
+  }
+
+  let enabled = request.enabled.unwrap_or(target_user.enabled);
+  let locked_until = locked_until.unwrap_or(target_user.locked_until);
+  let Ok(updated) = state
+    .table
+    .user
+    .update_status(UserSearchKey::SubscriberId(&target_user.subscriber_id), enabled, locked_until)
+    .await
+  else {
+    return Err(UpdateUserStatusError::StatusUpdateFailed);
+  };
+  // at least one active admin must remain, which is checked atomically with the update against concurrent updates
+  if !updated {
+    return Err(UpdateUserStatusError::LastAdmin);
+  }
+  // explicitly unlocking the user also forgives past failed logins
+  if request.locked_until == Some(None) {
+    state.reset_failed_logins(&target_user).await;
+  }
+
+  let event = AuditEvent::new(AuditEventKind::UserStatusUpdated, Some(remote_addr))
+    .actor(&sub)
+    .target(target_user.username());
+  state.table.record_audit_event(event).await;
+
+  Ok(Json(MessageResponse {
+    message: "ok. updated the status of the user.".to_string(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::{get_tokens, get_tokens::GetTokensError, list_users, refresh, refresh::RefreshError, tests::*},
+    entity::{Role, TryNewEntity, Username},
+    state::tests::{test_state, TEST_ADMIN_PASSWORD, TEST_CLIENT_ID},
+  };
+  use libcommon::token_fields::Field;
+
+  fn login_request<T: serde::de::DeserializeOwned>() -> Json<T> {
+    request(json!({ "auth": { "username": "user", "password": "user_password" }, "client_id": TEST_CLIENT_ID }))
+  }
+
+  fn admin_login_request<T: serde::de::DeserializeOwned>() -> Json<T> {
+    request(json!({ "auth": { "username": "admin", "password": TEST_ADMIN_PASSWORD }, "client_id": TEST_CLIENT_ID }))
+  }
+
+  #[tokio::test]
+  async fn disabled_and_locked_users_cannot_get_tokens() {
+    let state = test_state().await;
+    add_user(&state, "user", "user_password").await;
+    let user = login(&state, "user", "user_password").await.token;
+    let admin = login(&state, "admin", TEST_ADMIN_PASSWORD).await.token;
+    let refresh_request = json!({ "refresh_token": user.refresh.as_ref().unwrap().as_str(), "client_id": TEST_CLIENT_ID });
+
+    let update = json!({ "username": "user", "enabled": false });
+    let res = update_user_status(State(state.clone()), remote_addr(), bearer(&user), request(update.clone())).await;
+    assert!(matches!(res, Err(UpdateUserStatusError::UnauthorizedUser)));
+    assert!(update_user_status(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request()).await;
+    assert!(matches!(res, Err(GetTokensError::AccountDisabled)));
+    let res = refresh(State(state.clone()), remote_addr(), request(refresh_request.clone())).await;
+    assert!(matches!(res, Err(RefreshError::AccountDisabled)));
+    #[cfg(feature = "blind-signatures")]
+    {
+      use crate::apis::{blind_sign, blind_sign::BlindSignError};
+      let blind_request = json!({
+        "blinded_token_message": "AAAA",
+        "blinded_token_options": { "hash": "Sha384", "deterministic": false, "salt_len": 48 },
+      });
+      let res = blind_sign(State(state.clone()), remote_addr(), bearer(&user), request(blind_request)).await;
+      assert!(matches!(res, Err(BlindSignError::AccountDisabled)));
+    }
+
+    // the subscriber id is kept, and the status is shown in the list
+    let res = list_users(State(state.clone()), bearer(&admin), request(json!({}))).await.unwrap();
+    let listed = res.users.iter().find(|u| u.username == "user").unwrap();
+    assert_eq!(listed.subscriber_id, user.subscriber_id.as_str());
+    assert!(!listed.enabled);
+
+    // locked until the given time, and the refresh token is still usable after unlocking
+    let until = chrono::Local::now().timestamp() + 3600;
+    let update = json!({ "username": "user", "enabled": true, "locked_until": until });
+    assert!(update_user_status(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+    let res = get_tokens(State(state.clone()), remote_addr(), login_request()).await;
+    assert!(matches!(res, Err(GetTokensError::AccountLocked)));
+    let res = refresh(State(state.clone()), remote_addr(), request(refresh_request.clone())).await;
+    assert!(matches!(res, Err(RefreshError::AccountLocked)));
+    let update = json!({ "username": "user", "locked_until": null });
+    assert!(update_user_status(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await.is_ok());
+    assert!(refresh(State(state.clone()), remote_addr(), request(refresh_request)).await.is_ok());
+    assert!(get_tokens(State(state.clone()), remote_addr(), login_request()).await.is_ok());
+
+    let update = json!({ "username": "admin", "enabled": false });
+    let res = update_user_status(State(state.clone()), remote_addr(), bearer(&admin), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserStatusError::UpdateProhibitedUser)));
+  }
+
+  #[tokio::test]
+  async fn built_in_admin_cannot_be_shut_out() {
+    let state = test_state().await;
+    add_user(&state, "operator", "operator_password").await;
+    let admin_roles = [Role::Admin].into_iter().collect::<Roles>();
+    let operator_name = Username::new("operator").unwrap();
+    let search_key = UserSearchKey::Username(&operator_name);
+    assert!(state.table.user.update_roles(search_key, &admin_roles).await.unwrap());
+    let operator = login(&state, "operator", "operator_password").await.token;
+
+    // even other admins can neither disable nor lock the built-in admin
+    let update = json!({ "username": "admin", "enabled": false });
+    let res = update_user_status(State(state.clone()), remote_addr(), bearer(&operator), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserStatusError::UpdateProhibitedUser)));
+    let until = chrono::Local::now().timestamp() + 3600;
+    let update = json!({ "username": "admin", "locked_until": until });
+    let res = update_user_status(State(state.clone()), remote_addr(), bearer(&operator), request(update)).await;
+    assert!(matches!(res, Err(UpdateUserStatusError::UpdateProhibitedUser)));
+
+    // but can unlock it after failed logins
+    let admin_name = Username::new(ADMIN_USERNAME).unwrap();
+    let until = chrono::Local::now() + chrono::Duration::minutes(10);
+    state.table.user.lock(UserSearchKey::Username(&admin_name), until).await.unwrap();
+    let update = json!({ "username": "admin", "locked_until": null });
+    assert!(update_user_status(State(state.clone()), remote_addr(), bearer(&operator), request(update))
+      .await
+      .is_ok());
+    assert!(get_tokens(State(state.clone()), remote_addr(), admin_login_request()).await.is_ok());
+  }
+}
@@ -2,23 +2,213 @@ use crate::{
   constants::{ARGON2_CONFIG, ARGON2_SALT_LEN},
   error::*,
 };
+use argon2::Config;
+use base64::{engine::general_purpose, Engine as _};
 use rand::prelude::*;
+use std::{collections::HashMap, sync::OnceLock};
+
+/// Costs of Argon2 to hash passwords. Hashes with other costs are still verified, and replaced on the next login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+  /// Memory size in KiB
+  pub mem_cost: u32,
+  /// Number of iterations
+  pub time_cost: u32,
+  /// Degree of parallelism
+  pub lanes: u32,
+}
+
+impl Default for Argon2Params {
+  fn default() -> Self {
+    Self {
+      mem_cost: ARGON2_CONFIG.mem_cost,
+      time_cost: ARGON2_CONFIG.time_cost,
+      lanes: ARGON2_CONFIG.lanes,
+    }
+  }
+}
+
+impl Argon2Params {
+  pub fn validate(&self) -> Result<()> {
+    if self.time_cost < 1 || self.lanes < 1 {
+      bail!("Argon2 time cost and parallelism must be at least 1");
+    }
+    if self.mem_cost < 8 * self.lanes {
+      bail!("Argon2 memory cost must be at least 8 KiB per lane");
+    }
+    Ok(())
+  }
+
+  fn config(&self) -> Config<'static> {
+    Config {
+      mem_cost: self.mem_cost,
+      time_cost: self.time_cost,
+      lanes: self.lanes,
+      ..ARGON2_CONFIG
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+/// Peppers passed to Argon2 as the secret, which are kept out of the database. Only the current one is used for new
+/// hashes, and the others are kept to verify hashes computed before the rotation.
+pub struct Peppers {
+  current_id: Option<String>,
+  secrets: HashMap<String, Vec<u8>>,
+}
+
+impl Peppers {
+  /// Peppers of the given ids. The current id can be omitted if only one pepper is given.
+  pub fn new(secrets: HashMap<String, Vec<u8>>, current_id: Option<String>) -> Result<Self> {
+    for (id, secret) in secrets.iter() {
+      if id.is_empty() || id.len() > 16 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        bail!("Pepper id must be 1 to 16 alphanumeric characters: {id}");
+      }
+      if secret.is_empty() {
+        bail!("Pepper must not be empty: {id}");
+      }
+    }
+    let current_id = match current_id {
+      Some(id) if secrets.contains_key(&id) => Some(id),
+      Some(id) => bail!("No pepper is given for the current pepper id: {id}"),
+      None if secrets.len() > 1 => bail!("Current pepper id must be specified when several peppers are given"),
+      None => secrets.keys().next().cloned(),
+    };
+    Ok(Self { current_id, secrets })
+  }
+
+  fn current(&self) -> Option<(&str, &[u8])> {
+    let id = self.current_id.as_deref()?;
+    Some((id, self.secrets.get(id)?.as_slice()))
+  }
+
+  /// Pepper of the `keyid` recorded in a hash, which is the B64 encoding of the pepper id. Hashes recording the raw id
+  /// are also verified, and need rehash.
+  fn find(&self, keyid: &str) -> Option<&[u8]> {
+    self
+      .secrets
+      .iter()
+      .find(|(id, _)| encode_pepper_id(id) == keyid)
+      .or_else(|| self.secrets.get_key_value(keyid))
+      .map(|(_, secret)| secret.as_slice())
+  }
+}
+
+/// Encode the pepper id as `keyid`, whose value is B64, i.e., base64 without padding, in the PHC string format
+fn encode_pepper_id(id: &str) -> String {
+  general_purpose::STANDARD_NO_PAD.encode(id)
+}
+
+/// Peppers configured at startup
+static PEPPERS: OnceLock<Peppers> = OnceLock::new();
+
+/// Set the peppers for the process. This must be called at most once, before any password is hashed or verified.
+pub fn set_peppers(peppers: Peppers) -> Result<()> {
+  if PEPPERS.set(peppers).is_err() {
+    bail!("Peppers are already set");
+  }
+  Ok(())
+}
+
+fn peppers() -> &'static Peppers {
+  PEPPERS.get_or_init(Peppers::default)
+}
+
+/// Costs configured at startup, which are used for every new hash in the process
+static ARGON2_PARAMS: OnceLock<Argon2Params> = OnceLock::new();
+
+/// Set the costs of Argon2 for the process. This must be called at most once, before any password is hashed.
+pub fn set_argon2_params(params: Argon2Params) -> Result<()> {
+  params.validate()?;
+  if ARGON2_PARAMS.set(params).is_err() {
+    bail!("Argon2 parameters are already set");
+  }
+  Ok(())
+}
+
+pub fn argon2_params() -> Argon2Params {
+  *ARGON2_PARAMS.get_or_init(Argon2Params::default)
+}
 
 pub fn generate_argon2(password: &str) -> Result<String> {
+  hash_with(password, &argon2_params(), peppers())
+}
+
+pub fn verify_argon2(password: &str, encoded_hash: &str) -> Result<bool> {
+  verify_with(password, encoded_hash, peppers())
+}
+
+/// Whether the encoded hash was computed with an algorithm, costs or a pepper other than the current ones
+pub fn argon2_needs_rehash(encoded_hash: &str) -> bool {
+  needs_rehash_with(encoded_hash, &argon2_params(), peppers())
+}
+
+fn hash_with(password: &str, params: &Argon2Params, peppers: &Peppers) -> Result<String> {
   let mut salt = [0u8; ARGON2_SALT_LEN];
   rand::thread_rng().fill_bytes(&mut salt);
 
-  let hash = argon2::hash_encoded(password.as_bytes(), &salt, &ARGON2_CONFIG)?;
+  let Some((pepper_id, pepper)) = peppers.current() else {
+    let hash = argon2::hash_encoded(password.as_bytes(), &salt, &params.config())?;
+    return Ok(hash);
+  };
+  let config = Config {
+    secret: pepper,
+    ..params.config()
+  };
+  let hash = argon2::hash_encoded(password.as_bytes(), &salt, &config)?;
 
-  Ok(hash)
+  // record the pepper id as `keyid` in the parameters like "$argon2id$v=19$m=4096,t=3,p=4,keyid=<B64 id>$<salt>$<hash>"
+  let mut fields = hash.split('$').map(str::to_string).collect::<Vec<_>>();
+  let Some(params) = fields.get_mut(3) else {
+    bail!("Unexpected encoding of Argon2 hash");
+  };
+  params.push_str(&format!(",keyid={}", encode_pepper_id(pepper_id)));
+  Ok(fields.join("$"))
 }
 
-pub fn verify_argon2(password: &str, encoded_hash: &str) -> Result<bool> {
-  let matches = argon2::verify_encoded(encoded_hash, password.as_bytes())?;
+fn verify_with(password: &str, encoded_hash: &str, peppers: &Peppers) -> Result<bool> {
+  let (encoded_hash, pepper_id) = split_pepper_id(encoded_hash);
+  let pepper = match pepper_id {
+    Some(keyid) => {
+      let Some(pepper) = peppers.find(keyid) else {
+        bail!("Password is hashed with an unknown pepper: {keyid}");
+      };
+      pepper
+    }
+    None => &[],
+  };
+  let matches = argon2::verify_encoded_ext(&encoded_hash, password.as_bytes(), pepper, &[])?;
 
   Ok(matches)
 }
 
+fn needs_rehash_with(encoded_hash: &str, params: &Argon2Params, peppers: &Peppers) -> bool {
+  let (encoded_hash, pepper_id) = split_pepper_id(encoded_hash);
+  if pepper_id.map(str::to_string) != peppers.current().map(|(id, _)| encode_pepper_id(id)) {
+    return true;
+  }
+  // like "$argon2id$v=19$m=4096,t=3,p=4$<salt>$<hash>"
+  let mut fields = encoded_hash.split('$').skip(1);
+  let (Some(variant), Some(version), Some(hash_params)) = (fields.next(), fields.next(), fields.next()) else {
+    return true;
+  };
+  let expected_params = format!("m={},t={},p={}", params.mem_cost, params.time_cost, params.lanes);
+  variant != ARGON2_CONFIG.variant.as_lowercase_str()
+    || version != format!("v={}", ARGON2_CONFIG.version.as_u32())
+    || hash_params != expected_params
+}
+
+/// Split the encoded pepper id recorded as `keyid` from the encoded hash, which is then understood by rust-argon2
+fn split_pepper_id(encoded_hash: &str) -> (String, Option<&str>) {
+  let Some(params) = encoded_hash.split('$').nth(3) else {
+    return (encoded_hash.to_string(), None);
+  };
+  let pepper_id = params.split(',').find_map(|p| p.strip_prefix("keyid="));
+  let mut fields = encoded_hash.split('$').map(str::to_string).collect::<Vec<_>>();
+  fields[3] = params.split(',').filter(|p| !p.starts_with("keyid=")).collect::<Vec<_>>().join(",");
+  (fields.join("$"), pepper_id)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -30,9 +220,65 @@ mod tests {
     let hash = hash.unwrap();
     assert_eq!(hash.len(), 117);
     assert!(hash.starts_with("$argon2id$v=19$m=4096,t=3,p=4$"));
+    assert!(!argon2_needs_rehash(&hash));
 
     let verify = verify_argon2(password, &hash);
     assert!(verify.is_ok());
     assert!(verify.unwrap());
   }
+
+  #[test]
+  fn hashes_with_other_costs_need_rehash() {
+    let weaker = Argon2Params {
+      mem_cost: 1024,
+      time_cost: 1,
+      lanes: 1,
+    };
+    let salt = [0u8; ARGON2_SALT_LEN];
+    let hash = argon2::hash_encoded(b"password", &salt, &weaker.config()).unwrap();
+    assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    assert!(argon2_needs_rehash(&hash));
+    assert!(verify_argon2("password", &hash).unwrap());
+    assert!(argon2_needs_rehash("not an argon2 hash"));
+
+    assert!(Argon2Params { lanes: 4, ..weaker }.validate().is_ok());
+    assert!(Argon2Params { mem_cost: 16, lanes: 4, ..weaker }.validate().is_err());
+  }
+
+  #[test]
+  fn peppers_are_rotated() {
+    let params = Argon2Params::default();
+    let secrets = HashMap::from([("old".to_string(), b"old pepper".to_vec()), ("new".to_string(), b"new pepper".to_vec())]);
+    let old = Peppers::new(HashMap::from([("old".to_string(), b"old pepper".to_vec())]), None).unwrap();
+    let rotated = Peppers::new(secrets.clone(), Some("new".to_string())).unwrap();
+    assert!(Peppers::new(secrets.clone(), None).is_err());
+    assert!(Peppers::new(secrets, Some("unknown".to_string())).is_err());
+
+    let hash = hash_with("password", &params, &old).unwrap();
+    assert!(hash.starts_with("$argon2id$v=19$m=4096,t=3,p=4,keyid=b2xk$"));
+    assert!(verify_with("password", &hash, &old).unwrap());
+    assert!(!verify_with("wrong", &hash, &old).unwrap());
+    // the pepper is required to verify the hash
+    assert!(verify_with("password", &hash, &Peppers::default()).is_err());
+    let (unpeppered, _) = split_pepper_id(&hash);
+    assert!(!verify_argon2("password", &unpeppered).unwrap());
+
+    // hashes with the old pepper are still verified after the rotation, and need rehash
+    assert!(!needs_rehash_with(&hash, &params, &old));
+    assert!(verify_with("password", &hash, &rotated).unwrap());
+    assert!(needs_rehash_with(&hash, &params, &rotated));
+    let rehashed = hash_with("password", &params, &rotated).unwrap();
+    assert!(rehashed.contains(",keyid=bmV3$"));
+    assert!(!needs_rehash_with(&rehashed, &params, &rotated));
+
+    // hashes recording the raw pepper id are still verified, and need rehash
+    let raw = hash.replace(",keyid=b2xk$", ",keyid=old$");
+    assert!(verify_with("password", &raw, &old).unwrap());
+    assert!(needs_rehash_with(&raw, &params, &old));
+
+    // hashes without pepper need rehash once a pepper is introduced
+    let plain = hash_with("password", &params, &Peppers::default()).unwrap();
+    assert!(verify_with("password", &plain, &old).unwrap());
+    assert!(needs_rehash_with(&plain, &params, &old));
+  }
 }
@@ -0,0 +1,147 @@
+use crate::{
+  entity::{Entity, Password},
+  error::*,
+};
+use md4::Md4;
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+use std::{cmp::Ordering, fs::File, str::FromStr};
+
+/// Hash function of the passwords listed in a breached password file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreachedHashKind {
+  Sha1,
+  /// MD4 of the UTF-16LE password used by Windows
+  Ntlm,
+}
+
+impl BreachedHashKind {
+  /// Length of the hash in hex
+  fn hex_len(&self) -> usize {
+    match self {
+      BreachedHashKind::Sha1 => 40,
+      BreachedHashKind::Ntlm => 32,
+    }
+  }
+
+  /// Uppercase hex hash of the password as listed in Have I Been Pwned dumps
+  fn hash(&self, password: &str) -> String {
+    match self {
+      BreachedHashKind::Sha1 => format!("{:X}", Sha1::digest(password.as_bytes())),
+      BreachedHashKind::Ntlm => {
+        let utf16 = password.encode_utf16().flat_map(|c| c.to_le_bytes()).collect::<Vec<_>>();
+        format!("{:X}", Md4::digest(utf16))
+      }
+    }
+  }
+}
+
+impl FromStr for BreachedHashKind {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let kind = match s {
+      "sha1" => BreachedHashKind::Sha1,
+      "ntlm" => BreachedHashKind::Ntlm,
+      _ => bail!("Unknown hash of breached passwords: {s}"),
+    };
+    Ok(kind)
+  }
+}
+
+/// Memory-mapped list of hashes of breached passwords in the format of the Have I Been Pwned downloadable dump,
+/// i.e., lines like `HASH:COUNT` ordered by the hash. Hashes may be truncated to prefixes of the same length,
+/// and a password matches if its hash starts with a listed prefix. The list is searched by bisection without reading
+/// the whole file.
+pub struct BreachedPasswords {
+  kind: BreachedHashKind,
+  /// Length of the hashes or their prefixes in the file
+  prefix_len: usize,
+  mmap: Mmap,
+}
+
+impl BreachedPasswords {
+  pub fn open(path: &str, kind: BreachedHashKind) -> Result<Self> {
+    let file = File::open(path)?;
+    // SAFETY: the file is opened read-only and must not be modified while the server runs.
+    let mmap = unsafe { Mmap::map(&file)? };
+    let first_line = mmap.split(|b| *b == b'\n').next().unwrap_or_default();
+    let prefix = line_hash(first_line);
+    if prefix.is_empty() || prefix.len() > kind.hex_len() || !prefix.iter().all(u8::is_ascii_hexdigit) {
+      bail!("Breached password file must list {}-digit hex hashes or their prefixes", kind.hex_len());
+    }
+    Ok(Self {
+      kind,
+      prefix_len: prefix.len(),
+      mmap,
+    })
+  }
+
+  /// Whether the password is found in the list
+  pub fn contains(&self, password: &Password) -> bool {
+    let hash = self.kind.hash(password.as_str());
+    let prefix = &hash.as_bytes()[..self.prefix_len];
+    let data = &self.mmap[..];
+
+    // `lo` and `hi` always point to the beginning of lines
+    let (mut lo, mut hi) = (0, data.len());
+    while lo < hi {
+      let mid = lo + (hi - lo) / 2;
+      let start = data[lo..mid].iter().rposition(|b| *b == b'\n').map_or(lo, |pos| lo + pos + 1);
+      let end = data[start..hi].iter().position(|b| *b == b'\n').map_or(hi, |pos| start + pos);
+      let listed = line_hash(&data[start..end]).iter().map(u8::to_ascii_uppercase);
+      match listed.cmp(prefix.iter().copied()) {
+        Ordering::Equal => return true,
+        Ordering::Less => lo = end + 1,
+        Ordering::Greater => hi = start,
+      }
+    }
+    false
+  }
+}
+
+/// Hash part of a line like `HASH:COUNT`
+fn line_hash(line: &[u8]) -> &[u8] {
+  let hash = line.split(|b| *b == b':').next().unwrap_or_default();
+  hash.strip_suffix(b"\r").unwrap_or(hash)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::entity::TryNewEntity;
+
+  fn write_list(content: &str) -> String {
+    let path = std::env::temp_dir().join(format!("breached-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&path, content).unwrap();
+    path.to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn breached_passwords_are_found_by_bisection() {
+    let password = Password::new("password").unwrap();
+    let other = Password::new("correct horse battery staple").unwrap();
+
+    // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+    let path = write_list(
+      "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+       5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004\r\n\
+       5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD9:1\r\n\
+       FFFFFFFEE791CBAC0F6305CAF0CEE06BBE131160:2\r\n",
+    );
+    let list = BreachedPasswords::open(&path, BreachedHashKind::Sha1).unwrap();
+    assert!(list.contains(&password));
+    assert!(!list.contains(&other));
+    std::fs::remove_file(path).unwrap();
+
+    // NTLM hash of "password" is 8846F7EAEE8FB117AD06BDD830B7586C, listed by its prefix
+    let path = write_list("0000A:1\n8846F:3\nFFFFF:2");
+    let list = BreachedPasswords::open(&path, BreachedHashKind::Ntlm).unwrap();
+    assert!(list.contains(&password));
+    assert!(!BreachedPasswords::open(&path, BreachedHashKind::Sha1).unwrap().contains(&password));
+    std::fs::remove_file(path).unwrap();
+
+    let path = write_list("not a hash list\n");
+    assert!(BreachedPasswords::open(&path, BreachedHashKind::Sha1).is_err());
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -2,8 +2,15 @@ mod parse_opts;
 mod subcmd_admin;
 mod subcmd_run;
 
-use crate::error::Result;
+use crate::{
+  argon2::{Argon2Params, Peppers},
+  constants::{IN_MEMORY_DB_FILE_PATH, PASSWORD_MIN_LEN},
+  entity::{CharacterClass, PasswordPolicy},
+  error::*,
+};
 use async_trait::async_trait;
+use clap::{Arg, ArgAction};
+use std::{collections::HashMap, env, fs};
 use url::Url;
 
 pub use parse_opts::parse_opts;
@@ -28,3 +35,142 @@ pub(crate) fn verify_url(arg_val: &str) -> Result<String, String> {
   }
   Ok(url.to_string())
 }
+
+/// Options of the Argon2 costs shared by subcommands hashing passwords
+pub(crate) fn argon2_args() -> [Arg; 3] {
+  let default_params = Argon2Params::default();
+  [
+    Arg::new("argon2_memory")
+      .long("argon2-memory")
+      .value_name("KiB")
+      .value_parser(clap::value_parser!(u32).range(8..))
+      .help(format!("Memory cost of Argon2 to hash passwords in KiB [default: {}]", default_params.mem_cost)),
+    Arg::new("argon2_iterations")
+      .long("argon2-iterations")
+      .value_name("COUNT")
+      .value_parser(clap::value_parser!(u32).range(1..))
+      .help(format!("Time cost of Argon2 to hash passwords [default: {}]", default_params.time_cost)),
+    Arg::new("argon2_parallelism")
+      .long("argon2-parallelism")
+      .value_name("LANES")
+      .value_parser(clap::value_parser!(u32).range(1..))
+      .help(format!("Parallelism of Argon2 to hash passwords [default: {}]", default_params.lanes)),
+  ]
+}
+
+/// Argon2 costs given by the options
+pub(crate) fn argon2_params(sub_m: &clap::ArgMatches) -> Argon2Params {
+  let default_params = Argon2Params::default();
+  let param = |id: &str| sub_m.get_one::<u32>(id).copied();
+  Argon2Params {
+    mem_cost: param("argon2_memory").unwrap_or(default_params.mem_cost),
+    time_cost: param("argon2_iterations").unwrap_or(default_params.time_cost),
+    lanes: param("argon2_parallelism").unwrap_or(default_params.lanes),
+  }
+}
+
+/// Options of the peppers shared by subcommands hashing passwords
+pub(crate) fn pepper_args() -> [Arg; 3] {
+  [
+    Arg::new("pepper_file")
+      .long("pepper-file")
+      .value_name("ID=PATH")
+      .value_parser(parse_pepper_source)
+      .action(ArgAction::Append)
+      .help("File containing a pepper mixed into password hashes, given with its id of up to 16 alphanumeric characters. Can be specified multiple times to keep accepting old peppers"),
+    Arg::new("pepper_env")
+      .long("pepper-env")
+      .value_name("ID=VAR")
+      .value_parser(parse_pepper_source)
+      .action(ArgAction::Append)
+      .help("Environment variable containing a pepper, given with its id like --pepper-file. Can be specified multiple times"),
+    Arg::new("pepper_id")
+      .long("pepper-id")
+      .value_name("ID")
+      .help("Id of the pepper for new password hashes. Required if several peppers are given"),
+  ]
+}
+
+/// Peppers given by the options
+pub(crate) fn peppers(sub_m: &clap::ArgMatches) -> Result<Peppers> {
+  let mut secrets = HashMap::new();
+  for (id, path) in sub_m.get_many::<(String, String)>("pepper_file").unwrap_or_default() {
+    let Ok(secret) = fs::read(path) else {
+      bail!("Failed to read pepper: {id}");
+    };
+    if secrets.insert(id.to_string(), secret).is_some() {
+      bail!("Pepper id is given more than once: {id}");
+    }
+  }
+  for (id, var) in sub_m.get_many::<(String, String)>("pepper_env").unwrap_or_default() {
+    let Ok(secret) = env::var(var) else {
+      bail!("Failed to read pepper from environment variable: {var}");
+    };
+    if secrets.insert(id.to_string(), secret.into_bytes()).is_some() {
+      bail!("Pepper id is given more than once: {id}");
+    }
+  }
+  Peppers::new(secrets, sub_m.get_one::<String>("pepper_id").cloned())
+}
+
+/// Parse a pepper source given with its id like "2024=/etc/pepper"
+fn parse_pepper_source(arg_val: &str) -> Result<(String, String), String> {
+  match arg_val.split_once('=') {
+    Some((id, source)) if !id.is_empty() && !source.is_empty() => Ok((id.to_string(), source.to_string())),
+    _ => Err("Must be given as ID=SOURCE".to_string()),
+  }
+}
+
+/// Options of the password policy shared by subcommands setting passwords
+pub(crate) fn password_policy_args() -> [Arg; 2] {
+  [
+    Arg::new("password_min_length")
+      .long("password-min-length")
+      .value_name("LEN")
+      .value_parser(clap::value_parser!(u32).range(1..))
+      .help(format!("Minimum number of characters in passwords [default: {PASSWORD_MIN_LEN}]")),
+    Arg::new("password_required_classes")
+      .long("password-required-classes")
+      .value_name("CLASSES")
+      .value_parser(parse_character_classes)
+      .help("Classes of characters every password must contain, split with comma like 'uppercase,digit'. Classes are 'lowercase', 'uppercase', 'digit' and 'symbol'"),
+  ]
+}
+
+/// Password policy given by the options. Passwords containing the username are always rejected.
+pub(crate) fn password_policy(sub_m: &clap::ArgMatches) -> PasswordPolicy {
+  let default_policy = PasswordPolicy::default();
+  PasswordPolicy {
+    min_length: sub_m
+      .get_one::<u32>("password_min_length")
+      .map(|len| *len as usize)
+      .unwrap_or(default_policy.min_length),
+    required_classes: sub_m
+      .get_one::<Vec<CharacterClass>>("password_required_classes")
+      .map(|classes| classes.iter().copied().collect())
+      .unwrap_or(default_policy.required_classes),
+  }
+}
+
+/// Parse character classes split with comma like "uppercase,digit"
+fn parse_character_classes(arg_val: &str) -> Result<Vec<CharacterClass>, String> {
+  arg_val
+    .split(',')
+    .map(|s| s.trim().parse::<CharacterClass>().map_err(|e| e.to_string()))
+    .collect()
+}
+
+/// Database url given by `--db-url`, or the sqlite url of `--db-file-path` if no url is given.
+/// `--db-file-path :memory:` selects the in-memory storage.
+pub(crate) fn db_url(sub_m: &clap::ArgMatches) -> Result<String> {
+  if let Some(url) = sub_m.get_one::<String>("db_url") {
+    return Ok(url.to_string());
+  }
+  match sub_m.get_one::<String>("db_file_path") {
+    Some(p) if p == IN_MEMORY_DB_FILE_PATH => Ok("memory:".to_string()),
+    Some(p) => Ok(format!("sqlite:{}", p)),
+    None => {
+      bail!("Database path must be specified");
+    }
+  }
+}
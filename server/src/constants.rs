@@ -7,14 +7,28 @@ pub const DEFAULT_PORT: &str = "3000";
 pub const ADMIN_USERNAME: &str = "admin";
 /// Default password length when random password is needed at the user creation.
 pub const PASSWORD_LEN: usize = 32;
+/// Length of client secrets randomly generated for confidential clients
+pub const CLIENT_SECRET_LEN: usize = 48;
+
+// OAuth 2.0 grant types defined in RFC 6749
+pub const PASSWORD_GRANT_TYPE: &str = "password";
+pub const REFRESH_TOKEN_GRANT_TYPE: &str = "refresh_token";
+pub const CLIENT_CREDENTIALS_GRANT_TYPE: &str = "client_credentials";
+/// Default minimum length of passwords given by users and admins
+pub const PASSWORD_MIN_LEN: usize = 8;
 /// Default environment variable of admin password
 pub const ADMIN_PASSWORD_VAR: &str = "ADMIN_PASSWORD";
 
 // Database settings
 pub const DB_FILE_PATH: &str = "./users.db";
+/// Database file path to keep users and refresh tokens only in memory
+pub const IN_MEMORY_DB_FILE_PATH: &str = ":memory:";
 pub const USER_TABLE_NAME: &str = "users";
-// pub const ALLOWED_CLIENT_TABLE_NAME: &str = "client_ids";
+pub const CLIENT_TABLE_NAME: &str = "clients";
 pub const REFRESH_TOKEN_TABLE_NAME: &str = "tokens";
+pub const AUDIT_EVENT_TABLE_NAME: &str = "audit_events";
+pub const TOTP_TABLE_NAME: &str = "totp_factors";
+pub const RECOVERY_CODE_TABLE_NAME: &str = "recovery_codes";
 
 // Argon2 password hashing params
 use argon2::{Config, Variant, Version};
@@ -33,12 +47,48 @@ pub const ARGON2_SALT_LEN: usize = 32;
 // ID Token settings
 /// Default client ID if no client id check is required
 pub const DEFAUTL_CLIENT_ID: &str = "none";
-/// Default duration of refresh token validity in minutes
-/// TODO: 30days, clapで設定できるように
+/// Default duration of refresh token validity in minutes [default: 30 days]
 pub const REFRESH_TOKEN_DURATION_MINS: usize = 30 * 24 * 60;
+/// Default period to prune expired refresh tokens in minutes
+pub const REFRESH_TOKEN_PRUNE_PERIOD_MINS: u64 = 60;
+
+// Account lockout settings
+/// Default number of consecutive failed logins to lock the account
+pub const LOCKOUT_THRESHOLD: u32 = 5;
+/// Default period to lock the account in minutes
+pub const LOCKOUT_DURATION_MINS: u32 = 15;
+/// Maximum period to lock the account in minutes when the period grows exponentially [default: 1 day]
+pub const MAX_LOCKOUT_DURATION_MINS: i64 = 24 * 60;
+
+// TOTP settings, which are the defaults of authenticator apps
+/// Length of TOTP secrets in bytes, i.e., the output size of HMAC-SHA1
+pub const TOTP_SECRET_LEN: usize = 20;
+/// Number of digits of TOTP codes
+pub const TOTP_DIGITS: u32 = 6;
+/// Period of a TOTP time step in seconds
+pub const TOTP_STEP_SECS: i64 = 30;
+/// Number of steps before and after the current one whose codes are accepted, allowing for clock skew
+pub const TOTP_SKEW_STEPS: i64 = 1;
+/// Issuer shown in authenticator apps if the token issuer has no host
+pub const TOTP_DEFAULT_ISSUER: &str = "rust-token-server";
+/// Number of recovery codes issued when TOTP is enabled
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Number of characters of a recovery code, excluding the hyphen in the middle
+pub const RECOVERY_CODE_LEN: usize = 10;
+
+/// Maximum number of client IPs whose request rates are tracked for each route. The least recently seen one is forgotten
+/// to track a new one.
+pub const MAX_RATE_LIMITED_CLIENTS: usize = 100_000;
+
+/// Maximum length of free-form user attributes serialized in JSON
+pub const MAX_USER_ATTRIBUTES_LEN: usize = 4096;
 
 /// Maximum number of users per page in the list user API
 pub const MAX_USERS_PER_PAGE: u32 = 20;
+/// Maximum number of events per page in the audit event API
+pub const MAX_AUDIT_EVENTS_PER_PAGE: u32 = 50;
+/// Maximum number of clients per page in the list client API
+pub const MAX_CLIENTS_PER_PAGE: u32 = 20;
 
 #[cfg(feature = "blind-signatures")]
 /// Default RSA key size for blind signature
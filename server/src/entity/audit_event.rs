@@ -0,0 +1,156 @@
+use crate::error::*;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, str::FromStr};
+
+use libcommon::token_fields::{ClientId, SubscriberId};
+
+/// Kind of security events recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+  Login,
+  LoginFailed,
+  AccountLocked,
+  Refresh,
+  UserCreated,
+  UserUpdated,
+  UserDeleted,
+  RolesUpdated,
+  UserStatusUpdated,
+  UserLoggedOut,
+  PasswordReset,
+  ClientCreated,
+  ClientUpdated,
+  ClientDeleted,
+  ClientCredentials,
+  ClientCredentialsFailed,
+  TotpEnabled,
+  TotpDisabled,
+  RecoveryCodeUsed,
+  BlindSignatureIssued,
+  AdminPasswordChanged,
+}
+
+impl AuditEventKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AuditEventKind::Login => "login",
+      AuditEventKind::LoginFailed => "login_failed",
+      AuditEventKind::AccountLocked => "account_locked",
+      AuditEventKind::Refresh => "refresh",
+      AuditEventKind::UserCreated => "user_created",
+      AuditEventKind::UserUpdated => "user_updated",
+      AuditEventKind::UserDeleted => "user_deleted",
+      AuditEventKind::RolesUpdated => "roles_updated",
+      AuditEventKind::UserStatusUpdated => "user_status_updated",
+      AuditEventKind::UserLoggedOut => "user_logged_out",
+      AuditEventKind::PasswordReset => "password_reset",
+      AuditEventKind::ClientCreated => "client_created",
+      AuditEventKind::ClientUpdated => "client_updated",
+      AuditEventKind::ClientDeleted => "client_deleted",
+      AuditEventKind::ClientCredentials => "client_credentials",
+      AuditEventKind::ClientCredentialsFailed => "client_credentials_failed",
+      AuditEventKind::TotpEnabled => "totp_enabled",
+      AuditEventKind::TotpDisabled => "totp_disabled",
+      AuditEventKind::RecoveryCodeUsed => "recovery_code_used",
+      AuditEventKind::BlindSignatureIssued => "blind_signature_issued",
+      AuditEventKind::AdminPasswordChanged => "admin_password_changed",
+    }
+  }
+}
+
+impl FromStr for AuditEventKind {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let kind = match s {
+      "login" => AuditEventKind::Login,
+      "login_failed" => AuditEventKind::LoginFailed,
+      "account_locked" => AuditEventKind::AccountLocked,
+      "refresh" => AuditEventKind::Refresh,
+      "user_created" => AuditEventKind::UserCreated,
+      "user_updated" => AuditEventKind::UserUpdated,
+      "user_deleted" => AuditEventKind::UserDeleted,
+      "roles_updated" => AuditEventKind::RolesUpdated,
+      "user_status_updated" => AuditEventKind::UserStatusUpdated,
+      "user_logged_out" => AuditEventKind::UserLoggedOut,
+      "password_reset" => AuditEventKind::PasswordReset,
+      "client_created" => AuditEventKind::ClientCreated,
+      "client_updated" => AuditEventKind::ClientUpdated,
+      "client_deleted" => AuditEventKind::ClientDeleted,
+      "client_credentials" => AuditEventKind::ClientCredentials,
+      "client_credentials_failed" => AuditEventKind::ClientCredentialsFailed,
+      "totp_enabled" => AuditEventKind::TotpEnabled,
+      "totp_disabled" => AuditEventKind::TotpDisabled,
+      "recovery_code_used" => AuditEventKind::RecoveryCodeUsed,
+      "blind_signature_issued" => AuditEventKind::BlindSignatureIssued,
+      "admin_password_changed" => AuditEventKind::AdminPasswordChanged,
+      _ => bail!("Unknown audit event kind: {s}"),
+    };
+    Ok(kind)
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Security event recorded in the audit log
+pub struct AuditEvent {
+  pub timestamp: DateTime<Local>,
+  pub kind: AuditEventKind,
+  /// Subscriber id of the user who caused the event, if known
+  pub actor: Option<SubscriberId>,
+  /// Username the event is about, e.g., the user deleted by an admin
+  pub target: Option<String>,
+  pub client_id: Option<ClientId>,
+  /// IP address of the peer who sent the request. None for events caused from the command line.
+  pub remote_addr: Option<String>,
+}
+
+impl AuditEvent {
+  /// Event of the kind happening now
+  pub fn new(kind: AuditEventKind, remote_addr: Option<SocketAddr>) -> Self {
+    Self {
+      timestamp: Local::now(),
+      kind,
+      actor: None,
+      target: None,
+      client_id: None,
+      remote_addr: remote_addr.map(|addr| addr.ip().to_string()),
+    }
+  }
+  pub fn actor(mut self, subscriber_id: &SubscriberId) -> Self {
+    self.actor = Some(subscriber_id.clone());
+    self
+  }
+  pub fn target(mut self, target: &str) -> Self {
+    self.target = Some(target.to_string());
+    self
+  }
+  pub fn client_id(mut self, client_id: &ClientId) -> Self {
+    self.client_id = Some(client_id.clone());
+    self
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Conditions to search the audit log. Unspecified conditions match any event.
+pub struct AuditEventFilter {
+  pub kind: Option<AuditEventKind>,
+  pub actor: Option<SubscriberId>,
+  pub target: Option<String>,
+  pub client_id: Option<ClientId>,
+  /// Events at or after this time
+  pub since: Option<DateTime<Local>>,
+  /// Events before this time
+  pub until: Option<DateTime<Local>>,
+}
+
+impl AuditEventFilter {
+  pub fn matches(&self, event: &AuditEvent) -> bool {
+    self.kind.is_none_or(|k| k == event.kind)
+      && self.actor.as_ref().is_none_or(|a| event.actor.as_ref() == Some(a))
+      && self.target.as_ref().is_none_or(|t| event.target.as_ref() == Some(t))
+      && self.client_id.as_ref().is_none_or(|c| event.client_id.as_ref() == Some(c))
+      && self.since.is_none_or(|s| event.timestamp.timestamp() >= s.timestamp())
+      && self.until.is_none_or(|u| event.timestamp.timestamp() < u.timestamp())
+  }
+}
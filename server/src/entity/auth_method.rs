@@ -0,0 +1,43 @@
+use crate::error::*;
+use std::str::FromStr;
+
+/// Method used to authenticate the user at the login, emitted in the `amr` claim with the values of RFC 8176
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+  Password,
+  /// TOTP code or recovery code
+  OneTimePassword,
+  MultiFactor,
+}
+
+impl AuthMethod {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AuthMethod::Password => "pwd",
+      AuthMethod::OneTimePassword => "otp",
+      AuthMethod::MultiFactor => "mfa",
+    }
+  }
+
+  /// Methods of a login with the password alone, or with the password and a second factor
+  pub fn login(second_factor: bool) -> Vec<AuthMethod> {
+    if second_factor {
+      vec![AuthMethod::Password, AuthMethod::OneTimePassword, AuthMethod::MultiFactor]
+    } else {
+      vec![AuthMethod::Password]
+    }
+  }
+}
+
+impl FromStr for AuthMethod {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let method = match s {
+      "pwd" => AuthMethod::Password,
+      "otp" => AuthMethod::OneTimePassword,
+      "mfa" => AuthMethod::MultiFactor,
+      _ => bail!("Unknown authentication method: {s}"),
+    };
+    Ok(method)
+  }
+}
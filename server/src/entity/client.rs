@@ -0,0 +1,59 @@
+use super::{deserialize_some, DisplayName, EncodedHash};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+
+use libcommon::token_fields::ClientId;
+
+/// Client application registered as allowed to get tokens
+#[derive(Debug, Clone)]
+pub struct Client {
+  pub client_id: ClientId,
+  pub display_name: Option<DisplayName>,
+  /// Disabled clients stay registered but cannot get tokens
+  pub enabled: bool,
+  /// Hash of the client secret of confidential clients, which can get tokens by themselves via the client credentials
+  /// grant. Public clients have no secret.
+  pub secret_hash: Option<EncodedHash>,
+  pub created_at: DateTime<Local>,
+}
+
+impl Client {
+  /// Newly registered public client, which is enabled
+  pub fn new(client_id: &ClientId, display_name: Option<DisplayName>) -> Self {
+    Self {
+      client_id: client_id.clone(),
+      display_name,
+      enabled: true,
+      secret_hash: None,
+      created_at: Local::now(),
+    }
+  }
+
+  pub fn is_confidential(&self) -> bool {
+    self.secret_hash.is_some()
+  }
+
+  /// Apply the update. Fields absent from the update are kept as they are.
+  pub fn apply(&mut self, update: &ClientUpdate) {
+    if let Some(display_name) = &update.display_name {
+      self.display_name = display_name.clone();
+    }
+    if let Some(enabled) = update.enabled {
+      self.enabled = enabled;
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// Update of the registered client. An absent field is kept as it is, and `null` clears the display name.
+pub struct ClientUpdate {
+  #[serde(default, deserialize_with = "deserialize_some")]
+  pub display_name: Option<Option<DisplayName>>,
+  pub enabled: Option<bool>,
+}
+
+impl ClientUpdate {
+  pub fn is_empty(&self) -> bool {
+    self.display_name.is_none() && self.enabled.is_none()
+  }
+}
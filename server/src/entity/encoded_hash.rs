@@ -1,5 +1,5 @@
 use super::{Entity, Password, TryNewEntity};
-use crate::error::*;
+use crate::{argon2::argon2_needs_rehash, error::*};
 use std::borrow::Cow;
 use validator::Validate;
 
@@ -32,4 +32,9 @@ impl EncodedHash {
     object.validate()?;
     Ok(object)
   }
+
+  /// Whether the hash should be replaced with one computed with the current parameters
+  pub fn needs_rehash(&self) -> bool {
+    argon2_needs_rehash(&self.value)
+  }
 }
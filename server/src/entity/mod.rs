@@ -1,15 +1,31 @@
+mod audit_event;
+mod auth_method;
+mod client;
 mod encoded_hash;
 mod password;
+mod password_policy;
+mod refresh_token_hash;
 mod refresh_token_info;
+mod role;
+mod totp;
 mod user;
+mod user_profile;
 mod username;
 
 use crate::error::{Error, Result};
 
+pub use audit_event::*;
+pub use auth_method::AuthMethod;
+pub use client::*;
 pub use encoded_hash::EncodedHash;
 pub use password::Password;
+pub use password_policy::*;
+pub use refresh_token_hash::{RefreshTokenHash, RefreshTokenSecret};
 pub use refresh_token_info::*;
+pub use role::*;
+pub use totp::*;
 pub use user::*;
+pub use user_profile::*;
 pub use username::*;
 
 pub trait Entity
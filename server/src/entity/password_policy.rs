@@ -0,0 +1,153 @@
+use super::{Entity, Password, Username};
+use crate::{constants::PASSWORD_MIN_LEN, error::*};
+use serde::Serialize;
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+/// Class of characters that passwords can be required to contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharacterClass {
+  Lowercase,
+  Uppercase,
+  Digit,
+  Symbol,
+}
+
+impl CharacterClass {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CharacterClass::Lowercase => "lowercase",
+      CharacterClass::Uppercase => "uppercase",
+      CharacterClass::Digit => "digit",
+      CharacterClass::Symbol => "symbol",
+    }
+  }
+
+  fn matches(&self, c: char) -> bool {
+    match self {
+      CharacterClass::Lowercase => c.is_lowercase(),
+      CharacterClass::Uppercase => c.is_uppercase(),
+      CharacterClass::Digit => c.is_ascii_digit(),
+      CharacterClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+    }
+  }
+}
+
+impl FromStr for CharacterClass {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let class = match s {
+      "lowercase" => CharacterClass::Lowercase,
+      "uppercase" => CharacterClass::Uppercase,
+      "digit" => CharacterClass::Digit,
+      "symbol" => CharacterClass::Symbol,
+      _ => bail!("Unknown character class: {s}"),
+    };
+    Ok(class)
+  }
+}
+
+/// Rule of the password policy broken by a password, which is returned to clients as an error detail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum PasswordPolicyViolation {
+  TooShort { min_length: usize },
+  MissingCharacterClass { class: CharacterClass },
+  ContainsUsername,
+}
+
+impl fmt::Display for PasswordPolicyViolation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PasswordPolicyViolation::TooShort { min_length } => write!(f, "Password must be at least {min_length} characters"),
+      PasswordPolicyViolation::MissingCharacterClass { class } => {
+        write!(f, "Password must contain a {} character", class.as_str())
+      }
+      PasswordPolicyViolation::ContainsUsername => write!(f, "Password must not contain the username"),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Policy that passwords given by users and admins must satisfy. Randomly generated passwords are not checked.
+pub struct PasswordPolicy {
+  /// Minimum number of characters
+  pub min_length: usize,
+  /// Classes of characters every password must contain
+  pub required_classes: BTreeSet<CharacterClass>,
+}
+
+impl Default for PasswordPolicy {
+  fn default() -> Self {
+    Self {
+      min_length: PASSWORD_MIN_LEN,
+      required_classes: BTreeSet::new(),
+    }
+  }
+}
+
+impl PasswordPolicy {
+  /// Check the password of the user against the policy, and return every rule it breaks
+  pub fn check(&self, username: &Username, password: &Password) -> std::result::Result<(), Vec<PasswordPolicyViolation>> {
+    let password = password.as_str();
+    let mut violations = vec![];
+    if password.chars().count() < self.min_length {
+      violations.push(PasswordPolicyViolation::TooShort {
+        min_length: self.min_length,
+      });
+    }
+    for class in &self.required_classes {
+      if !password.chars().any(|c| class.matches(c)) {
+        violations.push(PasswordPolicyViolation::MissingCharacterClass { class: *class });
+      }
+    }
+    if password.to_lowercase().contains(&username.as_str().to_lowercase()) {
+      violations.push(PasswordPolicyViolation::ContainsUsername);
+    }
+    if violations.is_empty() {
+      Ok(())
+    } else {
+      Err(violations)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::entity::TryNewEntity;
+
+  #[test]
+  fn policy_reports_every_violation() {
+    let policy = PasswordPolicy {
+      min_length: 10,
+      required_classes: [CharacterClass::Uppercase, CharacterClass::Digit, CharacterClass::Symbol]
+        .into_iter()
+        .collect(),
+    };
+    let username = Username::new("alice").unwrap();
+    let check = |password: &str| policy.check(&username, &Password::new(password).unwrap());
+
+    assert!(check("Correct-horse-1").is_ok());
+    assert_eq!(
+      check("x").unwrap_err(),
+      vec![
+        PasswordPolicyViolation::TooShort { min_length: 10 },
+        PasswordPolicyViolation::MissingCharacterClass {
+          class: CharacterClass::Uppercase
+        },
+        PasswordPolicyViolation::MissingCharacterClass {
+          class: CharacterClass::Digit
+        },
+        PasswordPolicyViolation::MissingCharacterClass {
+          class: CharacterClass::Symbol
+        },
+      ]
+    );
+    assert_eq!(check("My-ALICE-pass-1").unwrap_err(), vec![PasswordPolicyViolation::ContainsUsername]);
+    assert_eq!(
+      serde_json::to_value(check("Alice-password").unwrap_err()).unwrap(),
+      serde_json::json!([{ "rule": "missing_character_class", "class": "digit" }, { "rule": "contains_username" }])
+    );
+  }
+}
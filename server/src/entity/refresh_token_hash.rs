@@ -0,0 +1,94 @@
+use super::{Entity, TryNewEntity};
+use crate::error::*;
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::borrow::Cow;
+use validator::Validate;
+
+use libcommon::token_fields::{Field, RefreshToken};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Label to derive the refresh token secret from other key material
+const REFRESH_TOKEN_SECRET_LABEL: &[u8] = b"rust-token-server refresh token secret";
+/// Length of base64url-encoded HMAC-SHA256 without padding
+const REFRESH_TOKEN_HASH_LEN: u64 = 43;
+
+/// Keyed hash of a refresh token, which is stored in the token table instead of the refresh token itself
+#[derive(Debug, Clone, PartialEq, Eq, Validate)]
+pub struct RefreshTokenHash {
+  #[validate(length(equal = "REFRESH_TOKEN_HASH_LEN"))]
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for RefreshTokenHash {
+  fn new(hash: T) -> Result<Self> {
+    let value = hash.into().to_string();
+    let object = Self { value };
+    object.validate()?;
+    Ok(object)
+  }
+}
+impl Entity for RefreshTokenHash {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+
+/// Server secret to compute HMAC-SHA256 of refresh tokens.
+/// Every server instance sharing a database must use the same secret.
+#[derive(Clone)]
+pub struct RefreshTokenSecret {
+  value: Vec<u8>,
+}
+
+impl RefreshTokenSecret {
+  pub fn new(secret: &[u8]) -> Result<Self> {
+    if secret.is_empty() {
+      bail!("Refresh token secret must not be empty");
+    }
+    Ok(Self { value: secret.to_vec() })
+  }
+
+  /// Derive the secret from given key material, e.g., the id token signing key,
+  /// so that instances sharing the key also agree on the secret.
+  pub fn derive(key_material: &[u8]) -> Result<Self> {
+    let mut mac = HmacSha256::new_from_slice(REFRESH_TOKEN_SECRET_LABEL)?;
+    mac.update(key_material);
+    Self::new(&mac.finalize().into_bytes())
+  }
+
+  /// Keyed hash of the refresh token
+  pub fn hash(&self, refresh_token: &RefreshToken) -> Result<RefreshTokenHash> {
+    let mut mac = HmacSha256::new_from_slice(&self.value)?;
+    mac.update(refresh_token.as_str().as_bytes());
+    let value = general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    RefreshTokenHash::new(value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hash_depends_on_token_and_secret() -> Result<()> {
+    let secret = RefreshTokenSecret::derive(b"signing key")?;
+    let token = RefreshToken::generate()?;
+
+    let hash = secret.hash(&token)?;
+    assert_eq!(hash, RefreshTokenSecret::derive(b"signing key")?.hash(&token)?);
+    assert_ne!(hash.as_str(), token.as_str());
+    assert_ne!(hash, secret.hash(&RefreshToken::generate()?)?);
+    assert_ne!(hash, RefreshTokenSecret::derive(b"another key")?.hash(&token)?);
+    assert_ne!(hash, RefreshTokenSecret::new(b"signing key")?.hash(&token)?);
+
+    assert!(RefreshTokenHash::new(hash.as_str()).is_ok());
+    assert!(RefreshTokenHash::new(token.as_str()).is_err());
+    assert!(RefreshTokenSecret::new(b"").is_err());
+    Ok(())
+  }
+}
@@ -1,38 +1,86 @@
-use crate::{constants::REFRESH_TOKEN_DURATION_MINS, error::*};
+use super::{AuthMethod, Entity, RefreshTokenHash, RefreshTokenSecret, TryNewEntity};
+use crate::error::*;
 use chrono::{DateTime, Duration, Local};
+use std::borrow::Cow;
+use uuid::Uuid;
+use validator::Validate;
 
 use libcommon::{
-  token_fields::{ClientId, RefreshToken, SubscriberId},
+  token_fields::{ClientId, SubscriberId},
   TokenBody,
 };
 
-#[derive(sqlx::FromRow, Debug, Clone)]
+/// Identifier shared by refresh tokens rotated from the same login
+#[derive(Debug, Clone, PartialEq, Eq, Validate)]
+pub struct TokenFamilyId {
+  #[validate(length(min = 1))]
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for TokenFamilyId {
+  fn new(family_id: T) -> Result<Self> {
+    let value = family_id.into().to_string();
+    let object = Self { value };
+    object.validate()?;
+    Ok(object)
+  }
+}
+impl Entity for TokenFamilyId {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+impl TokenFamilyId {
+  /// New family for a fresh login
+  pub fn generate() -> Self {
+    Self {
+      value: Uuid::new_v4().to_string(),
+    }
+  }
+}
+
+/// Record of an issued refresh token. Only its keyed hash is kept, never the refresh token itself.
+#[derive(Debug, Clone)]
 pub struct RefreshTokenInfo {
   pub subscriber_id: SubscriberId,
   pub client_id: ClientId,
-  pub inner: RefreshToken,
+  pub hash: RefreshTokenHash,
+  pub family_id: TokenFamilyId,
+  /// Methods used to authenticate the user at the login of the family, which are kept in refreshed id tokens
+  pub amr: Vec<AuthMethod>,
+  /// True once the refresh token has been exchanged for a new one
+  pub consumed: bool,
   pub expires: DateTime<Local>,
 }
-impl<'a> TryFrom<&'a TokenBody> for RefreshTokenInfo {
-  type Error = crate::error::Error;
 
-  fn try_from(value: &'a TokenBody) -> std::result::Result<Self, Self::Error> {
-    let inner = value
-      .refresh
-      .as_ref()
-      .ok_or_else(|| anyhow!("No refresh token"))?
-      .to_owned();
-    let subscriber_id = value.subscriber_id.clone();
-    let client_id = value
+impl RefreshTokenInfo {
+  /// Record of the refresh token issued in the token body, belonging to the given family of the login with the given
+  /// authentication methods and valid for the given duration
+  pub fn try_new(
+    token_body: &TokenBody,
+    secret: &RefreshTokenSecret,
+    family_id: TokenFamilyId,
+    amr: Vec<AuthMethod>,
+    duration: Duration,
+  ) -> Result<Self> {
+    let refresh_token = token_body.refresh.as_ref().ok_or_else(|| anyhow!("No refresh token"))?;
+    let hash = secret.hash(refresh_token)?;
+    let subscriber_id = token_body.subscriber_id.clone();
+    let client_id = token_body
       .allowed_apps
       .get_one()
       .ok_or_else(|| anyhow!("No client id"))?
       .to_owned();
-    let expires = Local::now() + Duration::minutes(REFRESH_TOKEN_DURATION_MINS as i64);
+    let expires = Local::now() + duration;
     Ok(Self {
-      inner,
       subscriber_id,
       client_id,
+      hash,
+      family_id,
+      amr,
+      consumed: false,
       expires,
     })
   }
@@ -0,0 +1,158 @@
+use super::TryNewEntity;
+use crate::error::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::BTreeSet, str::FromStr};
+
+use libcommon::Claims;
+
+/// Named role granted to users
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+  /// Full rights, including role management
+  Admin,
+  /// Create, update, delete and log out users
+  UserManager,
+  /// Read users and the audit log
+  Auditor,
+  /// Get blind signatures when they are restricted to this role
+  BlindIssuer,
+}
+
+/// Operation on admin endpoints, which is permitted by roles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+  ManageUsers,
+  ListUsers,
+  ManageRoles,
+  ManageClients,
+  ReadAuditLog,
+  #[cfg_attr(not(feature = "blind-signatures"), allow(dead_code))]
+  IssueBlindSignatures,
+}
+
+impl Role {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Role::Admin => "admin",
+      Role::UserManager => "user-manager",
+      Role::Auditor => "auditor",
+      Role::BlindIssuer => "blind-issuer",
+    }
+  }
+
+  pub fn permits(&self, permission: Permission) -> bool {
+    match self {
+      Role::Admin => true,
+      Role::UserManager => matches!(permission, Permission::ManageUsers | Permission::ListUsers),
+      Role::Auditor => matches!(permission, Permission::ListUsers | Permission::ReadAuditLog),
+      Role::BlindIssuer => matches!(permission, Permission::IssueBlindSignatures),
+    }
+  }
+}
+
+impl FromStr for Role {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let role = match s {
+      "admin" => Role::Admin,
+      "user-manager" => Role::UserManager,
+      "auditor" => Role::Auditor,
+      "blind-issuer" => Role::BlindIssuer,
+      _ => bail!("Unknown role: {s}"),
+    };
+    Ok(role)
+  }
+}
+
+impl Serialize for Role {
+  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(self.as_str())
+  }
+}
+impl<'de> Deserialize<'de> for Role {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+/// Set of roles granted to a user
+pub struct Roles {
+  value: BTreeSet<Role>,
+}
+
+impl FromIterator<Role> for Roles {
+  fn from_iter<I: IntoIterator<Item = Role>>(iter: I) -> Self {
+    Self {
+      value: iter.into_iter().collect(),
+    }
+  }
+}
+
+impl TryNewEntity<Vec<String>> for Roles {
+  fn new(roles: Vec<String>) -> Result<Self> {
+    roles.iter().map(|r| r.parse()).collect()
+  }
+}
+
+impl Roles {
+  pub fn contains(&self, role: Role) -> bool {
+    self.value.contains(&role)
+  }
+  pub fn insert(&mut self, role: Role) {
+    self.value.insert(role);
+  }
+  pub fn remove(&mut self, role: Role) {
+    self.value.remove(&role);
+  }
+  /// Whether any of the roles permits the operation
+  pub fn permits(&self, permission: Permission) -> bool {
+    self.value.iter().any(|r| r.permits(permission))
+  }
+  pub fn to_strings(&self) -> Vec<String> {
+    self.value.iter().map(|r| r.as_str().to_string()).collect()
+  }
+
+  /// Roles given in the `roles` claim of the id token. Unknown roles are ignored, and tokens issued before roles
+  /// were introduced are regarded as having the admin role if `iad` is true.
+  pub fn from_claims(claims: &Claims) -> Self {
+    let mut roles = claims
+      .custom
+      .get("roles")
+      .and_then(|v| v.as_array())
+      .map(|a| a.iter().filter_map(|r| r.as_str()?.parse().ok()).collect::<Roles>())
+      .unwrap_or_default();
+    if claims.custom.get("iad").and_then(|v| v.as_bool()).unwrap_or(false) {
+      roles.insert(Role::Admin);
+    }
+    roles
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn roles_permit_operations() {
+    let roles: Roles = serde_json::from_str(r#"["auditor", "blind-issuer"]"#).unwrap();
+    assert!(roles.permits(Permission::ReadAuditLog));
+    assert!(roles.permits(Permission::ListUsers));
+    assert!(roles.permits(Permission::IssueBlindSignatures));
+    assert!(!roles.permits(Permission::ManageUsers));
+    assert!(!roles.permits(Permission::ManageRoles));
+    assert_eq!(serde_json::to_string(&roles).unwrap(), r#"["auditor","blind-issuer"]"#);
+
+    let admin = [Role::Admin].into_iter().collect::<Roles>();
+    assert!(admin.permits(Permission::ManageRoles));
+    assert!(serde_json::from_str::<Roles>(r#"["superuser"]"#).is_err());
+  }
+}
@@ -0,0 +1,389 @@
+use super::{Entity, TryNewEntity};
+use crate::{constants::*, error::*};
+use aes_gcm::{
+  aead::{Aead, Payload},
+  Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use rand::prelude::*;
+use sha1::Sha1;
+use sha2::Sha256;
+use std::{borrow::Cow, fmt};
+use url::Url;
+use validator::Validate;
+
+use libcommon::token_fields::{Field, SubscriberId};
+
+type HmacSha1 = Hmac<Sha1>;
+type HmacSha256 = Hmac<Sha256>;
+
+/// Base32 alphabet of RFC 4648, in which authenticator apps take secrets
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Characters of recovery codes, excluding ones easily confused with others like `0`, `o`, `1` and `l`
+const RECOVERY_CODE_CHARS: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+/// Label to derive the key protecting second factors from other key material
+const SECOND_FACTOR_KEY_LABEL: &[u8] = b"rust-token-server second factor key";
+/// Length of the nonce of AES-GCM prepended to sealed TOTP secrets
+const SEALED_NONCE_LEN: usize = 12;
+/// Length of base64url-encoded HMAC-SHA256 without padding
+const RECOVERY_CODE_HASH_LEN: u64 = 43;
+
+/// Shared secret of TOTP (RFC 6238) with HMAC-SHA1, which authenticator apps support by default
+#[derive(Clone, PartialEq, Eq)]
+pub struct TotpSecret {
+  value: Vec<u8>,
+}
+
+// never print the secret in logs
+impl fmt::Debug for TotpSecret {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("TotpSecret(..)")
+  }
+}
+
+impl TotpSecret {
+  pub fn generate() -> Self {
+    let mut value = vec![0u8; TOTP_SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut value);
+    Self { value }
+  }
+
+  /// Decode the secret as authenticator apps do, which the server itself never needs since it stores secrets sealed
+  #[cfg(test)]
+  pub fn from_base32(encoded: &str) -> Result<Self> {
+    let value = base32_decode(encoded)?;
+    if value.is_empty() {
+      bail!("TOTP secret must not be empty");
+    }
+    Ok(Self { value })
+  }
+
+  pub fn to_base32(&self) -> String {
+    base32_encode(&self.value)
+  }
+
+  /// Code of the time step, i.e., HOTP (RFC 4226) of the step number
+  pub(crate) fn code(&self, step: i64) -> Result<String> {
+    let mut mac = HmacSha1::new_from_slice(&self.value)?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    Ok(format!(
+      "{:0width$}",
+      binary % 10u32.pow(TOTP_DIGITS),
+      width = TOTP_DIGITS as usize
+    ))
+  }
+
+  /// Time step of the code if it matches the code at the given UNIX time, or at an adjacent step allowing for clock skew
+  pub fn verify(&self, code: &str, unix_time: i64) -> Result<Option<i64>> {
+    let current = unix_time.div_euclid(TOTP_STEP_SECS);
+    for step in current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS {
+      if constant_time_eq(self.code(step)?.as_bytes(), code.as_bytes()) {
+        return Ok(Some(step));
+      }
+    }
+    Ok(None)
+  }
+
+  /// URI of the secret shown as a QR code to register it with authenticator apps
+  pub fn otpauth_uri(&self, issuer: &str, username: &str) -> Result<String> {
+    let mut uri = Url::parse("otpauth://totp/")?;
+    uri
+      .path_segments_mut()
+      .map_err(|_| anyhow!("Invalid otpauth uri"))?
+      .pop_if_empty()
+      .push(&format!("{issuer}:{username}"));
+    uri
+      .query_pairs_mut()
+      .append_pair("secret", &self.to_base32())
+      .append_pair("issuer", issuer)
+      .append_pair("algorithm", "SHA1")
+      .append_pair("digits", &TOTP_DIGITS.to_string())
+      .append_pair("period", &TOTP_STEP_SECS.to_string());
+    Ok(uri.to_string())
+  }
+}
+
+/// Whether the string looks like a TOTP code rather than a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+  code.len() == TOTP_DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// TOTP secret encrypted with the [`SecondFactorKey`], which is stored in the TOTP table instead of the secret itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedTotpSecret {
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for SealedTotpSecret {
+  fn new(sealed: T) -> Result<Self> {
+    let value = sealed.into().to_string();
+    if value.is_empty() {
+      bail!("Sealed TOTP secret must not be empty");
+    }
+    Ok(Self { value })
+  }
+}
+impl Entity for SealedTotpSecret {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+
+/// Keyed hash of a recovery code, which is stored instead of the recovery code itself
+#[derive(Debug, Clone, PartialEq, Eq, Validate)]
+pub struct RecoveryCodeHash {
+  #[validate(length(equal = "RECOVERY_CODE_HASH_LEN"))]
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for RecoveryCodeHash {
+  fn new(hash: T) -> Result<Self> {
+    let value = hash.into().to_string();
+    let object = Self { value };
+    object.validate()?;
+    Ok(object)
+  }
+}
+impl Entity for RecoveryCodeHash {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+
+/// Server key to encrypt TOTP secrets with AES-256-GCM and to compute HMAC-SHA256 of recovery codes, so that a leaked
+/// database alone reveals neither. Every server instance sharing a database must use the same key.
+#[derive(Clone)]
+pub struct SecondFactorKey {
+  encryption_key: Vec<u8>,
+  hash_key: Vec<u8>,
+}
+
+impl SecondFactorKey {
+  /// Derive the key from given key material, e.g., the id token signing key, so that instances sharing the key also
+  /// agree on the key
+  pub fn derive(key_material: &[u8]) -> Result<Self> {
+    let mut mac = HmacSha256::new_from_slice(SECOND_FACTOR_KEY_LABEL)?;
+    mac.update(key_material);
+    let master = mac.finalize().into_bytes();
+    let subkey = |purpose: &[u8]| -> Result<Vec<u8>> {
+      let mut mac = HmacSha256::new_from_slice(&master)?;
+      mac.update(purpose);
+      Ok(mac.finalize().into_bytes().to_vec())
+    };
+    Ok(Self {
+      encryption_key: subkey(b"totp secret")?,
+      hash_key: subkey(b"recovery code")?,
+    })
+  }
+
+  /// Encrypt the secret of the user, which cannot be decrypted as the secret of any other user
+  pub fn seal(&self, secret: &TotpSecret, subscriber_id: &SubscriberId) -> Result<SealedTotpSecret> {
+    let mut nonce = [0u8; SEALED_NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let payload = Payload {
+      msg: &secret.value,
+      aad: subscriber_id.as_str().as_bytes(),
+    };
+    let Ok(ciphertext) = self.cipher()?.encrypt(&Nonce::from(nonce), payload) else {
+      bail!("Failed to encrypt TOTP secret");
+    };
+    SealedTotpSecret::new(general_purpose::URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat()))
+  }
+
+  /// Decrypt the secret of the user
+  pub fn open(&self, sealed: &SealedTotpSecret, subscriber_id: &SubscriberId) -> Result<TotpSecret> {
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(sealed.as_str())?;
+    if bytes.len() <= SEALED_NONCE_LEN {
+      bail!("Sealed TOTP secret is too short");
+    }
+    let (nonce, ciphertext) = bytes.split_at(SEALED_NONCE_LEN);
+    let nonce: [u8; SEALED_NONCE_LEN] = nonce.try_into()?;
+    let payload = Payload {
+      msg: ciphertext,
+      aad: subscriber_id.as_str().as_bytes(),
+    };
+    let Ok(value) = self.cipher()?.decrypt(&Nonce::from(nonce), payload) else {
+      bail!("Failed to decrypt TOTP secret");
+    };
+    Ok(TotpSecret { value })
+  }
+
+  /// Keyed hash of the recovery code normalized by [`normalize_recovery_code`], which is fast to look up unlike
+  /// Argon2 while the code is too random to guess without the key
+  pub fn hash_recovery_code(&self, normalized_code: &str) -> Result<RecoveryCodeHash> {
+    let mut mac = HmacSha256::new_from_slice(&self.hash_key)?;
+    mac.update(normalized_code.as_bytes());
+    RecoveryCodeHash::new(general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+  }
+
+  fn cipher(&self) -> Result<Aes256Gcm> {
+    let Ok(cipher) = <Aes256Gcm as aes_gcm::KeyInit>::new_from_slice(&self.encryption_key) else {
+      bail!("Invalid length of TOTP encryption key");
+    };
+    Ok(cipher)
+  }
+}
+
+/// TOTP factor of a user
+#[derive(Debug, Clone)]
+pub struct TotpFactor {
+  pub subscriber_id: SubscriberId,
+  pub secret: SealedTotpSecret,
+  /// False until the user verifies the first code generated by the authenticator app
+  pub enabled: bool,
+  /// Time step of the last accepted code. Codes of this step and earlier ones cannot be used again.
+  pub last_step: Option<i64>,
+}
+
+impl TotpFactor {
+  /// Factor with a new secret, pending until the first code is verified
+  pub fn pending(subscriber_id: &SubscriberId, secret: SealedTotpSecret) -> Self {
+    Self {
+      subscriber_id: subscriber_id.clone(),
+      secret,
+      enabled: false,
+      last_step: None,
+    }
+  }
+}
+
+/// Generate single-use recovery codes like `abcde-fghij` to log in without the authenticator app.
+/// They are shown to the user only once, and only their keyed hashes are stored.
+pub fn generate_recovery_codes() -> Vec<String> {
+  let mut rng = rand::thread_rng();
+  (0..RECOVERY_CODE_COUNT)
+    .map(|_| {
+      let chars = (0..RECOVERY_CODE_LEN)
+        .map(|_| *RECOVERY_CODE_CHARS.choose(&mut rng).unwrap_or(&b'a') as char)
+        .collect::<String>();
+      let (first, second) = chars.split_at(RECOVERY_CODE_LEN / 2);
+      format!("{first}-{second}")
+    })
+    .collect()
+}
+
+/// Recovery code as typed by the user, ignoring case, hyphens and spaces. Returns None if it cannot be a recovery code.
+pub fn normalize_recovery_code(code: &str) -> Option<String> {
+  let normalized = code
+    .chars()
+    .filter(|c| *c != '-' && !c.is_whitespace())
+    .collect::<String>()
+    .to_lowercase();
+  let valid = normalized.len() == RECOVERY_CODE_LEN && normalized.bytes().all(|b| RECOVERY_CODE_CHARS.contains(&b));
+  valid.then_some(normalized)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+  let mut encoded = String::new();
+  let (mut buffer, mut bits) = (0u32, 0u32);
+  for byte in bytes {
+    buffer = (buffer << 8) | *byte as u32;
+    bits += 8;
+    while bits >= 5 {
+      bits -= 5;
+      encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+    }
+    buffer &= (1 << bits) - 1;
+  }
+  if bits > 0 {
+    encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+  }
+  encoded
+}
+
+#[cfg(test)]
+fn base32_decode(encoded: &str) -> Result<Vec<u8>> {
+  let mut bytes = vec![];
+  let (mut buffer, mut bits) = (0u32, 0u32);
+  for c in encoded.trim_end_matches('=').chars() {
+    let Some(value) = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase()) else {
+      bail!("Invalid base32 character");
+    };
+    buffer = (buffer << 5) | value as u32;
+    bits += 5;
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+    }
+    buffer &= (1 << bits) - 1;
+  }
+  Ok(bytes)
+}
+
+/// Compare without returning early, so that the time taken tells nothing about the expected code
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use libcommon::token_fields::TryNewField;
+
+  #[test]
+  fn totp_matches_rfc6238_test_vectors() -> Result<()> {
+    // the SHA-1 seed of RFC 6238, whose 8-digit codes end with these 6 digits
+    let secret = TotpSecret::from_base32(&base32_encode(b"12345678901234567890"))?;
+    assert_eq!(secret.to_base32(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    for (unix_time, code) in [(59, "287082"), (1111111109, "081804"), (1234567890, "005924"), (2000000000, "279037")] {
+      assert_eq!(secret.code(unix_time / TOTP_STEP_SECS)?, code);
+      assert_eq!(secret.verify(code, unix_time)?, Some(unix_time / TOTP_STEP_SECS));
+    }
+
+    // codes of adjacent steps are accepted for clock skew
+    assert_eq!(secret.verify("287082", 59 + TOTP_STEP_SECS)?, Some(1));
+    assert_eq!(secret.verify("287082", 59 + 2 * TOTP_STEP_SECS)?, None);
+    assert_eq!(secret.verify("000000", 59)?, None);
+    assert!(TotpSecret::from_base32("not base32!").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn otpauth_uri_and_recovery_codes() -> Result<()> {
+    let secret = TotpSecret::generate();
+    assert_eq!(secret.to_base32().len(), 32);
+    let uri = secret.otpauth_uri("auth.example.com", "alice")?;
+    assert!(uri.starts_with("otpauth://totp/auth.example.com:alice?secret="));
+    assert!(uri.ends_with("&issuer=auth.example.com&algorithm=SHA1&digits=6&period=30"));
+
+    let codes = generate_recovery_codes();
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+    assert!(codes.iter().all(|c| c.len() == RECOVERY_CODE_LEN + 1 && !is_totp_code(c)));
+    assert!(codes.iter().all(|c| normalize_recovery_code(c).is_some()));
+    assert_eq!(normalize_recovery_code(" ABCDE-fghij ").as_deref(), Some("abcdefghij"));
+    for malformed in ["-", "", "abcde", "abcde-fghi0", "abcde-fghijk"] {
+      assert!(normalize_recovery_code(malformed).is_none());
+    }
+    assert!(is_totp_code("012345"));
+    Ok(())
+  }
+
+  #[test]
+  fn second_factors_are_protected_with_server_key() -> Result<()> {
+    let key = SecondFactorKey::derive(b"signing key")?;
+    let (alice, bob) = (SubscriberId::new("alice")?, SubscriberId::new("bob")?);
+    let secret = TotpSecret::generate();
+
+    let sealed = key.seal(&secret, &alice)?;
+    assert!(!sealed.as_str().contains(&secret.to_base32()));
+    assert_ne!(sealed, key.seal(&secret, &alice)?);
+    assert_eq!(key.open(&sealed, &alice)?, secret);
+    // sealed secrets can be opened neither as those of other users nor with other keys
+    assert!(key.open(&sealed, &bob).is_err());
+    assert!(SecondFactorKey::derive(b"another key")?.open(&sealed, &alice).is_err());
+
+    let hash = key.hash_recovery_code("abcdefghij")?;
+    assert_eq!(hash, SecondFactorKey::derive(b"signing key")?.hash_recovery_code("abcdefghij")?);
+    assert_ne!(hash, key.hash_recovery_code("abcdefghik")?);
+    assert_ne!(hash, SecondFactorKey::derive(b"another key")?.hash_recovery_code("abcdefghij")?);
+    Ok(())
+  }
+}
@@ -1,48 +1,41 @@
-use super::{EncodedHash, Entity, Password, TryNewEntity, Username};
+use super::{EncodedHash, Entity, Password, Role, Roles, TryNewEntity, UserProfile, Username};
 use crate::{
   constants::{ADMIN_USERNAME, PASSWORD_LEN},
   error::*,
   log::*,
 };
+use chrono::{DateTime, Local};
 use rand::seq::SliceRandom;
-use serde::Serialize;
 use uuid::Uuid;
 
-use libcommon::token_fields::{Field, SubscriberId, TryNewField};
-
-#[derive(Debug, Clone)]
-pub struct IsAdmin {
-  value: bool,
-}
-impl TryNewEntity<bool> for IsAdmin {
-  fn new(is_admin: bool) -> Result<Self> {
-    let object = Self { value: is_admin };
-    Ok(object)
-  }
-}
-impl IsAdmin {
-  pub fn into_string(self) -> String {
-    self.value.to_string()
-  }
-  pub fn get(&self) -> bool {
-    self.value
-  }
-}
-impl Serialize for IsAdmin {
-  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
-  where
-    S: serde::Serializer,
-  {
-    serializer.serialize_bool(self.get())
-  }
-}
+use libcommon::{
+  token_fields::{Field, SubscriberId, TryNewField},
+  UserClaims, PASSWORD_CHANGE_SCOPE,
+};
 
 #[derive(sqlx::FromRow, Debug, Clone)]
 pub struct User {
   pub username: Username,
   pub subscriber_id: SubscriberId,
   pub encoded_hash: EncodedHash, // including salt and argon2 config
-  pub is_admin: IsAdmin,
+  pub roles: Roles,
+  pub profile: UserProfile,
+  /// Disabled users keep their subscriber id but cannot get tokens
+  pub enabled: bool,
+  /// Users cannot get tokens until this time
+  pub locked_until: Option<DateTime<Local>>,
+  /// Number of consecutive failed logins
+  pub failed_logins: u32,
+  /// Set when an admin resets the password, until the user changes it
+  pub must_change_password: bool,
+}
+
+/// Whether the user can get tokens at the moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountState {
+  Active,
+  Disabled,
+  Locked,
 }
 
 impl User {
@@ -65,16 +58,20 @@ Password was automatically generated for the user of name "{}". Keep this secure
     };
     let subscriber_id = SubscriberId::new(Uuid::new_v4().to_string())?;
     let encoded_hash = EncodedHash::generate(&password_unwrapped)?;
-    let is_admin = if username.as_str() == ADMIN_USERNAME {
-      IsAdmin::new(true)
-    } else {
-      IsAdmin::new(false)
-    }?;
+    let mut roles = Roles::default();
+    if username.as_str() == ADMIN_USERNAME {
+      roles.insert(Role::Admin);
+    }
     Ok(User {
       username: username.to_owned(),
       subscriber_id,
       encoded_hash,
-      is_admin,
+      roles,
+      profile: UserProfile::default(),
+      enabled: true,
+      locked_until: None,
+      failed_logins: 0,
+      must_change_password: false,
     })
   }
 
@@ -83,18 +80,48 @@ Password was automatically generated for the user of name "{}". Keep this secure
     self.encoded_hash.as_str()
   }
   pub fn is_admin(&self) -> bool {
-    self.is_admin.get()
+    self.roles.contains(Role::Admin)
+  }
+  pub fn account_state(&self) -> AccountState {
+    if !self.enabled {
+      AccountState::Disabled
+    } else if self.locked_until.is_some_and(|t| t > Local::now()) {
+      AccountState::Locked
+    } else {
+      AccountState::Active
+    }
   }
   pub fn username(&self) -> &str {
     self.username.as_str()
   }
+  /// Claims of the user emitted in id tokens, where the profile is given as standard OIDC claims.
+  /// Tokens of users who must change the password are restricted to changing it, and carry neither roles nor profile
+  /// so that they are good for nothing else even at validators unaware of the scope.
+  pub fn claims(&self) -> UserClaims {
+    if self.must_change_password {
+      return UserClaims {
+        scope: Some(PASSWORD_CHANGE_SCOPE.to_string()),
+        ..Default::default()
+      };
+    }
+    UserClaims {
+      is_admin: Some(self.is_admin()),
+      roles: self.roles.to_strings(),
+      email: self.profile.email.as_ref().map(|e| e.as_str().to_string()),
+      name: self.profile.display_name.as_ref().map(|n| n.as_str().to_string()),
+      scope: None,
+      // depends on the login rather than the user
+      amr: Vec::new(),
+      is_client: false,
+    }
+  }
   #[allow(dead_code)]
   pub fn subscriber_id(&self) -> &str {
     self.subscriber_id.as_str()
   }
 }
 
-fn generate_random_string(length: usize) -> Result<String> {
+pub fn generate_random_string(length: usize) -> Result<String> {
   const BASE_STR: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
   let mut rng = &mut rand::thread_rng();
   let res = String::from_utf8(BASE_STR.as_bytes().choose_multiple(&mut rng, length).cloned().collect())?;
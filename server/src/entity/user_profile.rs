@@ -0,0 +1,171 @@
+use super::{Entity, TryNewEntity};
+use crate::{constants::MAX_USER_ATTRIBUTES_LEN, error::*};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::borrow::Cow;
+use validator::Validate;
+
+#[derive(Debug, Clone, PartialEq, Eq, Validate)]
+pub struct Email {
+  #[validate(email)]
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for Email {
+  fn new(email: T) -> Result<Self> {
+    let value = email.into().to_string();
+    let object = Self { value };
+    object.validate()?;
+    Ok(object)
+  }
+}
+impl Entity for Email {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+impl<'de> Deserialize<'de> for Email {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = String::deserialize(deserializer)?;
+    Self::new(value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Validate)]
+pub struct DisplayName {
+  #[validate(length(min = 1, max = 256))]
+  value: String,
+}
+impl<'a, T: Into<Cow<'a, str>>> TryNewEntity<T> for DisplayName {
+  fn new(display_name: T) -> Result<Self> {
+    let value = display_name.into().to_string();
+    let object = Self { value };
+    object.validate()?;
+    Ok(object)
+  }
+}
+impl Entity for DisplayName {
+  fn as_str(&self) -> &str {
+    &self.value
+  }
+  fn into_string(self) -> String {
+    self.value
+  }
+}
+impl<'de> Deserialize<'de> for DisplayName {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = String::deserialize(deserializer)?;
+    Self::new(value).map_err(serde::de::Error::custom)
+  }
+}
+
+/// Free-form JSON object attached to the user, which is never interpreted by the server
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UserAttributes {
+  value: serde_json::Map<String, serde_json::Value>,
+}
+impl TryNewEntity<serde_json::Map<String, serde_json::Value>> for UserAttributes {
+  fn new(value: serde_json::Map<String, serde_json::Value>) -> Result<Self> {
+    if serde_json::to_string(&value)?.len() > MAX_USER_ATTRIBUTES_LEN {
+      bail!("User attributes must be at most {MAX_USER_ATTRIBUTES_LEN} bytes in JSON");
+    }
+    Ok(Self { value })
+  }
+}
+impl UserAttributes {
+  pub fn get(&self) -> &serde_json::Map<String, serde_json::Value> {
+    &self.value
+  }
+  pub fn into_inner(self) -> serde_json::Map<String, serde_json::Value> {
+    self.value
+  }
+}
+impl<'de> Deserialize<'de> for UserAttributes {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = serde_json::Map::deserialize(deserializer)?;
+    Self::new(value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Optional profile of the user
+pub struct UserProfile {
+  pub email: Option<Email>,
+  pub display_name: Option<DisplayName>,
+  pub attributes: UserAttributes,
+}
+
+impl UserProfile {
+  /// Apply the update. Fields absent from the update are kept as they are.
+  pub fn apply(&mut self, update: &ProfileUpdate) {
+    if let Some(email) = &update.email {
+      self.email = email.clone();
+    }
+    if let Some(display_name) = &update.display_name {
+      self.display_name = display_name.clone();
+    }
+    if let Some(attributes) = &update.attributes {
+      self.attributes = attributes.clone();
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// Update of the user profile. An absent field is kept as it is, and `null` clears the email and display name.
+pub struct ProfileUpdate {
+  #[serde(default, deserialize_with = "deserialize_some")]
+  pub email: Option<Option<Email>>,
+  #[serde(default, deserialize_with = "deserialize_some")]
+  pub display_name: Option<Option<DisplayName>>,
+  pub attributes: Option<UserAttributes>,
+}
+
+impl ProfileUpdate {
+  pub fn is_empty(&self) -> bool {
+    self.email.is_none() && self.display_name.is_none() && self.attributes.is_none()
+  }
+}
+
+/// Distinguish an explicit `null` from an absent field
+pub(crate) fn deserialize_some<'de, T, D>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+  T: Deserialize<'de>,
+  D: Deserializer<'de>,
+{
+  T::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn profile_update_distinguishes_null_from_absent() {
+    let mut profile = UserProfile {
+      email: Some(Email::new("user@example.com").unwrap()),
+      display_name: Some(DisplayName::new("User").unwrap()),
+      attributes: UserAttributes::default(),
+    };
+    let update: ProfileUpdate = serde_json::from_str(r#"{ "email": null, "attributes": { "team": "red" } }"#).unwrap();
+    profile.apply(&update);
+    assert!(profile.email.is_none());
+    assert_eq!(profile.display_name.as_ref().unwrap().as_str(), "User");
+    assert_eq!(profile.attributes.get()["team"], "red");
+
+    assert!(serde_json::from_str::<ProfileUpdate>(r#"{ "email": "not an email" }"#).is_err());
+    assert!(serde_json::from_str::<ProfileUpdate>(r#"{ "display_name": "" }"#).is_err());
+    assert!(serde_json::from_str::<ProfileUpdate>(r#"{ "attributes": [] }"#).is_err());
+    let large = format!(r#"{{ "attributes": {{ "a": "{}" }} }}"#, "a".repeat(MAX_USER_ATTRIBUTES_LEN));
+    assert!(serde_json::from_str::<ProfileUpdate>(&large).is_err());
+  }
+}
@@ -1,19 +1,26 @@
 mod apis;
 mod argon2;
+mod breached_passwords;
 mod config;
 mod constants;
 mod entity;
 mod error;
 mod log;
+mod rate_limit;
 mod state;
 mod table;
 
 // use crate::api_create_user::create_user;
 use crate::{
-  apis::{create_user, delete_user, get_tokens, health_check, jwks, list_users, refresh, update_user},
+  apis::{
+    activate_totp, audit_events, client_tokens, create_client, create_user, delete_client, delete_user, disable_totp,
+    enroll_totp, get_tokens, grant_admin, health_check, jwks, list_clients, list_users, logout, logout_all, logout_user,
+    oauth2_token, refresh, reset_password, revoke_admin, update_client, update_roles, update_user, update_user_status,
+  },
   constants::*,
   error::*,
   log::*,
+  rate_limit::LimitedRoute,
   state::AppState,
 };
 use axum::{
@@ -21,7 +28,7 @@ use axum::{
   Router,
 };
 use config::parse_opts;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 use tokio::runtime::Builder;
 
 #[cfg(feature = "blind-signatures")]
@@ -58,26 +65,58 @@ async fn define_route(shared_state: Arc<AppState>) {
   let tcp_listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
   info!("Listening on {}", &addr);
 
+  // rate limits are checked before handlers start verifying passwords and tokens
+  let rate_limits = &shared_state.rate_limits;
+
   // routes nested under /v1.0
   let api_routes = Router::new()
     .route("/jwks", get(jwks))
-    .route("/tokens", post(get_tokens))
-    .route("/refresh", post(refresh))
+    .route("/tokens", rate_limits.apply(LimitedRoute::Tokens, post(get_tokens)))
+    .route("/refresh", rate_limits.apply(LimitedRoute::Refresh, post(refresh)))
+    .route("/client_tokens", rate_limits.apply(LimitedRoute::ClientTokens, post(client_tokens)))
+    .route("/logout", post(logout))
+    .route("/logout_all", post(logout_all))
     .route("/create_user", post(create_user))
     .route("/update_user", post(update_user))
+    .route("/update_user_status", post(update_user_status))
+    .route("/reset_password", post(reset_password))
+    .route("/enroll_totp", post(enroll_totp))
+    .route("/activate_totp", post(activate_totp))
+    .route("/disable_totp", post(disable_totp))
     .route("/delete_user", post(delete_user))
-    .route("/list_users", post(list_users));
+    .route("/list_users", post(list_users))
+    .route("/logout_user", post(logout_user))
+    .route("/update_roles", post(update_roles))
+    .route("/grant_admin", post(grant_admin))
+    .route("/revoke_admin", post(revoke_admin))
+    .route("/create_client", post(create_client))
+    .route("/list_clients", post(list_clients))
+    .route("/update_client", post(update_client))
+    .route("/delete_client", post(delete_client))
+    .route("/audit_events", post(audit_events));
 
   #[cfg(feature = "blind-signatures")]
   let api_routes = api_routes
     .route("/blindjwks", get(blind_jwks))
-    .route("/blindsign", post(blind_sign));
+    .route("/blindsign", rate_limits.apply(LimitedRoute::BlindSign, post(blind_sign)));
 
   let api_routes = api_routes.with_state(shared_state.clone());
 
-  let router = Router::new().route("/health", get(health_check)).nest("/v1.0", api_routes);
+  // the OAuth 2.0 token endpoint is served at the root, out of the versioned APIs
+  let oauth2_routes = Router::new()
+    .route("/oauth2/token", rate_limits.apply(LimitedRoute::OAuth2Token, post(oauth2_token)))
+    .with_state(shared_state.clone());
+
+  let router = Router::new()
+    .route("/health", get(health_check))
+    .merge(oauth2_routes)
+    .nest("/v1.0", api_routes);
+
+  // remote addresses are recorded in the audit log
+  let server = axum::serve(tcp_listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
-  let server = axum::serve(tcp_listener, router);
+  // start pruning expired refresh tokens
+  shared_state.table.start_pruning(shared_state.prune_period);
 
   // start blind RSA key rotation
   #[cfg(feature = "blind-signatures")]
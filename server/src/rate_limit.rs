@@ -0,0 +1,239 @@
+use crate::{constants::MAX_RATE_LIMITED_CLIENTS, error::*, log::*};
+use axum::{
+  extract::{ConnectInfo, Request, State},
+  http::{header::RETRY_AFTER, HeaderMap, StatusCode},
+  middleware::{self, Next},
+  response::{IntoResponse, Response},
+  routing::MethodRouter,
+  Json,
+};
+use serde_json::json;
+use std::{
+  collections::{BTreeSet, HashMap},
+  net::{IpAddr, SocketAddr},
+  str::FromStr,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Period in which the number of requests given as a rate limit is allowed
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+
+/// Routes whose requests are limited for each client IP, since they verify passwords or sign tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+  Tokens,
+  Refresh,
+  BlindSign,
+  ClientTokens,
+  OAuth2Token,
+}
+
+impl FromStr for LimitedRoute {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let route = match s {
+      "tokens" => LimitedRoute::Tokens,
+      "refresh" => LimitedRoute::Refresh,
+      "blindsign" => LimitedRoute::BlindSign,
+      "client_tokens" => LimitedRoute::ClientTokens,
+      "oauth2_token" => LimitedRoute::OAuth2Token,
+      _ => bail!("Unknown rate-limited route: {s}"),
+    };
+    Ok(route)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Rate limits of routes, which are applied per client IP
+pub struct RateLimits {
+  /// Maximum number of requests per minute for each route. Routes not listed are not limited.
+  pub requests_per_min: HashMap<LimitedRoute, u32>,
+  /// Reverse proxies whose `X-Forwarded-For` header is trusted to tell the client IP
+  pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimits {
+  /// Put the rate limit of the route, if any, in front of the handler so that excess requests never reach it
+  pub fn apply<S>(&self, route: LimitedRoute, method_router: MethodRouter<S>) -> MethodRouter<S>
+  where
+    S: Clone + Send + Sync + 'static,
+  {
+    let Some(requests_per_min) = self.requests_per_min.get(&route) else {
+      return method_router;
+    };
+    let limiter = Arc::new(RateLimiter::new(*requests_per_min, self.trusted_proxies.clone()));
+    method_router.layer(middleware::from_fn_with_state(limiter, rate_limit))
+  }
+}
+
+/// Token bucket of a client, which is refilled continuously up to the capacity
+struct Bucket {
+  tokens: f64,
+  updated_at: Instant,
+}
+
+/// Token buckets of clients indexed also by the time of the last update, so that the least recently updated one is
+/// found without scanning all buckets
+#[derive(Default)]
+struct Buckets {
+  by_client: HashMap<IpAddr, Bucket>,
+  by_update: BTreeSet<(Instant, IpAddr)>,
+}
+
+/// Token buckets of clients for a route
+pub struct RateLimiter {
+  /// Capacity of each bucket, which is also the number of tokens refilled per minute
+  capacity: u32,
+  /// Maximum number of clients whose buckets are kept
+  max_clients: usize,
+  trusted_proxies: Vec<IpAddr>,
+  buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+  pub fn new(requests_per_min: u32, trusted_proxies: Vec<IpAddr>) -> Self {
+    Self {
+      capacity: requests_per_min,
+      max_clients: MAX_RATE_LIMITED_CLIENTS,
+      trusted_proxies,
+      buckets: Mutex::new(Buckets::default()),
+    }
+  }
+
+  /// Take a token from the bucket of the client. If the bucket is empty, returns the time until a token is refilled.
+  fn acquire(&self, client_ip: IpAddr, now: Instant) -> std::result::Result<(), Duration> {
+    let capacity = self.capacity as f64;
+    let Ok(mut buckets) = self.buckets.lock() else {
+      // never block clients due to a poisoned lock
+      return Ok(());
+    };
+    let Buckets { by_client, by_update } = &mut *buckets;
+    if by_client.len() >= self.max_clients && !by_client.contains_key(&client_ip) {
+      // forget the least recently updated client, which is the most likely to have a full bucket again
+      if let Some((_, evicted)) = by_update.pop_first() {
+        by_client.remove(&evicted);
+      }
+    }
+    let bucket = by_client.entry(client_ip).or_insert(Bucket {
+      tokens: capacity,
+      updated_at: now,
+    });
+    let elapsed = now.duration_since(bucket.updated_at).as_secs_f64() / RATE_LIMIT_PERIOD.as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * capacity).min(capacity);
+    by_update.remove(&(bucket.updated_at, client_ip));
+    by_update.insert((now, client_ip));
+    bucket.updated_at = now;
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+    Err(RATE_LIMIT_PERIOD.mul_f64((1.0 - bucket.tokens) / capacity))
+  }
+
+  /// IP address of the client. If the peer is a trusted proxy, the nearest untrusted address in `X-Forwarded-For` is taken.
+  fn client_ip(&self, remote_addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    let mut client_ip = remote_addr.ip();
+    if !self.trusted_proxies.contains(&client_ip) {
+      return client_ip;
+    }
+    let forwarded = headers
+      .get_all("x-forwarded-for")
+      .iter()
+      .filter_map(|v| v.to_str().ok())
+      .flat_map(|v| v.split(','))
+      .map(str::trim)
+      .collect::<Vec<_>>();
+    // proxies append the address of their peer, so walk from the nearest hop
+    for hop in forwarded.iter().rev() {
+      let Ok(ip) = hop.parse::<IpAddr>() else {
+        break;
+      };
+      client_ip = ip;
+      if !self.trusted_proxies.contains(&ip) {
+        break;
+      }
+    }
+    client_ip
+  }
+}
+
+/// Middleware rejecting requests with 429 Too Many Requests when the client runs out of tokens
+async fn rate_limit(
+  State(limiter): State<Arc<RateLimiter>>,
+  ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let client_ip = limiter.client_ip(remote_addr, request.headers());
+  if let Err(retry_after) = limiter.acquire(client_ip, Instant::now()) {
+    debug!("Rate limit exceeded by {client_ip} for {}", request.uri().path());
+    let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let body = Json(json!({
+        "error": "Too many requests",
+    }));
+    return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after_secs.to_string())], body).into_response();
+  }
+  next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn buckets_are_refilled_per_client() {
+    let limiter = RateLimiter::new(2, vec![]);
+    let (client, another) = ("192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+    let now = Instant::now();
+    assert!(limiter.acquire(client, now).is_ok());
+    assert!(limiter.acquire(client, now).is_ok());
+    assert_eq!(limiter.acquire(client, now), Err(Duration::from_secs(30)));
+    assert!(limiter.acquire(another, now).is_ok());
+
+    // a token is refilled every 30 seconds
+    assert!(limiter.acquire(client, now + Duration::from_secs(30)).is_ok());
+    assert!(limiter.acquire(client, now + Duration::from_secs(30)).is_err());
+    assert!(limiter.acquire(client, now + Duration::from_secs(600)).is_ok());
+    assert!(limiter.acquire(client, now + Duration::from_secs(600)).is_ok());
+    assert!(limiter.acquire(client, now + Duration::from_secs(600)).is_err());
+  }
+
+  #[test]
+  fn buckets_are_bounded() {
+    let mut limiter = RateLimiter::new(1, vec![]);
+    limiter.max_clients = 100;
+    let now = Instant::now();
+    let client: IpAddr = "192.0.2.1".parse().unwrap();
+    assert!(limiter.acquire(client, now).is_ok());
+
+    // clients rotating addresses never grow the buckets beyond the limit
+    for i in 0..1000u32 {
+      let ip = IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i));
+      assert!(limiter.acquire(ip, now + Duration::from_millis(i as u64 + 1)).is_ok());
+      let buckets = limiter.buckets.lock().unwrap();
+      assert!(buckets.by_client.len() <= 100);
+      assert_eq!(buckets.by_client.len(), buckets.by_update.len());
+    }
+    // the least recently updated client was evicted and starts with a full bucket
+    assert!(!limiter.buckets.lock().unwrap().by_client.contains_key(&client));
+
+    // recently updated clients are kept
+    let recent = IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + 999));
+    assert!(limiter.acquire(recent, now + Duration::from_millis(1000)).is_err());
+  }
+
+  #[test]
+  fn client_ip_is_taken_from_trusted_proxies() {
+    let proxy: SocketAddr = "10.0.0.1:443".parse().unwrap();
+    let limiter = RateLimiter::new(1, vec![proxy.ip(), "10.0.0.2".parse().unwrap()]);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "203.0.113.9, 198.51.100.7, 10.0.0.2".parse().unwrap());
+
+    assert_eq!(limiter.client_ip(proxy, &headers), "198.51.100.7".parse::<IpAddr>().unwrap());
+    // headers from untrusted peers are ignored since they can be forged
+    let peer: SocketAddr = "192.0.2.1:443".parse().unwrap();
+    assert_eq!(limiter.client_ip(peer, &headers), peer.ip());
+    assert_eq!(limiter.client_ip(proxy, &HeaderMap::new()), proxy.ip());
+  }
+}
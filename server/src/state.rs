@@ -1,21 +1,33 @@
 use crate::{
-  entity::User,
+  breached_passwords::BreachedPasswords,
+  constants::{
+    DEFAUTL_CLIENT_ID, LOCKOUT_DURATION_MINS, LOCKOUT_THRESHOLD, MAX_LOCKOUT_DURATION_MINS, REFRESH_TOKEN_DURATION_MINS,
+  },
+  entity::{
+    is_totp_code, normalize_recovery_code, AccountState, AuditEvent, AuditEventKind, AuthMethod, Client, EncodedHash,
+    Entity, Password, PasswordPolicy, RefreshTokenSecret, SecondFactorKey, User, Username,
+  },
   error::*,
-  table::{SqliteRefreshTokenTable, SqliteUserTable},
+  rate_limit::RateLimits,
+  table::{AuditLog, ClientTable, RefreshTokenTable, TotpTable, UserSearchKey, UserTable},
 };
 use libcommon::{
-  token_fields::{Audiences, ClientId, IdToken, Issuer},
-  Claims, SigningKey, TokenBody, TokenMeta, ValidationOptions,
+  token_fields::{Audiences, ClientId, Field, IdToken, Issuer, SubscriberId, TryNewField},
+  Claims, SigningKey, TokenBody, TokenMeta, UserClaims, ValidationOptions, JWT_DURATION_MINS, PASSWORD_CHANGE_SCOPE,
 };
+use chrono::{Duration, Local};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{
+  collections::{HashMap, HashSet},
+  net::SocketAddr,
+  sync::Arc,
+};
 
-#[cfg(feature = "blind-signatures")]
 use crate::log::*;
 #[cfg(feature = "blind-signatures")]
 use libcommon::blind_sig;
 #[cfg(feature = "blind-signatures")]
-use std::sync::{Arc, RwLock};
+use std::sync::RwLock;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 /// Token generated at server as a response to login request
@@ -24,45 +36,235 @@ pub struct Token {
   pub meta: TokenMeta,
 }
 
+#[derive(Debug, Clone)]
+/// Lifetimes of id and refresh tokens, which can be overridden for each client
+pub struct TokenLifetimes {
+  pub id_token: Duration,
+  pub refresh_token: Duration,
+  pub client_id_token: HashMap<ClientId, Duration>,
+  pub client_refresh_token: HashMap<ClientId, Duration>,
+}
+
+impl Default for TokenLifetimes {
+  fn default() -> Self {
+    Self {
+      id_token: Duration::minutes(JWT_DURATION_MINS as i64),
+      refresh_token: Duration::minutes(REFRESH_TOKEN_DURATION_MINS as i64),
+      client_id_token: HashMap::new(),
+      client_refresh_token: HashMap::new(),
+    }
+  }
+}
+
+impl TokenLifetimes {
+  /// Lifetime of id tokens issued to the client
+  pub fn id_token(&self, client_id: &ClientId) -> Duration {
+    self.client_id_token.get(client_id).copied().unwrap_or(self.id_token)
+  }
+  /// Lifetime of refresh tokens issued to the client
+  pub fn refresh_token(&self, client_id: &ClientId) -> Duration {
+    self.client_refresh_token.get(client_id).copied().unwrap_or(self.refresh_token)
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Policy to lock accounts after consecutive failed logins
+pub struct LockoutPolicy {
+  /// Number of consecutive failed logins to lock the account. Zero disables lockouts.
+  pub threshold: u32,
+  pub duration: Duration,
+  /// Double the period at every lockout until the user logs in successfully
+  pub backoff: bool,
+}
+
+impl Default for LockoutPolicy {
+  fn default() -> Self {
+    Self {
+      threshold: LOCKOUT_THRESHOLD,
+      duration: Duration::minutes(LOCKOUT_DURATION_MINS as i64),
+      backoff: false,
+    }
+  }
+}
+
+impl LockoutPolicy {
+  /// Period to lock the account that has just failed to log in the given number of times in a row, if it is to be locked
+  pub fn lock_duration(&self, failed_logins: u32) -> Option<Duration> {
+    if self.threshold == 0 || failed_logins == 0 || !failed_logins.is_multiple_of(self.threshold) {
+      return None;
+    }
+    if !self.backoff {
+      return Some(self.duration);
+    }
+    let max_duration = Duration::minutes(MAX_LOCKOUT_DURATION_MINS).max(self.duration);
+    let exponent = (failed_logins / self.threshold - 1).min(30);
+    let duration = self.duration.checked_mul(1 << exponent).unwrap_or(max_duration);
+    Some(duration.min(max_duration))
+  }
+}
+
+/// Result of checking the second factor of a user whose password has been verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondFactor {
+  /// The user has not enabled TOTP
+  NotEnrolled,
+  /// A TOTP code or an unused recovery code is verified
+  Verified,
+  /// TOTP is enabled but no code is given
+  Missing,
+  /// The code is wrong or has already been used
+  Invalid,
+}
+
+/// Result of authenticating a user who logs in with the username, the password and the second factor if enabled
+#[derive(Debug)]
+pub enum UserAuthentication {
+  /// The user is authenticated with the methods of the login
+  Authenticated(Box<User>, Vec<AuthMethod>),
+  NoSuchUser,
+  AccountDisabled,
+  /// The user has been locked, or is locked right now by this failure
+  AccountLocked,
+  InvalidPassword,
+  TotpRequired,
+  InvalidTotp,
+}
+
+/// Result of checking the client id given in a request against the client registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAuthorization {
+  /// The client is registered and enabled, or any client is allowed since the server is started without clients
+  Allowed(ClientId),
+  /// Clients are registered but no client id is given
+  Missing,
+  /// The client is not registered or disabled
+  Unauthorized,
+}
+
 /// For JWT
 pub struct CryptoState {
   pub signing_key: SigningKey,
   pub issuer: Issuer,
-  pub audiences: Option<Audiences>,
+  /// Secret for the keyed hashes of refresh tokens stored in the token table
+  pub refresh_token_secret: RefreshTokenSecret,
+  /// Key encrypting TOTP secrets and hashing recovery codes stored in the TOTP tables
+  pub second_factor_key: SecondFactorKey,
+  pub lifetimes: TokenLifetimes,
 }
 
 impl CryptoState {
-  pub fn generate_token(&self, user: &User, client_id: &ClientId, refresh_required: bool) -> Result<Token> {
+  /// Issue an id token of the user authenticated with the given methods, with a refresh token if required.
+  /// Tokens restricted to changing the password are issued to this server itself instead of the client, so that
+  /// validators of the client never accept them.
+  pub fn generate_token(
+    &self,
+    user: &User,
+    client_id: &ClientId,
+    amr: &[AuthMethod],
+    refresh_required: bool,
+  ) -> Result<Token> {
+    let claims = UserClaims {
+      amr: amr.iter().map(|m| m.as_str().to_string()).collect(),
+      ..user.claims()
+    };
+    let audience = match user.must_change_password {
+      true => self.password_change_audience()?,
+      false => client_id.clone(),
+    };
     let body = self.signing_key.authorize(
       &user.subscriber_id,
-      client_id,
+      &audience,
       &self.issuer,
-      user.is_admin(),
+      &claims,
       refresh_required,
+      self.lifetimes.id_token(client_id),
     )?;
     let meta = TokenMeta {
       username: user.username().to_string(),
       is_admin: user.is_admin(),
+      must_change_password: user.must_change_password,
     };
 
     Ok(Token { body, meta })
   }
+  /// Audience of tokens restricted to changing the password, which is the issuer, i.e., this server itself
+  pub fn password_change_audience(&self) -> Result<ClientId> {
+    ClientId::new(self.issuer.as_str())
+  }
+  /// Issue a token to the client itself authenticated via the client credentials grant, without refresh token.
+  /// The client id is given as `sub` with the client flag claim, so that the token is never taken for a user's one.
+  pub fn generate_client_token(&self, client_id: &ClientId) -> Result<TokenBody> {
+    let claims = UserClaims {
+      is_client: true,
+      ..Default::default()
+    };
+    self.signing_key.authorize(
+      &SubscriberId::new(client_id.as_str())?,
+      client_id,
+      &self.issuer,
+      &claims,
+      false,
+      self.lifetimes.id_token(client_id),
+    )
+  }
+  /// Verify the signature, issuer and scope of the id token, rejecting tokens restricted to changing the password.
+  /// The audience is not checked here but against the client registry by [`AppState::verify_token`].
   pub fn verify_token(&self, id_token: &IdToken) -> Result<Claims> {
-    let mut iss = std::collections::HashSet::new();
+    self.verify_token_with_scopes(id_token, HashSet::new())
+  }
+  /// Verify the id token, accepting tokens restricted to changing the password as well as unrestricted ones
+  pub fn verify_password_change_token(&self, id_token: &IdToken) -> Result<Claims> {
+    self.verify_token_with_scopes(id_token, HashSet::from([PASSWORD_CHANGE_SCOPE.to_string()]))
+  }
+  fn verify_token_with_scopes(&self, id_token: &IdToken, allowed_scopes: HashSet<String>) -> Result<Claims> {
+    let mut iss = HashSet::new();
     iss.insert(self.issuer.clone());
 
+    // audiences are checked against the client registry, which can be updated without a restart
     let vo = ValidationOptions {
-      allowed_audiences: self.audiences.clone(),
+      allowed_audiences: None,
       allowed_issuers: Some(iss),
+      allowed_scopes,
       ..Default::default()
     };
 
     self.signing_key.validate(id_token, &vo)
   }
 }
+/// Tables of the storage backend chosen at startup
 pub struct TableState {
-  pub user: SqliteUserTable,
-  pub refresh_token: SqliteRefreshTokenTable,
+  pub user: Arc<dyn UserTable>,
+  pub refresh_token: Arc<dyn RefreshTokenTable>,
+  pub audit_log: Arc<dyn AuditLog>,
+  pub totp: Arc<dyn TotpTable>,
+  pub client: Arc<dyn ClientTable>,
+}
+
+impl TableState {
+  /// Record the event to the audit log. A failure to record is logged but never fails the request.
+  pub async fn record_audit_event(&self, event: AuditEvent) {
+    if let Err(e) = self.audit_log.record(&event).await {
+      error!("Failed to record audit event {}: {e}", event.kind.as_str());
+    }
+  }
+
+  /// Start pruning expired refresh tokens periodically in a separate task
+  pub fn start_pruning(&self, period: tokio::time::Duration) {
+    info!("Starting periodic pruning of expired refresh tokens");
+    let refresh_token = self.refresh_token.clone();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(period);
+      interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+      loop {
+        interval.tick().await;
+        match refresh_token.prune_expired().await {
+          Ok(0) => debug!("No expired refresh token to prune"),
+          Ok(cnt) => info!("Pruned {cnt} expired refresh tokens"),
+          Err(e) => error!("Failed to prune expired refresh tokens: {e}"),
+        }
+      }
+    });
+  }
 }
 
 pub struct AppState {
@@ -73,9 +275,289 @@ pub struct AppState {
   pub blind_crypto: BlindCryptoState,
 
   pub table: TableState,
+  /// Allow any client and issue tokens to the default client id while no client is registered, which is enabled at
+  /// startup as the server is started without `--client-ids`. Once a client is registered, the registry is enforced.
+  pub open_without_clients: bool,
+  /// Period to prune expired refresh tokens
+  pub prune_period: tokio::time::Duration,
+  pub lockout: LockoutPolicy,
+  pub rate_limits: RateLimits,
+  pub password_policy: PasswordPolicy,
+  /// Passwords rejected on creating and changing passwords since they are known to be breached
+  pub breached_passwords: Option<BreachedPasswords>,
 }
 
-// client ids = audiences テーブルは持つのをやめた。テーブルに格納する意味はあんまりなさそう。
+impl AppState {
+  /// Check the client id of the request against the client registry, which is looked up on every request so that
+  /// registered, updated and deleted clients take effect without a restart. If the server is open without clients and
+  /// none is registered, any client is allowed and tokens are issued to the default client id.
+  pub async fn authorize_client(&self, client_id: Option<ClientId>) -> Result<ClientAuthorization> {
+    if self.open_without_clients && self.table.client.count().await? == 0 {
+      return Ok(ClientAuthorization::Allowed(ClientId::new(DEFAUTL_CLIENT_ID)?));
+    }
+    let Some(client_id) = client_id else {
+      return Ok(ClientAuthorization::Missing);
+    };
+    match self.table.client.find(&client_id).await? {
+      Some(client) if client.enabled => Ok(ClientAuthorization::Allowed(client_id)),
+      _ => Ok(ClientAuthorization::Unauthorized),
+    }
+  }
+
+  /// Verify the id token, and check that its audience is a registered and enabled client, so that tokens issued to
+  /// clients disabled or deleted since are rejected. Tokens restricted to changing the password are rejected.
+  pub async fn verify_token(&self, id_token: &IdToken) -> Result<Claims> {
+    let claims = self.crypto.verify_token(id_token)?;
+    self.verify_audience(&claims).await?;
+    Ok(claims)
+  }
+
+  /// Verify the id token like [`AppState::verify_token`], accepting tokens restricted to changing the password as well
+  pub async fn verify_password_change_token(&self, id_token: &IdToken) -> Result<Claims> {
+    let claims = self.crypto.verify_password_change_token(id_token)?;
+    self.verify_audience(&claims).await?;
+    Ok(claims)
+  }
+
+  async fn verify_audience(&self, claims: &Claims) -> Result<()> {
+    let Some(aud) = claims.custom.get("aud") else {
+      bail!("No audience is specified in JWT");
+    };
+    let audiences = serde_json::from_value::<Audiences>(aud.clone())?;
+    // restricted tokens are issued to this server itself rather than to any client
+    if claims.custom.contains_key("scope") {
+      if !audiences.contains(&self.crypto.password_change_audience()?) {
+        bail!("Restricted token is issued to a client");
+      }
+      return Ok(());
+    }
+    match self.authorize_client(audiences.get_one().cloned()).await? {
+      ClientAuthorization::Allowed(_) => Ok(()),
+      _ => bail!("Token is issued to an unauthorized client"),
+    }
+  }
+
+  /// Count a failed login of the user, and lock the account if the lockout policy says so.
+  /// Returns true if the account has been locked. A failure to update the count is logged but never fails the request.
+  pub async fn record_failed_login(&self, user: &User, remote_addr: SocketAddr) -> bool {
+    let search_key = UserSearchKey::SubscriberId(&user.subscriber_id);
+    let failed_logins = match self.table.user.increment_failed_logins(search_key).await {
+      Ok(cnt) => cnt,
+      Err(e) => {
+        error!("Failed to count failed login of {}: {e}", user.username());
+        return false;
+      }
+    };
+    let Some(duration) = self.lockout.lock_duration(failed_logins) else {
+      return false;
+    };
+    let locked_until = Local::now() + duration;
+    let search_key = UserSearchKey::SubscriberId(&user.subscriber_id);
+    if let Err(e) = self.table.user.lock(search_key, locked_until).await {
+      error!("Failed to lock {}: {e}", user.username());
+      return false;
+    }
+    warn!(
+      "{} is locked until {} after {failed_logins} consecutive failed logins",
+      user.username(),
+      locked_until.to_rfc3339()
+    );
+    let event = AuditEvent::new(AuditEventKind::AccountLocked, Some(remote_addr))
+      .actor(&user.subscriber_id)
+      .target(user.username());
+    self.table.record_audit_event(event).await;
+    true
+  }
+
+  /// Verify the TOTP code, or the recovery code which is then consumed, if the user has enabled TOTP.
+  /// TOTP codes are accepted only once, and the use of recovery codes is recorded in the audit log.
+  pub async fn verify_second_factor(
+    &self,
+    user: &User,
+    code: Option<&str>,
+    remote_addr: SocketAddr,
+  ) -> Result<SecondFactor> {
+    let Some(factor) = self.table.totp.find(&user.subscriber_id).await? else {
+      return Ok(SecondFactor::NotEnrolled);
+    };
+    if !factor.enabled {
+      return Ok(SecondFactor::NotEnrolled);
+    }
+    let Some(code) = code.map(str::trim) else {
+      return Ok(SecondFactor::Missing);
+    };
+
+    if is_totp_code(code) {
+      let secret = self.crypto.second_factor_key.open(&factor.secret, &user.subscriber_id)?;
+      let Some(step) = secret.verify(code, Local::now().timestamp())? else {
+        return Ok(SecondFactor::Invalid);
+      };
+      // the code stays valid during its step and the adjacent ones, so it must not be replayed
+      if !self.table.totp.use_step(&user.subscriber_id, step).await? {
+        return Ok(SecondFactor::Invalid);
+      }
+      return Ok(SecondFactor::Verified);
+    }
+
+    // malformed codes are just wrong codes, which count as failed logins
+    let Some(recovery_code) = normalize_recovery_code(code) else {
+      return Ok(SecondFactor::Invalid);
+    };
+    // the keyed hash is looked up at once and deleted atomically, so that a code is never used twice
+    let code_hash = self.crypto.second_factor_key.hash_recovery_code(&recovery_code)?;
+    if !self.table.totp.consume_recovery_code(&user.subscriber_id, &code_hash).await? {
+      return Ok(SecondFactor::Invalid);
+    }
+    warn!("{} logged in with a recovery code", user.username());
+    let event = AuditEvent::new(AuditEventKind::RecoveryCodeUsed, Some(remote_addr))
+      .actor(&user.subscriber_id)
+      .target(user.username());
+    self.table.record_audit_event(event).await;
+    Ok(SecondFactor::Verified)
+  }
+
+  /// Authenticate the user logging in via the client with the password and, if enabled, the second factor. Failures are
+  /// recorded in the audit log and wrong passwords and codes count as failed logins, while a successful login resets
+  /// the count and rehashes the password if needed. Errors are failures of the storage or of the password hashing.
+  pub async fn authenticate_user(
+    &self,
+    username: &Username,
+    password: &Password,
+    totp: Option<&str>,
+    client_id: &ClientId,
+    remote_addr: SocketAddr,
+  ) -> Result<UserAuthentication> {
+    let Some(user) = self.table.user.find_user(UserSearchKey::Username(username)).await? else {
+      let event = AuditEvent::new(AuditEventKind::LoginFailed, Some(remote_addr))
+        .target(username.as_str())
+        .client_id(client_id);
+      self.table.record_audit_event(event).await;
+      return Ok(UserAuthentication::NoSuchUser);
+    };
+    let login_failed = || {
+      AuditEvent::new(AuditEventKind::LoginFailed, Some(remote_addr))
+        .actor(&user.subscriber_id)
+        .target(username.as_str())
+        .client_id(client_id)
+    };
+
+    // disabled and locked users are refused whatever the password is
+    let refused = match user.account_state() {
+      AccountState::Active => None,
+      AccountState::Disabled => Some(UserAuthentication::AccountDisabled),
+      AccountState::Locked => Some(UserAuthentication::AccountLocked),
+    };
+    if let Some(refused) = refused {
+      self.table.record_audit_event(login_failed()).await;
+      return Ok(refused);
+    }
+
+    if !password.verify(&user.encoded_hash)? {
+      self.table.record_audit_event(login_failed()).await;
+      if self.record_failed_login(&user, remote_addr).await {
+        return Ok(UserAuthentication::AccountLocked);
+      }
+      return Ok(UserAuthentication::InvalidPassword);
+    }
+
+    // users who have enabled TOTP also need a TOTP code or a recovery code, whose failures count as failed logins
+    let second_factor = self.verify_second_factor(&user, totp, remote_addr).await?;
+    match second_factor {
+      SecondFactor::NotEnrolled | SecondFactor::Verified => (),
+      SecondFactor::Missing => return Ok(UserAuthentication::TotpRequired),
+      SecondFactor::Invalid => {
+        self.table.record_audit_event(login_failed()).await;
+        if self.record_failed_login(&user, remote_addr).await {
+          return Ok(UserAuthentication::AccountLocked);
+        }
+        return Ok(UserAuthentication::InvalidTotp);
+      }
+    }
+    self.reset_failed_logins(&user).await;
+    self.rehash_password(&user, password).await;
+    let amr = AuthMethod::login(second_factor == SecondFactor::Verified);
+    Ok(UserAuthentication::Authenticated(Box::new(user), amr))
+  }
+
+  /// Reset the count of failed logins of the user who has just logged in
+  pub async fn reset_failed_logins(&self, user: &User) {
+    if user.failed_logins == 0 {
+      return;
+    }
+    let search_key = UserSearchKey::SubscriberId(&user.subscriber_id);
+    if let Err(e) = self.table.user.reset_failed_logins(search_key).await {
+      error!("Failed to reset failed logins of {}: {e}", user.username());
+    }
+  }
+
+  /// Replace the password hash of the user who has just logged in if it was computed with outdated parameters.
+  /// A failure is logged but never fails the request, since the user is already authenticated.
+  pub async fn rehash_password(&self, user: &User, password: &Password) {
+    if !user.encoded_hash.needs_rehash() {
+      return;
+    }
+    let new_hash = match EncodedHash::generate(password) {
+      Ok(hash) => hash,
+      Err(e) => {
+        error!("Failed to rehash password of {}: {e}", user.username());
+        return;
+      }
+    };
+    let search_key = UserSearchKey::SubscriberId(&user.subscriber_id);
+    if let Err(e) = self.table.user.replace_encoded_hash(search_key, &user.encoded_hash, &new_hash).await {
+      error!("Failed to store rehashed password of {}: {e}", user.username());
+      return;
+    }
+    debug!("Rehashed password of {} with the current parameters", user.username());
+  }
+
+  /// Authenticate the confidential client, which has been looked up by its id, with its client secret, which is rehashed
+  /// if needed. Returns None if the client is unknown, disabled or public, or if the secret is wrong, which is recorded
+  /// in the audit log. Errors are those of verifying the secret.
+  pub async fn authenticate_client(
+    &self,
+    client_id: &ClientId,
+    client: Option<Client>,
+    client_secret: &Password,
+    remote_addr: SocketAddr,
+  ) -> Result<Option<Client>> {
+    let failed_event = AuditEvent::new(AuditEventKind::ClientCredentialsFailed, Some(remote_addr)).client_id(client_id);
+    let Some((client, secret_hash)) = client
+      .filter(|c| c.enabled)
+      .and_then(|c| c.secret_hash.clone().map(|h| (c, h)))
+    else {
+      self.table.record_audit_event(failed_event).await;
+      return Ok(None);
+    };
+    if !client_secret.verify(&secret_hash)? {
+      self.table.record_audit_event(failed_event).await;
+      return Ok(None);
+    }
+    self.rehash_client_secret(&client, client_secret).await;
+    Ok(Some(client))
+  }
+
+  /// Replace the secret hash of the client that has just been authenticated if it was computed with outdated
+  /// parameters. A failure is logged but never fails the request, since the client is already authenticated.
+  pub async fn rehash_client_secret(&self, client: &Client, client_secret: &Password) {
+    let Some(current_hash) = client.secret_hash.as_ref().filter(|h| h.needs_rehash()) else {
+      return;
+    };
+    let client_id = client.client_id.as_str();
+    let new_hash = match EncodedHash::generate(client_secret) {
+      Ok(hash) => hash,
+      Err(e) => {
+        error!("Failed to rehash client secret of {client_id}: {e}");
+        return;
+      }
+    };
+    if let Err(e) = self.table.client.replace_secret_hash(&client.client_id, current_hash, &new_hash).await {
+      error!("Failed to store rehashed client secret of {client_id}: {e}");
+      return;
+    }
+    debug!("Rehashed client secret of {client_id} with the current parameters");
+  }
+}
 
 /* ------------------------------------------------------ */
 #[cfg(feature = "blind-signatures")]
@@ -89,6 +571,8 @@ pub struct BlindCryptoState {
   pub key_size: usize,
   /// Rotation period of RSA key pair to invalidate old tokens
   pub rotation_period: tokio::time::Duration,
+  /// Whether blind signatures are issued only to users whose roles permit it
+  pub role_required: bool,
 }
 
 #[cfg(feature = "blind-signatures")]
@@ -136,3 +620,148 @@ impl BlindCryptoState {
     });
   }
 }
+
+/* ------------------------------------------------------ */
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+  use crate::{
+    constants::ADMIN_USERNAME,
+    entity::{Password, RefreshTokenInfo, TokenFamilyId, TryNewEntity, Username},
+    table::{seed_clients, setup_tables, UserSearchKey},
+  };
+
+  pub(crate) const TEST_ADMIN_PASSWORD: &str = "admin_password_1234";
+  pub(crate) const TEST_CLIENT_ID: &str = "client_id1";
+  const SIGNING_KEY_PEM: &str = include_str!("../sample-keys/p256.private_key.pem");
+
+  #[cfg(feature = "blind-signatures")]
+  /// RSA key generation is slow, so a single key is shared among tests
+  fn shared_blind_signing_key() -> Arc<RwLock<blind_sig::RsaPrivateKey>> {
+    static KEY: std::sync::OnceLock<Arc<RwLock<blind_sig::RsaPrivateKey>>> = std::sync::OnceLock::new();
+    KEY
+      .get_or_init(|| Arc::new(RwLock::new(blind_sig::RsaPrivateKey::new(Some(2048)).unwrap())))
+      .clone()
+  }
+
+  fn test_crypto_state() -> CryptoState {
+    CryptoState {
+      signing_key: SigningKey::from_pem(SIGNING_KEY_PEM).unwrap(),
+      issuer: Issuer::new("https://auth.example.com/v1.0").unwrap(),
+      refresh_token_secret: RefreshTokenSecret::derive(SIGNING_KEY_PEM.as_bytes()).unwrap(),
+      second_factor_key: SecondFactorKey::derive(SIGNING_KEY_PEM.as_bytes()).unwrap(),
+      lifetimes: TokenLifetimes::default(),
+    }
+  }
+
+  /// Application state backed by the in-memory storage with the admin password of [`TEST_ADMIN_PASSWORD`] and the
+  /// registered client of [`TEST_CLIENT_ID`]
+  pub(crate) async fn test_state() -> Arc<AppState> {
+    build_test_state(Some(Audiences::new(TEST_CLIENT_ID).unwrap())).await
+  }
+
+  /// Application state of the server started without `--client-ids`, where no client is registered
+  pub(crate) async fn test_state_without_client_ids() -> Arc<AppState> {
+    build_test_state(None).await
+  }
+
+  async fn build_test_state(client_ids: Option<Audiences>) -> Arc<AppState> {
+    let table = setup_tables("memory:").await.unwrap();
+    table
+      .user
+      .update_password(
+        UserSearchKey::Username(&Username::new(ADMIN_USERNAME).unwrap()),
+        &Password::new(TEST_ADMIN_PASSWORD).unwrap(),
+      )
+      .await
+      .unwrap();
+    if let Some(client_ids) = &client_ids {
+      seed_clients(table.client.as_ref(), client_ids).await.unwrap();
+    }
+
+    Arc::new(AppState {
+      listen_socket: "127.0.0.1:0".parse().unwrap(),
+      crypto: test_crypto_state(),
+
+      #[cfg(feature = "blind-signatures")]
+      blind_crypto: BlindCryptoState {
+        signing_key: shared_blind_signing_key(),
+        rotated_at: Arc::new(0.into()),
+        key_size: 2048,
+        rotation_period: tokio::time::Duration::from_secs(60),
+        role_required: false,
+      },
+
+      table,
+      open_without_clients: client_ids.is_none(),
+      prune_period: tokio::time::Duration::from_secs(60),
+      lockout: LockoutPolicy::default(),
+      rate_limits: RateLimits::default(),
+      password_policy: PasswordPolicy::default(),
+      breached_passwords: None,
+    })
+  }
+
+  #[test]
+  fn token_lifetimes_can_be_overridden_per_client() {
+    let mobile = ClientId::new("mobile").unwrap();
+    let mut crypto = test_crypto_state();
+    crypto.lifetimes.id_token = Duration::minutes(10);
+    crypto.lifetimes.client_id_token.insert(mobile.clone(), Duration::minutes(60));
+    crypto.lifetimes.client_refresh_token.insert(mobile.clone(), Duration::days(90));
+
+    let default_client = ClientId::new(TEST_CLIENT_ID).unwrap();
+    assert_eq!(crypto.lifetimes.refresh_token(&default_client), crypto.lifetimes.refresh_token);
+    assert_eq!(crypto.lifetimes.refresh_token(&mobile), Duration::days(90));
+
+    let user = User::new(&Username::new("user").unwrap(), None).unwrap();
+    for (client_id, expected) in [(default_client, Duration::minutes(10)), (mobile, Duration::minutes(60))] {
+      let token = crypto.generate_token(&user, &client_id, &[], false).unwrap();
+      let claims = crypto.signing_key.validation_key().validate(&token.body.id, &Default::default()).unwrap();
+      assert_eq!(claims.expiration.unwrap() - claims.issued_at.unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn lockout_period_grows_with_backoff() {
+    let mut policy = LockoutPolicy {
+      threshold: 3,
+      duration: Duration::minutes(10),
+      backoff: false,
+    };
+    assert_eq!(policy.lock_duration(2), None);
+    assert_eq!(policy.lock_duration(3), Some(Duration::minutes(10)));
+    assert_eq!(policy.lock_duration(4), None);
+    assert_eq!(policy.lock_duration(6), Some(Duration::minutes(10)));
+
+    policy.backoff = true;
+    assert_eq!(policy.lock_duration(3), Some(Duration::minutes(10)));
+    assert_eq!(policy.lock_duration(6), Some(Duration::minutes(20)));
+    assert_eq!(policy.lock_duration(9), Some(Duration::minutes(40)));
+    assert_eq!(policy.lock_duration(300), Some(Duration::minutes(MAX_LOCKOUT_DURATION_MINS)));
+
+    policy.threshold = 0;
+    assert_eq!(policy.lock_duration(3), None);
+  }
+
+  #[tokio::test]
+  async fn pruning_task_deletes_expired_refresh_tokens() {
+    let state = test_state().await;
+    let user = User::new(&Username::new("user").unwrap(), None).unwrap();
+    let client_id = ClientId::new(TEST_CLIENT_ID).unwrap();
+    let token = state.crypto.generate_token(&user, &client_id, &[], true).unwrap();
+    let expired = RefreshTokenInfo::try_new(
+      &token.body,
+      &state.crypto.refresh_token_secret,
+      TokenFamilyId::generate(),
+      AuthMethod::login(false),
+      Duration::minutes(-1),
+    )
+    .unwrap();
+    state.table.refresh_token.add(&expired).await.unwrap();
+
+    state.table.start_pruning(tokio::time::Duration::from_millis(10));
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    assert_eq!(state.table.refresh_token.prune_expired().await.unwrap(), 0);
+  }
+}
@@ -0,0 +1,51 @@
+use super::SharedMemoryStore;
+use crate::{constants::*, entity::*, error::*, table::AuditLog};
+use async_trait::async_trait;
+
+#[derive(Debug, Clone)]
+pub struct MemoryAuditLog {
+  store: SharedMemoryStore,
+}
+
+impl MemoryAuditLog {
+  pub(super) fn new(store: SharedMemoryStore) -> Self {
+    Self { store }
+  }
+}
+
+#[async_trait]
+impl AuditLog for MemoryAuditLog {
+  async fn record<'a>(&self, event: &'a AuditEvent) -> Result<()> {
+    self.store.write()?.audit_events.push(event.clone());
+    Ok(())
+  }
+
+  async fn query<'a>(&self, filter: &'a AuditEventFilter, page: u32) -> Result<(Vec<AuditEvent>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let store = self.store.read()?;
+    let matched = store
+      .audit_events
+      .iter()
+      .rev()
+      .filter(|e| filter.matches(e))
+      .collect::<Vec<_>>();
+    let total_cnt = matched.len();
+    let total_pages = (total_cnt as f64 / MAX_AUDIT_EVENTS_PER_PAGE as f64).ceil() as u32;
+
+    // the first page is always available even if no event matches
+    if page > total_pages.max(1) {
+      bail!("Page number exceeds total pages");
+    }
+
+    let events = matched
+      .into_iter()
+      .skip(((page - 1) * MAX_AUDIT_EVENTS_PER_PAGE) as usize)
+      .take(MAX_AUDIT_EVENTS_PER_PAGE as usize)
+      .cloned()
+      .collect::<Vec<_>>();
+
+    Ok((events, total_pages, total_cnt as u32))
+  }
+}
@@ -0,0 +1,93 @@
+use super::SharedMemoryStore;
+use crate::{constants::*, entity::*, error::*, table::ClientTable};
+use async_trait::async_trait;
+
+use libcommon::token_fields::{ClientId, Field};
+
+#[derive(Debug, Clone)]
+pub struct MemoryClientTable {
+  store: SharedMemoryStore,
+}
+
+impl MemoryClientTable {
+  pub(super) fn new(store: SharedMemoryStore) -> Self {
+    Self { store }
+  }
+}
+
+#[async_trait]
+impl ClientTable for MemoryClientTable {
+  async fn add<'a>(&self, client: &'a Client) -> Result<()> {
+    let mut store = self.store.write()?;
+    if store.clients.contains_key(client.client_id.as_str()) {
+      bail!("Client already exists");
+    }
+    store.clients.insert(client.client_id.as_str().to_string(), client.clone());
+    Ok(())
+  }
+
+  async fn find<'a>(&self, client_id: &'a ClientId) -> Result<Option<Client>> {
+    Ok(self.store.read()?.clients.get(client_id.as_str()).cloned())
+  }
+
+  async fn list(&self, page: u32) -> Result<(Vec<Client>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let store = self.store.read()?;
+    let total_cnt = store.clients.len();
+    let total_pages = (total_cnt as f64 / MAX_CLIENTS_PER_PAGE as f64).ceil() as u32;
+
+    // the first page is always available even if no client is registered
+    if page > total_pages.max(1) {
+      bail!("Page number exceeds total pages");
+    }
+
+    let clients = store
+      .clients
+      .values()
+      .skip(((page - 1) * MAX_CLIENTS_PER_PAGE) as usize)
+      .take(MAX_CLIENTS_PER_PAGE as usize)
+      .cloned()
+      .collect::<Vec<_>>();
+
+    Ok((clients, total_pages, total_cnt as u32))
+  }
+
+  async fn count(&self) -> Result<u32> {
+    Ok(self.store.read()?.clients.len() as u32)
+  }
+
+  async fn update<'a>(&self, client_id: &'a ClientId, update: &'a ClientUpdate) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(client) = store.clients.get_mut(client_id.as_str()) else {
+      return Ok(false);
+    };
+    client.apply(update);
+    Ok(true)
+  }
+
+  async fn replace_secret_hash<'a>(
+    &self,
+    client_id: &'a ClientId,
+    current_hash: &'a EncodedHash,
+    new_hash: &'a EncodedHash,
+  ) -> Result<()> {
+    let mut store = self.store.write()?;
+    if let Some(client) = store.clients.get_mut(client_id.as_str()) {
+      if client.secret_hash.as_ref().map(|h| h.as_str()) == Some(current_hash.as_str()) {
+        client.secret_hash = Some(new_hash.clone());
+      }
+    }
+    Ok(())
+  }
+
+  async fn delete<'a>(&self, client_id: &'a ClientId) -> Result<bool> {
+    let mut store = self.store.write()?;
+    if store.clients.remove(client_id.as_str()).is_none() {
+      return Ok(false);
+    }
+    store.tokens.retain(|t| &t.client_id != client_id);
+    Ok(true)
+  }
+}
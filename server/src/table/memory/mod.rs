@@ -0,0 +1,70 @@
+mod audit_log;
+mod client_table;
+mod refresh_table;
+mod totp_table;
+mod user_table;
+
+use crate::{
+  entity::{AuditEvent, Client, RecoveryCodeHash, RefreshTokenInfo, TotpFactor, User},
+  error::*,
+};
+use libcommon::token_fields::SubscriberId;
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use audit_log::MemoryAuditLog;
+use client_table::MemoryClientTable;
+use refresh_table::MemoryRefreshTokenTable;
+use totp_table::MemoryTotpTable;
+use user_table::MemoryUserTable;
+
+#[derive(Debug, Default)]
+/// Records kept in memory, shared by the in-memory tables
+struct MemoryStore {
+  /// Users keyed by username, which keeps the listing order identical to the sql backends
+  users: BTreeMap<String, User>,
+  /// Refresh tokens
+  tokens: Vec<RefreshTokenInfo>,
+  /// Audit events in the order of recording
+  audit_events: Vec<AuditEvent>,
+  /// TOTP factors keyed by subscriber id
+  totp_factors: BTreeMap<String, TotpFactor>,
+  /// Keyed hashes of recovery codes with the subscriber ids of their owners
+  recovery_codes: Vec<(SubscriberId, RecoveryCodeHash)>,
+  /// Registered clients keyed by client id, which keeps the listing order identical to the sql backends
+  clients: BTreeMap<String, Client>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SharedMemoryStore {
+  inner: Arc<RwLock<MemoryStore>>,
+}
+
+impl SharedMemoryStore {
+  fn read(&self) -> Result<RwLockReadGuard<'_, MemoryStore>> {
+    self.inner.read().map_err(|_| anyhow!("Failed to lock in-memory store"))
+  }
+  fn write(&self) -> Result<RwLockWriteGuard<'_, MemoryStore>> {
+    self.inner.write().map_err(|_| anyhow!("Failed to lock in-memory store"))
+  }
+}
+
+/// Setup in-memory user, refresh token, audit event, TOTP and client tables. Everything is lost when the server stops.
+pub(super) fn setup_memory() -> (
+  MemoryUserTable,
+  MemoryRefreshTokenTable,
+  MemoryAuditLog,
+  MemoryTotpTable,
+  MemoryClientTable,
+) {
+  let store = SharedMemoryStore::default();
+  (
+    MemoryUserTable::new(store.clone()),
+    MemoryRefreshTokenTable::new(store.clone()),
+    MemoryAuditLog::new(store.clone()),
+    MemoryTotpTable::new(store.clone()),
+    MemoryClientTable::new(store),
+  )
+}
@@ -0,0 +1,83 @@
+use super::SharedMemoryStore;
+use crate::{
+  entity::*,
+  error::*,
+  table::{RefreshTokenConsumption, RefreshTokenTable},
+};
+use async_trait::async_trait;
+
+use libcommon::token_fields::{ClientId, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct MemoryRefreshTokenTable {
+  store: SharedMemoryStore,
+}
+
+impl MemoryRefreshTokenTable {
+  pub(super) fn new(store: SharedMemoryStore) -> Self {
+    Self { store }
+  }
+}
+
+#[async_trait]
+impl RefreshTokenTable for MemoryRefreshTokenTable {
+  async fn add<'a>(&self, refresh_token: &'a RefreshTokenInfo) -> Result<()> {
+    self.store.write()?.tokens.push(refresh_token.clone());
+    Ok(())
+  }
+
+  async fn find_refresh_token<'a>(
+    &self,
+    refresh_token_hash: &'a RefreshTokenHash,
+    client_id: &'a ClientId,
+  ) -> Result<Option<RefreshTokenInfo>> {
+    let current = chrono::Local::now();
+    let found = self
+      .store
+      .read()?
+      .tokens
+      .iter()
+      .find(|t| &t.client_id == client_id && &t.hash == refresh_token_hash && t.expires > current)
+      .cloned();
+    Ok(found)
+  }
+
+  async fn consume_refresh_token<'a>(
+    &self,
+    refresh_token_hash: &'a RefreshTokenHash,
+    client_id: &'a ClientId,
+  ) -> Result<RefreshTokenConsumption> {
+    let current = chrono::Local::now();
+    let mut store = self.store.write()?;
+    let Some(token) = store
+      .tokens
+      .iter_mut()
+      .find(|t| &t.client_id == client_id && &t.hash == refresh_token_hash && t.expires > current)
+    else {
+      return Ok(RefreshTokenConsumption::NotFound);
+    };
+    if token.consumed {
+      return Ok(RefreshTokenConsumption::Reused(token.clone()));
+    }
+    token.consumed = true;
+    Ok(RefreshTokenConsumption::Consumed(token.clone()))
+  }
+
+  async fn revoke_family<'a>(&self, family_id: &'a TokenFamilyId) -> Result<()> {
+    self.store.write()?.tokens.retain(|t| &t.family_id != family_id);
+    Ok(())
+  }
+
+  async fn revoke_subscriber<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<()> {
+    self.store.write()?.tokens.retain(|t| &t.subscriber_id != subscriber_id);
+    Ok(())
+  }
+
+  async fn prune_expired(&self) -> Result<u64> {
+    let current = chrono::Local::now();
+    let mut store = self.store.write()?;
+    let before = store.tokens.len();
+    store.tokens.retain(|t| t.expires >= current);
+    Ok((before - store.tokens.len()) as u64)
+  }
+}
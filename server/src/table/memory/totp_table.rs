@@ -0,0 +1,84 @@
+use super::SharedMemoryStore;
+use crate::{entity::*, error::*, table::TotpTable};
+use async_trait::async_trait;
+
+use libcommon::token_fields::{Field, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct MemoryTotpTable {
+  store: SharedMemoryStore,
+}
+
+impl MemoryTotpTable {
+  pub(super) fn new(store: SharedMemoryStore) -> Self {
+    Self { store }
+  }
+}
+
+#[async_trait]
+impl TotpTable for MemoryTotpTable {
+  async fn find<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<Option<TotpFactor>> {
+    Ok(self.store.read()?.totp_factors.get(subscriber_id.as_str()).cloned())
+  }
+
+  async fn enroll<'a>(&self, factor: &'a TotpFactor) -> Result<()> {
+    let mut store = self.store.write()?;
+    let entry = store.totp_factors.entry(factor.subscriber_id.as_str().to_string());
+    let current = entry.or_insert_with(|| factor.clone());
+    if !current.enabled {
+      *current = factor.clone();
+    }
+    Ok(())
+  }
+
+  async fn enable<'a>(
+    &self,
+    subscriber_id: &'a SubscriberId,
+    step: i64,
+    recovery_code_hashes: &'a [RecoveryCodeHash],
+  ) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(factor) = store.totp_factors.get_mut(subscriber_id.as_str()).filter(|f| !f.enabled) else {
+      return Ok(false);
+    };
+    factor.enabled = true;
+    factor.last_step = Some(step);
+    store.recovery_codes.retain(|(s, _)| s != subscriber_id);
+    for code_hash in recovery_code_hashes {
+      store.recovery_codes.push((subscriber_id.clone(), code_hash.clone()));
+    }
+    Ok(true)
+  }
+
+  async fn use_step<'a>(&self, subscriber_id: &'a SubscriberId, step: i64) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(factor) = store.totp_factors.get_mut(subscriber_id.as_str()).filter(|f| f.enabled) else {
+      return Ok(false);
+    };
+    if factor.last_step.is_some_and(|last| last >= step) {
+      return Ok(false);
+    }
+    factor.last_step = Some(step);
+    Ok(true)
+  }
+
+  async fn consume_recovery_code<'a>(
+    &self,
+    subscriber_id: &'a SubscriberId,
+    code_hash: &'a RecoveryCodeHash,
+  ) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let before = store.recovery_codes.len();
+    store
+      .recovery_codes
+      .retain(|(s, h)| !(s == subscriber_id && h == code_hash));
+    Ok(store.recovery_codes.len() < before)
+  }
+
+  async fn disable<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<()> {
+    let mut store = self.store.write()?;
+    store.totp_factors.remove(subscriber_id.as_str());
+    store.recovery_codes.retain(|(s, _)| s != subscriber_id);
+    Ok(())
+  }
+}
@@ -0,0 +1,256 @@
+use super::SharedMemoryStore;
+use crate::{
+  constants::*,
+  entity::*,
+  error::*,
+  table::{UserSearchKey, UserTable},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+
+use libcommon::token_fields::{Field, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct MemoryUserTable {
+  store: SharedMemoryStore,
+}
+
+impl MemoryUserTable {
+  pub(super) fn new(store: SharedMemoryStore) -> Self {
+    Self { store }
+  }
+
+  /// Set the password and whether the user must change it, deleting all of the user's refresh tokens
+  fn set_password(&self, user_search_key: UserSearchKey<'_>, password: &Password, must_change_password: bool) -> Result<()> {
+    let encoded_hash = EncodedHash::generate(password)?;
+    let mut store = self.store.write()?;
+    if let Some(username) = username_of(&store.users, &user_search_key) {
+      if let Some(user) = store.users.get_mut(&username) {
+        user.encoded_hash = encoded_hash;
+        user.must_change_password = must_change_password;
+        let subscriber_id = user.subscriber_id.clone();
+        // a new password ends every session established with the old one
+        store.tokens.retain(|t| t.subscriber_id != subscriber_id);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Username of the user matching the search key
+fn username_of(users: &std::collections::BTreeMap<String, User>, user_search_key: &UserSearchKey<'_>) -> Option<String> {
+  match user_search_key {
+    UserSearchKey::Username(username) => users.get(username.as_str()).map(|u| u.username().to_string()),
+    UserSearchKey::SubscriberId(sub_id) => users
+      .values()
+      .find(|u| &u.subscriber_id == *sub_id)
+      .map(|u| u.username().to_string()),
+  }
+}
+
+/// True if the user of the username is the only one of the admin role
+fn is_last_admin(users: &std::collections::BTreeMap<String, User>, username: &str) -> bool {
+  users.get(username).is_some_and(|u| u.is_admin()) && users.values().filter(|u| u.is_admin()).count() <= 1
+}
+
+#[async_trait]
+impl UserTable for MemoryUserTable {
+  async fn add(&self, user: User) -> Result<()> {
+    let mut store = self.store.write()?;
+    if store.users.contains_key(user.username()) || store.users.values().any(|u| u.subscriber_id == user.subscriber_id) {
+      bail!("User already exists");
+    }
+    store.users.insert(user.username().to_string(), user);
+    Ok(())
+  }
+
+  async fn delete_user<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(username) = username_of(&store.users, &user_search_key) else {
+      return Ok(false);
+    };
+    if is_last_admin(&store.users, &username) {
+      return Ok(false);
+    }
+    let Some(user) = store.users.remove(&username) else {
+      return Ok(false);
+    };
+    store.tokens.retain(|t| t.subscriber_id != user.subscriber_id);
+    store.totp_factors.remove(user.subscriber_id.as_str());
+    store.recovery_codes.retain(|(s, _)| s != &user.subscriber_id);
+    Ok(true)
+  }
+
+  async fn list_users(&self, page: u32) -> Result<(Vec<User>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let store = self.store.read()?;
+    let total_cnt = store.users.len();
+    let total_pages = (total_cnt as f64 / MAX_USERS_PER_PAGE as f64).ceil() as u32;
+
+    if page > total_pages {
+      bail!("Page number exceeds total pages");
+    }
+
+    let users = store
+      .users
+      .values()
+      .skip(((page - 1) * MAX_USERS_PER_PAGE) as usize)
+      .take(MAX_USERS_PER_PAGE as usize)
+      .cloned()
+      .collect::<Vec<_>>();
+
+    Ok((users, total_pages, total_cnt as u32))
+  }
+
+  async fn update_user<'a>(
+    &self,
+    subscriber_id: &SubscriberId,
+    new_username: Option<&Username>,
+    new_password: Option<&Password>,
+  ) -> Result<()> {
+    if new_username.is_none() && new_password.is_none() {
+      bail!("Both or either one of username and password must be specified");
+    }
+    let encoded_hash = new_password.map(EncodedHash::generate).transpose()?;
+
+    let mut store = self.store.write()?;
+    let Some(username) = username_of(&store.users, &UserSearchKey::SubscriberId(subscriber_id)) else {
+      return Ok(());
+    };
+    if let Some(new_username) = new_username {
+      if new_username.as_str() != username && store.users.contains_key(new_username.as_str()) {
+        bail!("Username already exists");
+      }
+    }
+    let Some(mut user) = store.users.remove(&username) else {
+      return Ok(());
+    };
+    if let Some(new_username) = new_username {
+      user.username = new_username.to_owned();
+    }
+    if let Some(encoded_hash) = encoded_hash {
+      user.encoded_hash = encoded_hash;
+      user.must_change_password = false;
+      // a new password ends every session established with the old one
+      store.tokens.retain(|t| &t.subscriber_id != subscriber_id);
+    }
+    store.users.insert(user.username().to_string(), user);
+    Ok(())
+  }
+
+  async fn update_password<'a>(&self, user_search_key: UserSearchKey<'a>, new_password: &Password) -> Result<()> {
+    self.set_password(user_search_key, new_password, false)
+  }
+
+  async fn reset_password<'a>(&self, user_search_key: UserSearchKey<'a>, temporary_password: &Password) -> Result<()> {
+    self.set_password(user_search_key, temporary_password, true)
+  }
+
+  async fn replace_encoded_hash<'a>(
+    &self,
+    user_search_key: UserSearchKey<'a>,
+    current_hash: &EncodedHash,
+    new_hash: &EncodedHash,
+  ) -> Result<()> {
+    let mut store = self.store.write()?;
+    if let Some(username) = username_of(&store.users, &user_search_key) {
+      if let Some(user) = store.users.get_mut(&username) {
+        if user.encoded_hash.as_str() == current_hash.as_str() {
+          user.encoded_hash = new_hash.clone();
+        }
+      }
+    }
+    Ok(())
+  }
+
+  async fn update_profile<'a>(&self, user_search_key: UserSearchKey<'a>, update: &ProfileUpdate) -> Result<()> {
+    if update.is_empty() {
+      bail!("At least one profile field must be specified");
+    }
+    let mut store = self.store.write()?;
+    if let Some(username) = username_of(&store.users, &user_search_key) {
+      if let Some(user) = store.users.get_mut(&username) {
+        user.profile.apply(update);
+      }
+    }
+    Ok(())
+  }
+
+  async fn update_roles<'a>(&self, user_search_key: UserSearchKey<'a>, roles: &Roles) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(username) = username_of(&store.users, &user_search_key) else {
+      return Ok(false);
+    };
+    if !roles.contains(Role::Admin) && is_last_admin(&store.users, &username) {
+      return Ok(false);
+    }
+    let Some(user) = store.users.get_mut(&username) else {
+      return Ok(false);
+    };
+    user.roles = roles.clone();
+    Ok(true)
+  }
+
+  async fn update_status<'a>(
+    &self,
+    user_search_key: UserSearchKey<'a>,
+    enabled: bool,
+    locked_until: Option<DateTime<Local>>,
+  ) -> Result<bool> {
+    let mut store = self.store.write()?;
+    let Some(username) = username_of(&store.users, &user_search_key) else {
+      return Ok(false);
+    };
+    let keeps_access = enabled && locked_until.is_none_or(|t| t <= Local::now());
+    let other_active_admin = store.users.values().any(|u| {
+      u.username() != username && u.is_admin() && u.account_state() == AccountState::Active
+    });
+    let Some(user) = store.users.get_mut(&username) else {
+      return Ok(false);
+    };
+    if !keeps_access && user.is_admin() && !other_active_admin {
+      return Ok(false);
+    }
+    user.enabled = enabled;
+    user.locked_until = locked_until;
+    Ok(true)
+  }
+
+  async fn lock<'a>(&self, user_search_key: UserSearchKey<'a>, locked_until: DateTime<Local>) -> Result<()> {
+    let mut store = self.store.write()?;
+    if let Some(username) = username_of(&store.users, &user_search_key) {
+      if let Some(user) = store.users.get_mut(&username) {
+        user.locked_until = Some(locked_until);
+      }
+    }
+    Ok(())
+  }
+
+  async fn increment_failed_logins<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<u32> {
+    let mut store = self.store.write()?;
+    let user = username_of(&store.users, &user_search_key).and_then(|username| store.users.get_mut(&username));
+    let Some(user) = user else {
+      bail!("No such user");
+    };
+    user.failed_logins += 1;
+    Ok(user.failed_logins)
+  }
+
+  async fn reset_failed_logins<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<()> {
+    let mut store = self.store.write()?;
+    if let Some(username) = username_of(&store.users, &user_search_key) {
+      if let Some(user) = store.users.get_mut(&username) {
+        user.failed_logins = 0;
+      }
+    }
+    Ok(())
+  }
+
+  async fn find_user<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<Option<User>> {
+    let store = self.store.read()?;
+    let user = username_of(&store.users, &user_search_key).and_then(|username| store.users.get(&username).cloned());
+    Ok(user)
+  }
+}
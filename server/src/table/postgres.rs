@@ -0,0 +1,39 @@
+use super::sql::{sql_tables, SqlDatabase, SqlTables};
+use crate::{constants::USER_TABLE_NAME, error::*};
+use async_trait::async_trait;
+use sqlx::{
+  postgres::{PgConnection, PgPoolOptions, PgQueryResult},
+  Postgres,
+};
+
+#[async_trait]
+impl SqlDatabase for Postgres {
+  fn is_admin_literal(is_admin: bool) -> &'static str {
+    if is_admin {
+      "true"
+    } else {
+      "false"
+    }
+  }
+
+  fn rows_affected(res: &PgQueryResult) -> u64 {
+    res.rows_affected()
+  }
+
+  /// Statements after the lock see the changes committed while waiting for it
+  async fn lock_admins(conn: &mut PgConnection) -> Result<()> {
+    let sql = format!("select subscriber_id from {} where is_admin for update", USER_TABLE_NAME);
+    let _res = sqlx::query(&sql).fetch_all(conn).await?;
+    Ok(())
+  }
+}
+
+/// Setup PostgreSQL database with automatic creation of user, refresh token, audit event, TOTP and client tables
+pub(super) async fn setup_postgres(postgres_url: &str) -> Result<SqlTables<Postgres>> {
+  let pool = PgPoolOptions::default().connect(postgres_url).await?;
+
+  // Embed migrations into binary
+  sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+
+  Ok(sql_tables(pool))
+}
@@ -0,0 +1,176 @@
+use crate::{entity::*, error::*};
+use chrono::{DateTime, Local, TimeZone};
+use sqlx::types::Json;
+use std::convert::{From, TryInto};
+use validator::Validate;
+
+use libcommon::token_fields::{ClientId, Field, SubscriberId, TryNewField};
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct UserRow {
+  username: String,
+  subscriber_id: String,
+  encoded_hash: String,
+  roles: Json<Vec<String>>,
+  email: Option<String>,
+  display_name: Option<String>,
+  attributes: Json<serde_json::Map<String, serde_json::Value>>,
+  enabled: bool,
+  locked_until: Option<i64>,
+  failed_logins: i32,
+  must_change_password: bool,
+}
+
+impl From<User> for UserRow {
+  fn from(value: User) -> Self {
+    Self {
+      username: value.username.into_string(),
+      subscriber_id: value.subscriber_id.into_string(),
+      encoded_hash: value.encoded_hash.into_string(),
+      roles: Json(value.roles.to_strings()),
+      email: value.profile.email.map(|e| e.into_string()),
+      display_name: value.profile.display_name.map(|n| n.into_string()),
+      attributes: Json(value.profile.attributes.into_inner()),
+      enabled: value.enabled,
+      locked_until: value.locked_until.map(|t| t.timestamp()),
+      failed_logins: value.failed_logins as i32,
+      must_change_password: value.must_change_password,
+    }
+  }
+}
+
+impl TryInto<User> for UserRow {
+  type Error = crate::error::Error;
+
+  fn try_into(self) -> std::result::Result<User, Self::Error> {
+    let x = User {
+      username: Username::new(self.username)?,
+      subscriber_id: SubscriberId::new(self.subscriber_id)?,
+      encoded_hash: EncodedHash::new(self.encoded_hash)?,
+      roles: Roles::new(self.roles.0)?,
+      profile: UserProfile {
+        email: self.email.map(Email::new).transpose()?,
+        display_name: self.display_name.map(DisplayName::new).transpose()?,
+        attributes: UserAttributes::new(self.attributes.0)?,
+      },
+      enabled: self.enabled,
+      locked_until: self.locked_until.map(timestamp_to_datetime).transpose()?,
+      failed_logins: self.failed_logins.try_into()?,
+      must_change_password: self.must_change_password,
+    };
+    x.username.validate()?;
+    x.subscriber_id.validate()?;
+    x.encoded_hash.validate()?;
+    Ok(x)
+  }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct RefreshTokenRow {
+  subscriber_id: String,
+  client_id: String,
+  refresh_token_hash: String,
+  family_id: String,
+  amr: Json<Vec<String>>,
+  consumed: bool,
+  expires: i64,
+}
+
+impl TryInto<RefreshTokenInfo> for RefreshTokenRow {
+  type Error = crate::error::Error;
+
+  fn try_into(self) -> std::result::Result<RefreshTokenInfo, Self::Error> {
+    let expires = timestamp_to_datetime(self.expires)?;
+    let res = RefreshTokenInfo {
+      subscriber_id: SubscriberId::new(self.subscriber_id)?,
+      client_id: ClientId::new(self.client_id)?,
+      hash: RefreshTokenHash::new(self.refresh_token_hash)?,
+      family_id: TokenFamilyId::new(self.family_id)?,
+      amr: self.amr.0.iter().map(|m| m.parse()).collect::<Result<_>>()?,
+      consumed: self.consumed,
+      expires,
+    };
+    Ok(res)
+  }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct AuditEventRow {
+  created_at: i64,
+  kind: String,
+  actor: Option<String>,
+  target: Option<String>,
+  client_id: Option<String>,
+  remote_addr: Option<String>,
+}
+
+impl TryInto<AuditEvent> for AuditEventRow {
+  type Error = crate::error::Error;
+
+  fn try_into(self) -> std::result::Result<AuditEvent, Self::Error> {
+    let timestamp = timestamp_to_datetime(self.created_at)?;
+    let res = AuditEvent {
+      timestamp,
+      kind: self.kind.parse()?,
+      actor: self.actor.map(SubscriberId::new).transpose()?,
+      target: self.target,
+      client_id: self.client_id.map(ClientId::new).transpose()?,
+      remote_addr: self.remote_addr,
+    };
+    Ok(res)
+  }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct TotpRow {
+  subscriber_id: String,
+  secret: String,
+  enabled: bool,
+  last_step: Option<i64>,
+}
+
+impl TryInto<TotpFactor> for TotpRow {
+  type Error = crate::error::Error;
+
+  fn try_into(self) -> std::result::Result<TotpFactor, Self::Error> {
+    let res = TotpFactor {
+      subscriber_id: SubscriberId::new(self.subscriber_id)?,
+      secret: SealedTotpSecret::new(self.secret)?,
+      enabled: self.enabled,
+      last_step: self.last_step,
+    };
+    Ok(res)
+  }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct ClientRow {
+  client_id: String,
+  display_name: Option<String>,
+  enabled: bool,
+  secret_hash: Option<String>,
+  created_at: i64,
+}
+
+impl TryInto<Client> for ClientRow {
+  type Error = crate::error::Error;
+
+  fn try_into(self) -> std::result::Result<Client, Self::Error> {
+    let created_at = timestamp_to_datetime(self.created_at)?;
+    let res = Client {
+      client_id: ClientId::new(self.client_id)?,
+      display_name: self.display_name.map(DisplayName::new).transpose()?,
+      enabled: self.enabled,
+      secret_hash: self.secret_hash.map(EncodedHash::new).transpose()?,
+      created_at,
+    };
+    Ok(res)
+  }
+}
+
+fn timestamp_to_datetime(timestamp: i64) -> Result<DateTime<Local>> {
+  let Some(datetime) = Local.timestamp_opt(timestamp, 0).single() else {
+    bail!("Invalid timestamp");
+  };
+  Ok(datetime)
+}
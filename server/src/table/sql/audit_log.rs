@@ -0,0 +1,98 @@
+use super::SqlDatabase;
+use crate::{constants::*, entity::*, error::*, table::{rows::AuditEventRow, AuditLog}};
+use async_trait::async_trait;
+use sqlx::{database::HasArguments, ColumnIndex, Decode, Encode, Executor, FromRow, IntoArguments, Pool, Type};
+use std::convert::TryInto;
+
+use libcommon::token_fields::Field;
+
+#[derive(Debug, Clone)]
+pub struct SqlAuditLog<DB: SqlDatabase> {
+  pool: Pool<DB>,
+}
+
+impl<DB: SqlDatabase> SqlAuditLog<DB> {
+  pub fn new(pool: Pool<DB>) -> Self {
+    Self { pool }
+  }
+}
+
+/// Conditions shared by count and select queries, where a null parameter matches any event
+const FILTER_CONDITIONS: &str = "($1 is null or kind = $1) and ($2 is null or actor = $2) and ($3 is null or target = $3) \
+  and ($4 is null or client_id = $4) and ($5 is null or created_at >= $5) and ($6 is null or created_at < $6)";
+
+#[async_trait]
+impl<DB> AuditLog for SqlAuditLog<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> Option<&'q str>: Encode<'q, DB> + Type<DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+  for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+  usize: ColumnIndex<DB::Row>,
+  AuditEventRow: for<'r> FromRow<'r, DB::Row>,
+{
+  async fn record<'a>(&self, event: &'a AuditEvent) -> Result<()> {
+    let sql = format!(
+      "insert into {} (created_at, kind, actor, target, client_id, remote_addr) VALUES ($1, $2, $3, $4, $5, $6)",
+      AUDIT_EVENT_TABLE_NAME
+    );
+    let _res = sqlx::query(&sql)
+      .bind(event.timestamp.timestamp())
+      .bind(event.kind.as_str())
+      .bind(event.actor.as_ref().map(|a| a.as_str()))
+      .bind(event.target.as_deref())
+      .bind(event.client_id.as_ref().map(|c| c.as_str()))
+      .bind(event.remote_addr.as_deref())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn query<'a>(&self, filter: &'a AuditEventFilter, page: u32) -> Result<(Vec<AuditEvent>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let sql = format!("select count(*) from {} where {}", AUDIT_EVENT_TABLE_NAME, FILTER_CONDITIONS);
+    let total_cnt = sqlx::query_scalar::<_, i64>(&sql)
+      .bind(filter.kind.map(|k| k.as_str()))
+      .bind(filter.actor.as_ref().map(|a| a.as_str()))
+      .bind(filter.target.as_deref())
+      .bind(filter.client_id.as_ref().map(|c| c.as_str()))
+      .bind(filter.since.map(|t| t.timestamp()))
+      .bind(filter.until.map(|t| t.timestamp()))
+      .fetch_one(&self.pool)
+      .await?;
+    let total_pages = (total_cnt as f64 / MAX_AUDIT_EVENTS_PER_PAGE as f64).ceil() as u32;
+
+    // the first page is always available even if no event matches
+    if page > total_pages.max(1) {
+      bail!("Page number exceeds total pages");
+    }
+
+    let sql = format!(
+      "select * from {} where {} order by id desc limit $7 offset $8",
+      AUDIT_EVENT_TABLE_NAME, FILTER_CONDITIONS
+    );
+    let event_rows: Vec<AuditEventRow> = sqlx::query_as(&sql)
+      .bind(filter.kind.map(|k| k.as_str()))
+      .bind(filter.actor.as_ref().map(|a| a.as_str()))
+      .bind(filter.target.as_deref())
+      .bind(filter.client_id.as_ref().map(|c| c.as_str()))
+      .bind(filter.since.map(|t| t.timestamp()))
+      .bind(filter.until.map(|t| t.timestamp()))
+      .bind(MAX_AUDIT_EVENTS_PER_PAGE as i64)
+      .bind(((page - 1) * MAX_AUDIT_EVENTS_PER_PAGE) as i64)
+      .fetch_all(&self.pool)
+      .await?;
+
+    let events = event_rows
+      .into_iter()
+      .map(|row| row.try_into())
+      .collect::<Result<Vec<AuditEvent>>>()?;
+
+    Ok((events, total_pages, total_cnt as u32))
+  }
+}
@@ -0,0 +1,153 @@
+use super::{assignments, SqlDatabase};
+use crate::{constants::*, entity::*, error::*, table::{rows::ClientRow, ClientTable}};
+use async_trait::async_trait;
+use sqlx::{database::HasArguments, ColumnIndex, Decode, Encode, Executor, FromRow, IntoArguments, Pool, Type};
+use std::convert::TryInto;
+
+use libcommon::token_fields::{ClientId, Field};
+
+#[derive(Debug, Clone)]
+pub struct SqlClientTable<DB: SqlDatabase> {
+  pool: Pool<DB>,
+}
+
+impl<DB: SqlDatabase> SqlClientTable<DB> {
+  pub fn new(pool: Pool<DB>) -> Self {
+    Self { pool }
+  }
+}
+
+#[async_trait]
+impl<DB> ClientTable for SqlClientTable<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> Option<&'q str>: Encode<'q, DB> + Type<DB>,
+  for<'q> Option<String>: Encode<'q, DB> + Type<DB>,
+  for<'q> bool: Encode<'q, DB> + Type<DB>,
+  for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+  usize: ColumnIndex<DB::Row>,
+  ClientRow: for<'r> FromRow<'r, DB::Row>,
+{
+  async fn add<'a>(&self, client: &'a Client) -> Result<()> {
+    let sql = format!(
+      "insert into {} (client_id, display_name, enabled, secret_hash, created_at) VALUES ($1, $2, $3, $4, $5)",
+      CLIENT_TABLE_NAME
+    );
+    let _res = sqlx::query(&sql)
+      .bind(client.client_id.as_str())
+      .bind(client.display_name.as_ref().map(|n| n.as_str()))
+      .bind(client.enabled)
+      .bind(client.secret_hash.as_ref().map(|h| h.as_str()))
+      .bind(client.created_at.timestamp())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn find<'a>(&self, client_id: &'a ClientId) -> Result<Option<Client>> {
+    let sql = format!("select * from {} where client_id = $1", CLIENT_TABLE_NAME);
+    let client_row_opt: Option<ClientRow> = sqlx::query_as(&sql)
+      .bind(client_id.as_str())
+      .fetch_optional(&self.pool)
+      .await?;
+    client_row_opt.map(|row| row.try_into()).transpose()
+  }
+
+  async fn list(&self, page: u32) -> Result<(Vec<Client>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let total_cnt = sqlx::query_scalar::<_, i64>(&format!("select count(*) from {}", CLIENT_TABLE_NAME))
+      .fetch_one(&self.pool)
+      .await?;
+    let total_pages = (total_cnt as f64 / MAX_CLIENTS_PER_PAGE as f64).ceil() as u32;
+
+    // the first page is always available even if no client is registered
+    if page > total_pages.max(1) {
+      bail!("Page number exceeds total pages");
+    }
+
+    let sql = format!("select * from {} order by client_id asc limit $1 offset $2", CLIENT_TABLE_NAME);
+    let client_rows: Vec<ClientRow> = sqlx::query_as(&sql)
+      .bind(MAX_CLIENTS_PER_PAGE as i64)
+      .bind(((page - 1) * MAX_CLIENTS_PER_PAGE) as i64)
+      .fetch_all(&self.pool)
+      .await?;
+
+    let clients = client_rows
+      .into_iter()
+      .map(|row| row.try_into())
+      .collect::<Result<Vec<Client>>>()?;
+
+    Ok((clients, total_pages, total_cnt as u32))
+  }
+
+  async fn count(&self) -> Result<u32> {
+    let sql = format!("select count(*) from {}", CLIENT_TABLE_NAME);
+    let cnt = sqlx::query_scalar::<_, i64>(&sql).fetch_one(&self.pool).await?;
+    Ok(cnt as u32)
+  }
+
+  async fn update<'a>(&self, client_id: &'a ClientId, update: &'a ClientUpdate) -> Result<bool> {
+    if update.is_empty() {
+      bail!("At least one client field must be specified");
+    }
+    let mut columns = vec![];
+    if update.display_name.is_some() {
+      columns.push("display_name");
+    }
+    if update.enabled.is_some() {
+      columns.push("enabled");
+    }
+    let sql = format!(
+      "update {} set {} where client_id = ${}",
+      CLIENT_TABLE_NAME,
+      assignments(&columns),
+      columns.len() + 1
+    );
+    let mut query = sqlx::query(&sql);
+    if let Some(display_name) = &update.display_name {
+      query = query.bind(display_name.as_ref().map(|n| n.as_str().to_string()));
+    }
+    if let Some(enabled) = update.enabled {
+      query = query.bind(enabled);
+    }
+    let res = query.bind(client_id.as_str()).execute(&self.pool).await?;
+    Ok(DB::rows_affected(&res) > 0)
+  }
+
+  async fn replace_secret_hash<'a>(
+    &self,
+    client_id: &'a ClientId,
+    current_hash: &'a EncodedHash,
+    new_hash: &'a EncodedHash,
+  ) -> Result<()> {
+    let sql = format!(
+      "update {} set secret_hash = $1 where client_id = $2 and secret_hash = $3",
+      CLIENT_TABLE_NAME
+    );
+    let _res = sqlx::query(&sql)
+      .bind(new_hash.as_str())
+      .bind(client_id.as_str())
+      .bind(current_hash.as_str())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn delete<'a>(&self, client_id: &'a ClientId) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+    let sql = format!("delete from {} where client_id = $1", CLIENT_TABLE_NAME);
+    let res = sqlx::query(&sql).bind(client_id.as_str()).execute(&mut *tx).await?;
+    if DB::rows_affected(&res) == 0 {
+      return Ok(false);
+    }
+    let sql = format!("delete from {} where client_id = $1", REFRESH_TOKEN_TABLE_NAME);
+    let _res = sqlx::query(&sql).bind(client_id.as_str()).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(true)
+  }
+}
@@ -0,0 +1,57 @@
+mod audit_log;
+mod client_table;
+mod refresh_table;
+mod totp_table;
+mod user_table;
+
+use crate::error::*;
+use async_trait::async_trait;
+use sqlx::{Database, Pool};
+
+use audit_log::SqlAuditLog;
+use client_table::SqlClientTable;
+use refresh_table::SqlRefreshTokenTable;
+use totp_table::SqlTotpTable;
+use user_table::SqlUserTable;
+
+/// Database of the sql backends, whose tables share their statements. sqlx takes numbered placeholders like `$1` for
+/// both sqlite and PostgreSQL, so that only the differences of their dialects and drivers are given here.
+#[async_trait]
+pub(super) trait SqlDatabase: Database {
+  /// Literal of the legacy is_admin column, which is text in sqlite and boolean in PostgreSQL
+  fn is_admin_literal(is_admin: bool) -> &'static str;
+  fn rows_affected(res: &Self::QueryResult) -> u64;
+  /// Lock the rows of admins until the end of the transaction, so that admins counted in the transaction are never
+  /// demoted, deleted, disabled or locked concurrently
+  async fn lock_admins(conn: &mut Self::Connection) -> Result<()>;
+}
+
+/// Assignments of the columns to the numbered placeholders from `$1`, in the order of the columns
+fn assignments(columns: &[&str]) -> String {
+  columns
+    .iter()
+    .enumerate()
+    .map(|(i, column)| format!("{} = ${}", column, i + 1))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// User, refresh token, audit event, TOTP and client tables of a database
+pub(super) type SqlTables<DB> = (
+  SqlUserTable<DB>,
+  SqlRefreshTokenTable<DB>,
+  SqlAuditLog<DB>,
+  SqlTotpTable<DB>,
+  SqlClientTable<DB>,
+);
+
+/// Setup user, refresh token, audit event, TOTP and client tables sharing the pool
+pub(super) fn sql_tables<DB: SqlDatabase>(pool: Pool<DB>) -> SqlTables<DB> {
+  (
+    SqlUserTable::new(pool.clone()),
+    SqlRefreshTokenTable::new(pool.clone()),
+    SqlAuditLog::new(pool.clone()),
+    SqlTotpTable::new(pool.clone()),
+    SqlClientTable::new(pool),
+  )
+}
@@ -0,0 +1,123 @@
+use super::SqlDatabase;
+use crate::{
+  constants::*,
+  entity::*,
+  error::*,
+  table::{rows::RefreshTokenRow, RefreshTokenConsumption, RefreshTokenTable},
+};
+use async_trait::async_trait;
+use sqlx::{database::HasArguments, types::Json, Encode, Executor, FromRow, IntoArguments, Pool, Type};
+use std::convert::TryInto;
+
+use libcommon::token_fields::{ClientId, Field, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct SqlRefreshTokenTable<DB: SqlDatabase> {
+  pool: Pool<DB>,
+}
+
+impl<DB: SqlDatabase> SqlRefreshTokenTable<DB> {
+  pub fn new(pool: Pool<DB>) -> Self {
+    Self { pool }
+  }
+}
+
+#[async_trait]
+impl<DB> RefreshTokenTable for SqlRefreshTokenTable<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> i64: Encode<'q, DB> + Type<DB>,
+  for<'q> bool: Encode<'q, DB> + Type<DB>,
+  for<'q> Json<Vec<String>>: Encode<'q, DB> + Type<DB>,
+  RefreshTokenRow: for<'r> FromRow<'r, DB::Row>,
+{
+  async fn add<'a>(&self, refresh_token: &'a RefreshTokenInfo) -> Result<()> {
+    let sql = format!(
+      "insert into {} (subscriber_id, client_id, refresh_token_hash, family_id, amr, consumed, expires) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+      REFRESH_TOKEN_TABLE_NAME
+    );
+    let _res = sqlx::query(&sql)
+      .bind(refresh_token.subscriber_id.as_str())
+      .bind(refresh_token.client_id.as_str())
+      .bind(refresh_token.hash.as_str())
+      .bind(refresh_token.family_id.as_str())
+      .bind(Json(refresh_token.amr.iter().map(|m| m.as_str().to_string()).collect::<Vec<_>>()))
+      .bind(refresh_token.consumed)
+      .bind(refresh_token.expires.timestamp())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn find_refresh_token<'a>(
+    &self,
+    refresh_token_hash: &'a RefreshTokenHash,
+    client_id: &'a ClientId,
+  ) -> Result<Option<RefreshTokenInfo>> {
+    let current = chrono::Local::now().timestamp();
+    let sql = format!(
+      "select * from {} where client_id = $1 and refresh_token_hash = $2 and expires > $3",
+      REFRESH_TOKEN_TABLE_NAME
+    );
+    let refresh_token_row_opt: Option<RefreshTokenRow> = sqlx::query_as(&sql)
+      .bind(client_id.as_str())
+      .bind(refresh_token_hash.as_str())
+      .bind(current)
+      .fetch_optional(&self.pool)
+      .await?;
+    if let Some(refresh_token_row) = refresh_token_row_opt {
+      let refresh_token: RefreshTokenInfo = refresh_token_row.try_into()?;
+      Ok(Some(refresh_token))
+    } else {
+      Ok(None)
+    }
+  }
+
+  async fn consume_refresh_token<'a>(
+    &self,
+    refresh_token_hash: &'a RefreshTokenHash,
+    client_id: &'a ClientId,
+  ) -> Result<RefreshTokenConsumption> {
+    let current = chrono::Local::now().timestamp();
+    // a single statement flips the flag, so that only one of concurrent requests consumes the token
+    let sql = format!(
+      "update {} set consumed = true where client_id = $1 and refresh_token_hash = $2 and consumed = false and expires > $3 returning *",
+      REFRESH_TOKEN_TABLE_NAME
+    );
+    let consumed_row_opt: Option<RefreshTokenRow> = sqlx::query_as(&sql)
+      .bind(client_id.as_str())
+      .bind(refresh_token_hash.as_str())
+      .bind(current)
+      .fetch_optional(&self.pool)
+      .await?;
+    if let Some(consumed_row) = consumed_row_opt {
+      return Ok(RefreshTokenConsumption::Consumed(consumed_row.try_into()?));
+    }
+    match self.find_refresh_token(refresh_token_hash, client_id).await? {
+      Some(refresh_token) if refresh_token.consumed => Ok(RefreshTokenConsumption::Reused(refresh_token)),
+      _ => Ok(RefreshTokenConsumption::NotFound),
+    }
+  }
+
+  async fn revoke_family<'a>(&self, family_id: &'a TokenFamilyId) -> Result<()> {
+    let sql = format!("delete from {} where family_id = $1", REFRESH_TOKEN_TABLE_NAME);
+    let _res = sqlx::query(&sql).bind(family_id.as_str()).execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn revoke_subscriber<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<()> {
+    let sql = format!("delete from {} where subscriber_id = $1", REFRESH_TOKEN_TABLE_NAME);
+    let _res = sqlx::query(&sql).bind(subscriber_id.as_str()).execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn prune_expired(&self) -> Result<u64> {
+    let current = chrono::Local::now().timestamp();
+    let sql = format!("delete from {} where expires < $1", REFRESH_TOKEN_TABLE_NAME);
+    let res = sqlx::query(&sql).bind(current).execute(&self.pool).await?;
+    Ok(DB::rows_affected(&res))
+  }
+}
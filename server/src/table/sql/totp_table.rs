@@ -0,0 +1,130 @@
+use super::SqlDatabase;
+use crate::{constants::*, entity::*, error::*, table::{rows::TotpRow, TotpTable}};
+use async_trait::async_trait;
+use sqlx::{database::HasArguments, Encode, Executor, FromRow, IntoArguments, Pool, Type};
+use std::convert::TryInto;
+
+use libcommon::token_fields::{Field, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct SqlTotpTable<DB: SqlDatabase> {
+  pool: Pool<DB>,
+}
+
+impl<DB: SqlDatabase> SqlTotpTable<DB> {
+  pub fn new(pool: Pool<DB>) -> Self {
+    Self { pool }
+  }
+}
+
+#[async_trait]
+impl<DB> TotpTable for SqlTotpTable<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> i64: Encode<'q, DB> + Type<DB>,
+  TotpRow: for<'r> FromRow<'r, DB::Row>,
+{
+  async fn find<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<Option<TotpFactor>> {
+    let sql = format!("select * from {} where subscriber_id = $1", TOTP_TABLE_NAME);
+    let totp_row_opt: Option<TotpRow> = sqlx::query_as(&sql)
+      .bind(subscriber_id.as_str())
+      .fetch_optional(&self.pool)
+      .await?;
+    totp_row_opt.map(|row| row.try_into()).transpose()
+  }
+
+  async fn enroll<'a>(&self, factor: &'a TotpFactor) -> Result<()> {
+    // an enabled factor is never overwritten, even by concurrent requests
+    let sql = format!(
+      "insert into {0} (subscriber_id, secret, enabled, last_step) VALUES ($1, $2, false, null) \
+       on conflict (subscriber_id) do update set secret = excluded.secret, last_step = null where {0}.enabled = false",
+      TOTP_TABLE_NAME
+    );
+    let _res = sqlx::query(&sql)
+      .bind(factor.subscriber_id.as_str())
+      .bind(factor.secret.as_str())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn enable<'a>(
+    &self,
+    subscriber_id: &'a SubscriberId,
+    step: i64,
+    recovery_code_hashes: &'a [RecoveryCodeHash],
+  ) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+    let sql = format!(
+      "update {} set enabled = true, last_step = $1 where subscriber_id = $2 and enabled = false",
+      TOTP_TABLE_NAME
+    );
+    let res = sqlx::query(&sql)
+      .bind(step)
+      .bind(subscriber_id.as_str())
+      .execute(&mut *tx)
+      .await?;
+    if DB::rows_affected(&res) == 0 {
+      return Ok(false);
+    }
+    let sql = format!("delete from {} where subscriber_id = $1", RECOVERY_CODE_TABLE_NAME);
+    let _res = sqlx::query(&sql).bind(subscriber_id.as_str()).execute(&mut *tx).await?;
+    let sql = format!(
+      "insert into {} (subscriber_id, code_hash) VALUES ($1, $2)",
+      RECOVERY_CODE_TABLE_NAME
+    );
+    for code_hash in recovery_code_hashes {
+      let _res = sqlx::query(&sql)
+        .bind(subscriber_id.as_str())
+        .bind(code_hash.as_str())
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(true)
+  }
+
+  async fn use_step<'a>(&self, subscriber_id: &'a SubscriberId, step: i64) -> Result<bool> {
+    // a single statement compares and sets the step, so that only one of concurrent requests accepts the code
+    let sql = format!(
+      "update {} set last_step = $1 where subscriber_id = $2 and enabled = true and (last_step is null or last_step < $1)",
+      TOTP_TABLE_NAME
+    );
+    let res = sqlx::query(&sql)
+      .bind(step)
+      .bind(subscriber_id.as_str())
+      .execute(&self.pool)
+      .await?;
+    Ok(DB::rows_affected(&res) == 1)
+  }
+
+  async fn consume_recovery_code<'a>(
+    &self,
+    subscriber_id: &'a SubscriberId,
+    code_hash: &'a RecoveryCodeHash,
+  ) -> Result<bool> {
+    let sql = format!(
+      "delete from {} where subscriber_id = $1 and code_hash = $2",
+      RECOVERY_CODE_TABLE_NAME
+    );
+    let res = sqlx::query(&sql)
+      .bind(subscriber_id.as_str())
+      .bind(code_hash.as_str())
+      .execute(&self.pool)
+      .await?;
+    Ok(DB::rows_affected(&res) == 1)
+  }
+
+  async fn disable<'a>(&self, subscriber_id: &'a SubscriberId) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+    for table_name in [TOTP_TABLE_NAME, RECOVERY_CODE_TABLE_NAME] {
+      let sql = format!("delete from {} where subscriber_id = $1", table_name);
+      let _res = sqlx::query(&sql).bind(subscriber_id.as_str()).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+  }
+}
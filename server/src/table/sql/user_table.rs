@@ -0,0 +1,451 @@
+use super::{assignments, SqlDatabase};
+use crate::{
+  constants::*,
+  entity::*,
+  error::*,
+  table::{rows::UserRow, UserSearchKey, UserTable},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use sqlx::{
+  database::HasArguments, types::Json, ColumnIndex, Decode, Encode, Executor, FromRow, IntoArguments, Pool, Type,
+};
+use serde_json::{Map, Value};
+use std::convert::TryInto;
+
+use libcommon::token_fields::{Field, SubscriberId};
+
+#[derive(Debug, Clone)]
+pub struct SqlUserTable<DB: SqlDatabase> {
+  pool: Pool<DB>,
+}
+
+impl<DB: SqlDatabase> SqlUserTable<DB> {
+  pub fn new(pool: Pool<DB>) -> Self {
+    Self { pool }
+  }
+}
+
+impl<DB> SqlUserTable<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> bool: Encode<'q, DB> + Type<DB>,
+{
+  /// Set the password and whether the user must change it, deleting all of the user's refresh tokens in the same transaction
+  async fn set_password(
+    &self,
+    user_search_key: UserSearchKey<'_>,
+    password: &Password,
+    must_change_password: bool,
+  ) -> Result<()> {
+    let encoded_hash = EncodedHash::generate(password)?;
+    let mut tx = self.pool.begin().await?;
+    // a new password ends every session established with the old one
+    revoke_sessions::<DB>(&mut tx, &user_search_key).await?;
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!(
+      "update {} set encoded_hash = $1, must_change_password = $2 where {} = $3",
+      USER_TABLE_NAME, column
+    );
+    let _res = sqlx::query(&sql)
+      .bind(encoded_hash.as_str())
+      .bind(must_change_password)
+      .bind(value)
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl<DB> UserTable for SqlUserTable<DB>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+  for<'q> Option<String>: Encode<'q, DB> + Type<DB>,
+  for<'q> bool: Encode<'q, DB> + Type<DB>,
+  for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+  for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+  for<'q> i32: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+  for<'q> Json<Vec<String>>: Encode<'q, DB> + Type<DB>,
+  for<'q> Json<Map<String, Value>>: Encode<'q, DB> + Type<DB>,
+  usize: ColumnIndex<DB::Row>,
+  UserRow: for<'r> FromRow<'r, DB::Row>,
+{
+  async fn add(&self, user: User) -> Result<()> {
+    let sql = format!(
+      "insert into {} (username, subscriber_id, encoded_hash, is_admin, roles, email, display_name, attributes, enabled, locked_until, failed_logins, must_change_password) VALUES ($1, $2, $3, {}, $4, $5, $6, $7, $8, $9, $10, $11)",
+      USER_TABLE_NAME,
+      DB::is_admin_literal(user.is_admin())
+    );
+    let _res = sqlx::query(&sql)
+      .bind(user.username.as_str())
+      .bind(user.subscriber_id.as_str())
+      .bind(user.encoded_hash.as_str())
+      .bind(Json(user.roles.to_strings()))
+      .bind(user.profile.email.map(|e| e.into_string()))
+      .bind(user.profile.display_name.map(|n| n.into_string()))
+      .bind(Json(user.profile.attributes.into_inner()))
+      .bind(user.enabled)
+      .bind(user.locked_until.map(|t| t.timestamp()))
+      .bind(user.failed_logins as i32)
+      .bind(user.must_change_password)
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn delete_user<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+    DB::lock_admins(&mut tx).await?;
+    revoke_sessions::<DB>(&mut tx, &user_search_key).await?;
+    delete_second_factor::<DB>(&mut tx, &user_search_key).await?;
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!(
+      "delete from {0} where {1} = $1 and (is_admin = {2} or (select count(*) from {0} where is_admin = {3}) > 1)",
+      USER_TABLE_NAME,
+      column,
+      DB::is_admin_literal(false),
+      DB::is_admin_literal(true)
+    );
+    let res = sqlx::query(&sql).bind(value).execute(&mut *tx).await?;
+    if DB::rows_affected(&res) == 0 {
+      // sessions and second factor are kept by rolling back
+      return Ok(false);
+    }
+    tx.commit().await?;
+    Ok(true)
+  }
+
+  async fn list_users(&self, page: u32) -> Result<(Vec<User>, u32, u32)> {
+    if page == 0 {
+      bail!("Page number starts from 1");
+    }
+    let total_cnt = sqlx::query_scalar::<_, i64>(&format!("select count(*) from {}", USER_TABLE_NAME))
+      .fetch_one(&self.pool)
+      .await?;
+    let total_pages = (total_cnt as f64 / MAX_USERS_PER_PAGE as f64).ceil() as u32;
+
+    if page > total_pages {
+      bail!("Page number exceeds total pages");
+    }
+
+    let sql = format!("select * from {} order by username asc limit $1 offset $2", USER_TABLE_NAME);
+    let user_rows: Vec<UserRow> = sqlx::query_as(&sql)
+      .bind(MAX_USERS_PER_PAGE as i64)
+      .bind(((page - 1) * MAX_USERS_PER_PAGE) as i64)
+      .fetch_all(&self.pool)
+      .await?;
+
+    let users = user_rows
+      .into_iter()
+      .map(|row| row.try_into())
+      .collect::<Result<Vec<User>>>()?;
+
+    Ok((users, total_pages, total_cnt as u32))
+  }
+
+  async fn update_user<'a>(
+    &self,
+    subscriber_id: &SubscriberId,
+    new_username: Option<&Username>,
+    new_password: Option<&Password>,
+  ) -> Result<()> {
+    // hashed before the transaction, so as not to hold the write lock of the database while computing it
+    let encoded_hash = new_password.map(EncodedHash::generate).transpose()?;
+    let mut tx = self.pool.begin().await?;
+    // a new password ends every session established with the old one
+    if encoded_hash.is_some() {
+      revoke_sessions::<DB>(&mut tx, &UserSearchKey::SubscriberId(subscriber_id)).await?;
+    }
+    let query = match (new_username, encoded_hash) {
+      (Some(username), None) => {
+        let sql = format!("update {} set username = $1 where subscriber_id = $2", USER_TABLE_NAME);
+        sqlx::query(&sql)
+          .bind(username.as_str())
+          .bind(subscriber_id.as_str())
+          .execute(&mut *tx)
+          .await
+      }
+      (None, Some(encoded_hash)) => {
+        let sql = format!(
+          "update {} set encoded_hash = $1, must_change_password = false where subscriber_id = $2",
+          USER_TABLE_NAME
+        );
+        sqlx::query(&sql)
+          .bind(encoded_hash.as_str())
+          .bind(subscriber_id.as_str())
+          .execute(&mut *tx)
+          .await
+      }
+      (Some(username), Some(encoded_hash)) => {
+        let sql = format!(
+          "update {} set username = $1, encoded_hash = $2, must_change_password = false where subscriber_id = $3",
+          USER_TABLE_NAME
+        );
+        sqlx::query(&sql)
+          .bind(username.as_str())
+          .bind(encoded_hash.as_str())
+          .bind(subscriber_id.as_str())
+          .execute(&mut *tx)
+          .await
+      }
+      (None, None) => {
+        bail!("Both or either one of username and password must be specified");
+      }
+    };
+    let _res = query?;
+    tx.commit().await?;
+    Ok(())
+  }
+
+  async fn update_password<'a>(&self, user_search_key: UserSearchKey<'a>, new_password: &Password) -> Result<()> {
+    self.set_password(user_search_key, new_password, false).await
+  }
+
+  async fn reset_password<'a>(&self, user_search_key: UserSearchKey<'a>, temporary_password: &Password) -> Result<()> {
+    self.set_password(user_search_key, temporary_password, true).await
+  }
+
+  async fn replace_encoded_hash<'a>(
+    &self,
+    user_search_key: UserSearchKey<'a>,
+    current_hash: &EncodedHash,
+    new_hash: &EncodedHash,
+  ) -> Result<()> {
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!(
+      "update {} set encoded_hash = $1 where {} = $2 and encoded_hash = $3",
+      USER_TABLE_NAME, column
+    );
+    let _res = sqlx::query(&sql)
+      .bind(new_hash.as_str())
+      .bind(value)
+      .bind(current_hash.as_str())
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn update_profile<'a>(&self, user_search_key: UserSearchKey<'a>, update: &ProfileUpdate) -> Result<()> {
+    if update.is_empty() {
+      bail!("At least one profile field must be specified");
+    }
+    let mut columns = vec![];
+    if update.email.is_some() {
+      columns.push("email");
+    }
+    if update.display_name.is_some() {
+      columns.push("display_name");
+    }
+    if update.attributes.is_some() {
+      columns.push("attributes");
+    }
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!(
+      "update {} set {} where {} = ${}",
+      USER_TABLE_NAME,
+      assignments(&columns),
+      column,
+      columns.len() + 1
+    );
+    let mut query = sqlx::query(&sql);
+    if let Some(email) = &update.email {
+      query = query.bind(email.as_ref().map(|e| e.as_str().to_string()));
+    }
+    if let Some(display_name) = &update.display_name {
+      query = query.bind(display_name.as_ref().map(|n| n.as_str().to_string()));
+    }
+    if let Some(attributes) = &update.attributes {
+      query = query.bind(Json(attributes.get().clone()));
+    }
+    let _res = query.bind(value).execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn update_roles<'a>(&self, user_search_key: UserSearchKey<'a>, roles: &Roles) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+    DB::lock_admins(&mut tx).await?;
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    // is_admin follows the admin role, since the guards of the last admin count admins by it
+    let is_admin = roles.contains(Role::Admin);
+    let sql = format!(
+      "update {0} set roles = $1, is_admin = {2} where {1} = $2 and ($3 or is_admin = {3} or (select count(*) from {0} where is_admin = {4}) > 1)",
+      USER_TABLE_NAME,
+      column,
+      DB::is_admin_literal(is_admin),
+      DB::is_admin_literal(false),
+      DB::is_admin_literal(true)
+    );
+    let res = sqlx::query(&sql)
+      .bind(Json(roles.to_strings()))
+      .bind(value)
+      .bind(is_admin)
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(DB::rows_affected(&res) > 0)
+  }
+
+  async fn update_status<'a>(
+    &self,
+    user_search_key: UserSearchKey<'a>,
+    enabled: bool,
+    locked_until: Option<DateTime<Local>>,
+  ) -> Result<bool> {
+    let mut tx = self.pool.begin().await?;
+    DB::lock_admins(&mut tx).await?;
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let now = Local::now().timestamp();
+    let keeps_access = enabled && locked_until.is_none_or(|t| t.timestamp() <= now);
+    let sql = format!(
+      "update {0} set enabled = $1, locked_until = $2 where {1} = $3 and ($4 or is_admin = {2} or (select count(*) from {0} where is_admin = {3} and enabled and (locked_until is null or locked_until <= $5) and {1} <> $3) > 0)",
+      USER_TABLE_NAME,
+      column,
+      DB::is_admin_literal(false),
+      DB::is_admin_literal(true)
+    );
+    let res = sqlx::query(&sql)
+      .bind(enabled)
+      .bind(locked_until.map(|t| t.timestamp()))
+      .bind(value)
+      .bind(keeps_access)
+      .bind(now)
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(DB::rows_affected(&res) > 0)
+  }
+
+  async fn lock<'a>(&self, user_search_key: UserSearchKey<'a>, locked_until: DateTime<Local>) -> Result<()> {
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!("update {} set locked_until = $1 where {} = $2", USER_TABLE_NAME, column);
+    let _res = sqlx::query(&sql)
+      .bind(locked_until.timestamp())
+      .bind(value)
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn increment_failed_logins<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<u32> {
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!(
+      "update {} set failed_logins = failed_logins + 1 where {} = $1 returning failed_logins",
+      USER_TABLE_NAME, column
+    );
+    let cnt = sqlx::query_scalar::<_, i32>(&sql).bind(value).fetch_one(&self.pool).await?;
+    Ok(cnt.try_into()?)
+  }
+
+  async fn reset_failed_logins<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<()> {
+    let (column, value) = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => ("subscriber_id", sub_id.as_str()),
+      UserSearchKey::Username(username) => ("username", username.as_str()),
+    };
+    let sql = format!("update {} set failed_logins = 0 where {} = $1", USER_TABLE_NAME, column);
+    let _res = sqlx::query(&sql).bind(value).execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn find_user<'a>(&self, user_search_key: UserSearchKey<'a>) -> Result<Option<User>> {
+    let user_row_opt: Option<UserRow> = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => {
+        let sql = format!("select * from {} where subscriber_id = $1", USER_TABLE_NAME);
+        sqlx::query_as(&sql).bind(sub_id.as_str()).fetch_optional(&self.pool).await?
+      }
+      UserSearchKey::Username(username) => {
+        let sql = format!("select * from {} where username = $1", USER_TABLE_NAME);
+        sqlx::query_as(&sql).bind(username.as_str()).fetch_optional(&self.pool).await?
+      }
+    };
+    if let Some(user_row) = user_row_opt {
+      let user: User = user_row.try_into()?;
+      Ok(Some(user))
+    } else {
+      Ok(None)
+    }
+  }
+}
+
+/// Delete refresh tokens of the user in the transaction of the user update
+async fn revoke_sessions<DB>(conn: &mut DB::Connection, user_search_key: &UserSearchKey<'_>) -> Result<()>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+{
+  let query = match user_search_key {
+    UserSearchKey::SubscriberId(sub_id) => {
+      let sql = format!("delete from {} where subscriber_id = $1", REFRESH_TOKEN_TABLE_NAME);
+      sqlx::query(&sql).bind(sub_id.as_str()).execute(conn).await
+    }
+    UserSearchKey::Username(username) => {
+      let sql = format!(
+        "delete from {} where subscriber_id in (select subscriber_id from {} where username = $1)",
+        REFRESH_TOKEN_TABLE_NAME, USER_TABLE_NAME
+      );
+      sqlx::query(&sql).bind(username.as_str()).execute(conn).await
+    }
+  };
+  let _res = query?;
+  Ok(())
+}
+
+/// Delete the TOTP factor and recovery codes of the user in the transaction of the user deletion
+async fn delete_second_factor<DB>(conn: &mut DB::Connection, user_search_key: &UserSearchKey<'_>) -> Result<()>
+where
+  DB: SqlDatabase,
+  for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+  for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+  for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+{
+  for table_name in [TOTP_TABLE_NAME, RECOVERY_CODE_TABLE_NAME] {
+    let query = match user_search_key {
+      UserSearchKey::SubscriberId(sub_id) => {
+        let sql = format!("delete from {} where subscriber_id = $1", table_name);
+        sqlx::query(&sql).bind(sub_id.as_str()).execute(&mut *conn).await
+      }
+      UserSearchKey::Username(username) => {
+        let sql = format!(
+          "delete from {} where subscriber_id in (select subscriber_id from {} where username = $1)",
+          table_name, USER_TABLE_NAME
+        );
+        sqlx::query(&sql).bind(username.as_str()).execute(&mut *conn).await
+      }
+    };
+    let _res = query?;
+  }
+  Ok(())
+}
@@ -0,0 +1,42 @@
+use super::sql::{sql_tables, SqlDatabase, SqlTables};
+use crate::error::*;
+use async_trait::async_trait;
+use sqlx::{
+  sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePoolOptions, SqliteQueryResult},
+  Sqlite,
+};
+use std::str::FromStr;
+
+#[async_trait]
+impl SqlDatabase for Sqlite {
+  fn is_admin_literal(is_admin: bool) -> &'static str {
+    if is_admin {
+      "'true'"
+    } else {
+      "'false'"
+    }
+  }
+
+  fn rows_affected(res: &SqliteQueryResult) -> u64 {
+    res.rows_affected()
+  }
+
+  /// Nothing to lock, since sqlite never interleaves writes. The guards of the last admin count admins in the same
+  /// statement as the update or deletion, which takes the write lock of the database before reading any row, so that
+  /// no other write comes between the count and the write even in a deferred transaction. A transaction whose snapshot
+  /// is outdated by then fails with busy instead of writing.
+  async fn lock_admins(_conn: &mut SqliteConnection) -> Result<()> {
+    Ok(())
+  }
+}
+
+/// Setup sqlite database with automatic creation of user, refresh token, audit event, TOTP and client tables
+pub(super) async fn setup_sqlite(sqlite_url: &str) -> Result<SqlTables<Sqlite>> {
+  let conn_opts = SqliteConnectOptions::from_str(sqlite_url)?.create_if_missing(true);
+  let pool = SqlitePoolOptions::default().connect_with(conn_opts).await?;
+
+  // Embed migrations into binary
+  sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+  Ok(sql_tables(pool))
+}